@@ -1 +1 @@
-pub mod x86;
\ No newline at end of file
+pub mod x86;
@@ -0,0 +1,228 @@
+use core::ptr::read_unaligned;
+use x86_64::VirtAddr;
+
+//the MADT is the only table we need so far, everything else is skipped
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+
+pub const MAX_IO_APICS: usize = 4;
+pub const MAX_SOURCE_OVERRIDES: usize = 16;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct Rsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    //the fields below only exist for revision >= 2
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+struct SdtHeader {
+    signature: [u8; 4],
+    length: u32,
+    revision: u8,
+    checksum: u8,
+    oem_id: [u8; 6],
+    oem_table_id: [u8; 8],
+    oem_revision: u32,
+    creator_id: u32,
+    creator_revision: u32,
+}
+
+pub struct MadtEntryType;
+
+impl MadtEntryType {
+    pub const LOCAL_APIC: u8 = 0;
+    pub const IO_APIC: u8 = 1;
+    pub const INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+    pub const LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: u64,
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ that is not wired 1:1 to the global system interrupt with the same number.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptSourceOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+impl InterruptSourceOverride {
+    fn from_flags(irq: u8, gsi: u32, flags: u16) -> InterruptSourceOverride {
+        //0b00 means "conforms to the bus", which is active high and edge triggered for ISA
+        let polarity = match flags & 0b11 {
+            0b11 => Polarity::ActiveLow,
+            _ => Polarity::ActiveHigh,
+        };
+
+        let trigger_mode = match (flags >> 2) & 0b11 {
+            0b11 => TriggerMode::Level,
+            _ => TriggerMode::Edge,
+        };
+
+        InterruptSourceOverride { irq, gsi, polarity, trigger_mode }
+    }
+}
+
+pub struct MadtInfo {
+    pub local_apic_address: u64,
+    pub processor_count: usize,
+    pub io_apics: [Option<IoApicInfo>; MAX_IO_APICS],
+    pub source_overrides: [Option<InterruptSourceOverride>; MAX_SOURCE_OVERRIDES],
+}
+
+impl MadtInfo {
+    pub fn source_override(&self, irq: u8) -> Option<InterruptSourceOverride> {
+        self.source_overrides.iter().flatten().find(|o| o.irq == irq).copied()
+    }
+}
+
+fn checksum_is_valid(ptr: *const u8, length: usize) -> bool {
+    let sum = (0..length).fold(0u8, |sum, i| sum.wrapping_add(unsafe { *ptr.add(i) }));
+    sum == 0
+}
+
+fn find_rsdp(physical_memory_offset: VirtAddr) -> Option<Rsdp> {
+    //the RSDP is 16 byte aligned, either in the first KiB of the EBDA or in the BIOS area below 1 MiB
+    let ebda = unsafe { read_unaligned((physical_memory_offset + 0x40Eu64).as_ptr::<u16>()) } as u64 * 16;
+    let areas = [(ebda, ebda + 1024), (0xE0000, 0x100000)];
+
+    for (start, end) in areas {
+        for address in (start..end).step_by(16) {
+            let ptr = (physical_memory_offset + address).as_ptr::<u8>();
+            let signature = unsafe { read_unaligned(ptr as *const [u8; 8]) };
+
+            //the v1 checksum only covers the first 20 bytes
+            if &signature == RSDP_SIGNATURE && checksum_is_valid(ptr, 20) {
+                return Some(unsafe { read_unaligned(ptr as *const Rsdp) });
+            }
+        }
+    }
+
+    None
+}
+
+fn find_table(physical_memory_offset: VirtAddr, rsdp: &Rsdp, signature: &[u8; 4]) -> Option<*const SdtHeader> {
+    //the XSDT holds 64 bit pointers, the RSDT 32 bit ones
+    let (root_address, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        (rsdp.xsdt_address, 8)
+    } else {
+        (rsdp.rsdt_address as u64, 4)
+    };
+
+    let root = (physical_memory_offset + root_address).as_ptr::<SdtHeader>();
+    let root_header = unsafe { read_unaligned(root) };
+    let entries = (root_header.length as usize - core::mem::size_of::<SdtHeader>()) / entry_size;
+    let first_entry = unsafe { (root as *const u8).add(core::mem::size_of::<SdtHeader>()) };
+
+    for i in 0..entries {
+        let table_address = unsafe {
+            match entry_size {
+                8 => read_unaligned(first_entry.add(i * 8) as *const u64),
+                _ => read_unaligned(first_entry.add(i * 4) as *const u32) as u64,
+            }
+        };
+
+        let table = (physical_memory_offset + table_address).as_ptr::<SdtHeader>();
+        let header = unsafe { read_unaligned(table) };
+
+        if &header.signature == signature && checksum_is_valid(table as *const u8, header.length as usize) {
+            return Some(table);
+        }
+    }
+
+    None
+}
+
+/// Locates and parses the MADT through the bootloader's mapping of physical memory.
+pub fn parse_madt(physical_memory_offset: VirtAddr) -> Option<MadtInfo> {
+    let rsdp = find_rsdp(physical_memory_offset)?;
+    let madt = find_table(physical_memory_offset, &rsdp, MADT_SIGNATURE)?;
+    let header = unsafe { read_unaligned(madt) };
+    let base = madt as *const u8;
+
+    let mut info = MadtInfo {
+        local_apic_address: unsafe { read_unaligned(base.add(36) as *const u32) } as u64,
+        processor_count: 0,
+        io_apics: [None; MAX_IO_APICS],
+        source_overrides: [None; MAX_SOURCE_OVERRIDES],
+    };
+
+    //the variable length entries start after the header, the local APIC address and the flags
+    let mut offset = 44;
+    let mut io_apic_count = 0;
+    let mut override_count = 0;
+
+    while offset + 2 <= header.length as usize {
+        let entry = unsafe { base.add(offset) };
+        let (entry_type, length) = unsafe { (*entry, *entry.add(1) as usize) };
+
+        if length < 2 {
+            break;
+        }
+
+        unsafe {
+            match entry_type {
+                MadtEntryType::LOCAL_APIC => {
+                    //bit 0 of the flags tells whether the processor is enabled
+                    if read_unaligned(entry.add(4) as *const u32) & 1 != 0 {
+                        info.processor_count += 1;
+                    }
+                }
+                MadtEntryType::IO_APIC if io_apic_count < MAX_IO_APICS => {
+                    info.io_apics[io_apic_count] = Some(IoApicInfo {
+                        id: *entry.add(2),
+                        address: read_unaligned(entry.add(4) as *const u32) as u64,
+                        gsi_base: read_unaligned(entry.add(8) as *const u32),
+                    });
+                    io_apic_count += 1;
+                }
+                MadtEntryType::INTERRUPT_SOURCE_OVERRIDE if override_count < MAX_SOURCE_OVERRIDES => {
+                    info.source_overrides[override_count] = Some(InterruptSourceOverride::from_flags(
+                        *entry.add(3),
+                        read_unaligned(entry.add(4) as *const u32),
+                        read_unaligned(entry.add(8) as *const u16),
+                    ));
+                    override_count += 1;
+                }
+                MadtEntryType::LOCAL_APIC_ADDRESS_OVERRIDE => {
+                    info.local_apic_address = read_unaligned(entry.add(4) as *const u64);
+                }
+                _ => {}
+            }
+        }
+
+        offset += length;
+    }
+
+    Some(info)
+}
@@ -0,0 +1,126 @@
+use core::arch::asm;
+use core::fmt;
+use x86_64::VirtAddr;
+use crate::kernel::memory::paging;
+use crate::kernel::symbols;
+use crate::println;
+
+//stops walking corrupted or circular chains
+const MAX_DEPTH: usize = 64;
+
+/// A code address on the stack. Return addresses point behind the call, which may already be
+/// the next function if the call was the last instruction, so they are looked up one byte earlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub address: u64,
+    pub is_return_address: bool,
+}
+
+impl Frame {
+    pub fn symbol(&self) -> Option<(&'static symbols::Symbol, u64)> {
+        let (symbol, offset) = symbols::lookup(self.address - self.is_return_address as u64)?;
+        Some((symbol, offset + self.is_return_address as u64))
+    }
+}
+
+/// `address function+offset`, or just the address if no symbol contains it.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.symbol() {
+            Some((symbol, offset)) => write!(f, "{:#018x} {}+{:#x}", self.address, symbol.demangled(), offset),
+            None => write!(f, "{:#018x} <unknown>", self.address),
+        }
+    }
+}
+
+/// Follows the chain of saved frame pointers: every function pushes the caller's `rbp` and points
+/// `rbp` at it, so `[rbp]` is the caller's frame pointer and `[rbp + 8]` the return address.
+/// Needs the kernel to be built with frame pointers (see `thunder_x86_64.json`). Threads start
+/// with `rbp` cleared, which ends the chain.
+pub struct StackWalker {
+    first: Option<Frame>,
+    frame_pointer: u64,
+    depth: usize,
+}
+
+impl StackWalker {
+    /// Walks the stack of interrupted code, e.g. from the registers saved in a `StackFrame`.
+    pub fn new(instruction_pointer: u64, frame_pointer: u64) -> StackWalker {
+        StackWalker {
+            first: Some(Frame { address: instruction_pointer, is_return_address: false }),
+            frame_pointer,
+            depth: 0,
+        }
+    }
+
+    /// Walks the stack of the caller, starting with the address `current` returns to.
+    #[inline(never)]
+    pub fn current() -> StackWalker {
+        let frame_pointer: u64;
+        unsafe { asm!("mov {}, rbp", out(reg) frame_pointer, options(nomem, nostack, preserves_flags)) };
+
+        //this frame is gone once we returned, so it has to be read now
+        let mut walker = StackWalker { first: None, frame_pointer, depth: 0 };
+        walker.first = walker.next();
+        walker
+    }
+}
+
+impl Iterator for StackWalker {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        if let Some(frame) = self.first.take() {
+            return Some(frame);
+        }
+
+        if self.depth >= MAX_DEPTH || !is_readable_frame(self.frame_pointer) {
+            return None;
+        }
+
+        let (caller_frame_pointer, return_address) = unsafe {
+            let frame = self.frame_pointer as *const u64;
+            (frame.read(), frame.add(1).read())
+        };
+
+        if return_address == 0 {
+            return None;
+        }
+
+        //the stack grows down, so callers' frames lie above. Anything else ends the walk
+        self.frame_pointer = if caller_frame_pointer > self.frame_pointer { caller_frame_pointer } else { 0 };
+        self.depth += 1;
+
+        Some(Frame { address: return_address, is_return_address: true })
+    }
+}
+
+//the walk may start from a corrupted rbp, so it must not fault itself
+fn is_readable_frame(frame_pointer: u64) -> bool {
+    let is_mapped = |address: u64| VirtAddr::try_new(address).map_or(false, |address| paging::translate(address).is_some());
+
+    frame_pointer != 0 && frame_pointer % 8 == 0 && is_mapped(frame_pointer) && frame_pointer.checked_add(8).map_or(false, is_mapped)
+}
+
+/// Prints one line per frame: `#index address function+offset`.
+pub fn print(walker: StackWalker) {
+    println!("Backtrace:");
+
+    for (index, frame) in walker.enumerate() {
+        println!("  #{:<2} {}", index, frame);
+    }
+}
+
+#[test_case]
+fn current_stack_is_walked() {
+    use alloc::vec::Vec;
+
+    let frames: Vec<Frame> = StackWalker::current().collect();
+
+    //this function, the test runner and further up to the kernel's entry point
+    assert!(frames.len() >= 3);
+    assert!(frames.iter().all(|frame| frame.is_return_address));
+
+    let (symbol, _) = frames[0].symbol().expect("No symbol for the calling function");
+    assert!(symbol.name.contains("current_stack_is_walked"));
+}
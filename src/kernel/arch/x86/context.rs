@@ -0,0 +1,131 @@
+use core::mem::size_of;
+use crate::kernel::arch::x86::gdt;
+use crate::kernel::arch::x86::registers::{IretRegisters, PreservedRegisters, ScratchRegisters, StackFrame};
+use crate::{restore_preserved_registers, restore_scratch_registers, save_preserved_registers};
+
+pub struct RflagsMasks;
+
+impl RflagsMasks {
+    //bit 1 is reserved and always set
+    pub const RESERVED: usize = 0x2;
+    //the arithmetic flags: CF, PF, AF, ZF, SF and OF
+    pub const STATUS: usize = 0x8d5;
+    pub const TRAP: usize = 0x100;
+    pub const INTERRUPT_ENABLE: usize = 0x200;
+    pub const DIRECTION: usize = 0x400;
+    pub const ALIGNMENT_CHECK: usize = 0x40000;
+}
+
+/// What a suspended thread's saved stack pointer points at: the preserved registers pushed
+/// by `switch_context`, followed by the address it returns to.
+#[derive(Default)]
+#[repr(packed)]
+pub struct SwitchFrame {
+    pub preserved: PreservedRegisters,
+    pub return_address: usize,
+}
+
+/// The stack of a thread that never ran. Switching to it returns into `enter_thread`,
+/// which unwinds `frame` like the end of an interrupt handler does.
+#[derive(Default)]
+#[repr(packed)]
+struct InitialStack {
+    switch: SwitchFrame,
+    frame: StackFrame,
+}
+
+#[naked]
+extern "C" fn switch_context(_old_stack_pointer: *mut usize, _new_stack_pointer: usize) {
+    unsafe {
+        core::arch::asm! {
+            save_preserved_registers!(), //save preserved (callee-saved/non volatile) registers, the caller saved the others
+
+            "mov [rdi], rsp", //store the old stack pointer in the location the first argument points to
+            "mov rsp, rsi", //the second argument is the stack pointer of the context we switch to
+
+            restore_preserved_registers!(), //restore preserved (callee-saved/non volatile) registers of the new context
+            "ret", //return to wherever the new context was suspended
+            options(noreturn)
+        }
+    }
+}
+
+#[naked]
+extern "C" fn enter_thread() -> ! {
+    unsafe {
+        core::arch::asm! {
+            restore_preserved_registers!(), //restore preserved (callee-saved/non volatile) registers
+            restore_scratch_registers!(), //restore scratch (caller-saved/volatile) registers, rdi holds the argument
+
+            "iretq", //jump to the entry point with the flags and stack of the iret frame
+            options(noreturn)
+        }
+    }
+}
+
+/// Suspends the current context, saving its stack pointer to `old_stack_pointer`, and resumes
+/// the one suspended at `new_stack_pointer`. Returns once something switches back.
+///
+/// Interrupts have to be disabled, the new context restores its own interrupt flag.
+pub unsafe fn switch(old_stack_pointer: *mut usize, new_stack_pointer: usize) {
+    switch_context(old_stack_pointer, new_stack_pointer);
+}
+
+/// Sets up `stack` so that switching to the returned stack pointer calls `entry(argument)`
+/// with interrupts enabled.
+pub fn prepare_stack(stack: &mut [u8], entry: extern "C" fn(usize) -> !, argument: usize) -> usize {
+    let top = (stack.as_mut_ptr() as usize + stack.len()) & !0xF;
+    let frame_start = top - 8 - size_of::<InitialStack>();
+    let selectors = gdt::selectors();
+
+    let initial = InitialStack {
+        switch: SwitchFrame {
+            preserved: PreservedRegisters::default(),
+            return_address: enter_thread as usize,
+        },
+        frame: StackFrame {
+            preserved: PreservedRegisters::default(),
+            scratch: ScratchRegisters { rdi: argument, ..ScratchRegisters::default() },
+            iret: IretRegisters {
+                rip: entry as usize,
+                cs: selectors.kernel_code.0 as usize,
+                rflags: RflagsMasks::RESERVED | RflagsMasks::INTERRUPT_ENABLE,
+                //the entry point sees the stack like after a call, 8 bytes below a 16 byte boundary
+                rsp: top - 8,
+                ss: selectors.kernel_data.0 as usize,
+            },
+        },
+    };
+
+    unsafe { core::ptr::write_unaligned(frame_start as *mut InitialStack, initial) };
+    frame_start
+}
+
+/// Drops to ring 3, continuing at `instruction_pointer` with `stack_pointer` and interrupts enabled.
+/// All other registers are cleared, so nothing of the kernel leaks to user space.
+///
+/// The page tables of the user program have to be active already.
+pub unsafe fn enter_user_mode(instruction_pointer: u64, stack_pointer: u64) -> ! {
+    let selectors = gdt::selectors();
+
+    let frame = StackFrame {
+        preserved: PreservedRegisters::default(),
+        scratch: ScratchRegisters::default(),
+        iret: IretRegisters {
+            rip: instruction_pointer as usize,
+            cs: selectors.user_code.0 as usize,
+            rflags: RflagsMasks::RESERVED | RflagsMasks::INTERRUPT_ENABLE,
+            rsp: stack_pointer as usize,
+            ss: selectors.user_data.0 as usize,
+        },
+    };
+
+    //unwind the frame like a thread that never ran, nothing below it on this stack is needed anymore
+    core::arch::asm!(
+        "mov rsp, {}",
+        "jmp {}",
+        in(reg) &frame,
+        sym enter_thread,
+        options(noreturn)
+    );
+}
@@ -0,0 +1,247 @@
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::mem::size_of;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Once;
+use x86_64::VirtAddr;
+use x86_64::instructions::{hlt, interrupts};
+use x86_64::instructions::segmentation::{DS, ES, FS, GS};
+use x86_64::registers::model_specific::Efer;
+use x86_64::registers::segmentation::Segment;
+use crate::kernel::arch::x86::backtrace::StackWalker;
+use crate::kernel::arch::x86::interrupts::exception::ExceptionContext;
+use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::arch::x86::{serial, vga};
+use crate::kernel::lib::print::Writer;
+use crate::kernel::memory::paging;
+use crate::{restore_preserved_registers, restore_scratch_registers, save_preserved_registers, save_scratch_registers};
+
+//keeps the whole report on one screen of VGA text mode
+const MAX_FRAMES: usize = 8;
+const CODE_BYTES: usize = 16;
+
+//a fault or panic while reporting would report again forever
+static CRASHING: AtomicBool = AtomicBool::new(false);
+//runs instead of halting, see `set_halt_handler`
+static HALT_HANDLER: Once<fn() -> !> = Once::new();
+
+/// The control, debug and model specific registers that a crash report shows besides the
+/// general purpose ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemRegisters {
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+    pub efer: u64,
+    pub dr6: u64,
+    pub dr7: u64,
+}
+
+impl SystemRegisters {
+    pub fn read() -> SystemRegisters {
+        let (cr0, cr2, cr3, cr4, dr6, dr7): (u64, u64, u64, u64, u64, u64);
+
+        unsafe {
+            asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr2", out(reg) cr2, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, dr6", out(reg) dr6, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, dr7", out(reg) dr7, options(nomem, nostack, preserves_flags));
+        }
+
+        SystemRegisters { cr0, cr2, cr3, cr4, efer: Efer::read_raw(), dr6, dr7 }
+    }
+}
+
+/// Stores the registers as they are at the call into `frame`, with the return address as `rip`
+/// and the stack pointer of the caller. The frame is pushed like the interrupt entries push theirs,
+/// so it has the layout of `StackFrame`, and then copied.
+#[naked]
+extern "C" fn capture_registers(_frame: *mut StackFrame) {
+    unsafe {
+        core::arch::asm! {
+            "push 0", //segment registers are stored as 16 bits
+            "mov [rsp], ss",
+            "push rsp",
+            "pushfq",
+            "add qword ptr [rsp + 8], 16", //the caller's stack pointer, before ss and the return address
+            "push 0",
+            "mov [rsp], cs",
+            "push qword ptr [rsp + 32]", //the return address, where the caller goes on
+
+            save_scratch_registers!(),
+            save_preserved_registers!(),
+
+            "mov rsi, rsp", //rdi still points at `frame`
+            "mov rcx, {words}",
+            "rep movsq",
+
+            restore_preserved_registers!(),
+            restore_scratch_registers!(),
+            "add rsp, 5 * 8", //drop the iret part
+            "ret",
+            words = const size_of::<StackFrame>() / 8,
+            options(noreturn)
+        }
+    }
+}
+
+/// Halts the processor for good. Only the bootstrap processor runs the kernel, so this stops
+/// the whole machine. `hlt` is repeated because an NMI still wakes it up.
+pub fn halt() -> ! {
+    if let Some(handler) = HALT_HANDLER.get() {
+        handler();
+    }
+
+    loop {
+        interrupts::disable();
+        hlt();
+    }
+}
+
+/// Makes `halt` call `handler` instead, e.g. for test kernels that crash on purpose and then
+/// have to exit QEMU. It can only be set once.
+pub fn set_halt_handler(handler: fn() -> !) {
+    HALT_HANDLER.call_once(|| handler);
+}
+
+/// Prints the crash report for an exception the kernel can't recover from and halts.
+pub fn exception(context: &ExceptionContext) -> ! {
+    interrupts::disable();
+
+    if !CRASHING.swap(true, Ordering::SeqCst) {
+        print_report(format_args!("{}", context), context.registers);
+    }
+
+    halt();
+}
+
+/// Prints the crash report for a kernel panic, with the registers at the call, and halts.
+pub fn panic(info: &PanicInfo) -> ! {
+    interrupts::disable();
+
+    if !CRASHING.swap(true, Ordering::SeqCst) {
+        let mut registers = StackFrame::default();
+        capture_registers(&mut registers);
+        render(format_args!("PANIC: {}", info), &registers, StackWalker::current());
+    }
+
+    halt();
+}
+
+/// Prints the crash report for `stack_frame` without halting, e.g. for tests that cause exceptions.
+pub fn print_report(title: fmt::Arguments, stack_frame: &StackFrame) {
+    render(title, stack_frame, StackWalker::new(stack_frame.iret.rip as u64, stack_frame.preserved.rbp as u64));
+}
+
+/// Writes the same report for an exception the kernel continues from, like a breakpoint, to the
+/// serial port only. The screen keeps showing what it did.
+pub fn log_report(title: fmt::Arguments, stack_frame: &StackFrame) {
+    let walker = StackWalker::new(stack_frame.iret.rip as u64, stack_frame.preserved.rbp as u64);
+
+    //the serial port can't fail
+    let _ = write_report(&mut SerialWriter, "EXCEPTION", title, stack_frame, walker);
+}
+
+struct SerialWriter;
+
+impl Write for SerialWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        serial::_print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+/// Writes to a blue VGA screen and to the serial port at once. The screen gets its own writer, the
+/// shared one may be locked by whatever crashed and won't be used again anyway.
+struct ReportWriter {
+    screen: Writer,
+}
+
+impl Write for ReportWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.screen.write_str(s);
+        serial::_print(format_args!("{}", s));
+        Ok(())
+    }
+}
+
+fn render(title: fmt::Arguments, registers: &StackFrame, walker: StackWalker) {
+    let mut screen = Writer::new();
+    screen.set_color(vga::Color::White, vga::Color::Blue);
+    screen.clear_screen();
+
+    //neither output can fail
+    let _ = write_report(&mut ReportWriter { screen }, "KERNEL CRASH", title, registers, walker);
+}
+
+fn write_report(out: &mut impl Write, heading: &str, title: fmt::Arguments, registers: &StackFrame, walker: StackWalker) -> fmt::Result {
+    let (preserved, scratch, iret) = (&registers.preserved, &registers.scratch, &registers.iret);
+    let system = SystemRegisters::read();
+
+    writeln!(out, "\n{}: {}\n", heading, title)?;
+
+    writeln!(out, "RAX={:016x} RBX={:016x} RCX={:016x}", { scratch.rax }, { preserved.rbx }, { scratch.rcx })?;
+    writeln!(out, "RDX={:016x} RSI={:016x} RDI={:016x}", { scratch.rdx }, { scratch.rsi }, { scratch.rdi })?;
+    writeln!(out, "RBP={:016x} RSP={:016x} R8 ={:016x}", { preserved.rbp }, { iret.rsp }, { scratch.r8 })?;
+    writeln!(out, "R9 ={:016x} R10={:016x} R11={:016x}", { scratch.r9 }, { scratch.r10 }, { scratch.r11 })?;
+    writeln!(out, "R12={:016x} R13={:016x} R14={:016x}", { preserved.r12 }, { preserved.r13 }, { preserved.r14 })?;
+    writeln!(out, "R15={:016x} RIP={:016x}", { preserved.r15 }, { iret.rip })?;
+
+    writeln!(out, "RFLAGS={}", iret.rflags())?;
+    writeln!(out, "CS={} SS={}", iret.code_segment(), iret.stack_segment())?;
+    writeln!(out, "DS={:#x} ES={:#x} FS={:#x} GS={:#x}", DS::get_reg().0, ES::get_reg().0, FS::get_reg().0, GS::get_reg().0)?;
+    writeln!(out, "CR0={:016x} CR2={:016x} CR3={:016x}", system.cr0, system.cr2, system.cr3)?;
+    writeln!(out, "CR4={:016x} EFER={:016x}", system.cr4, system.efer)?;
+    writeln!(out, "DR6={:016x} DR7={:016x}", system.dr6, system.dr7)?;
+
+    write!(out, "Code:")?;
+    write_code(out, iret.rip as u64)?;
+    writeln!(out)?;
+
+    writeln!(out, "Backtrace:")?;
+    for (index, frame) in walker.take(MAX_FRAMES).enumerate() {
+        writeln!(out, "  #{:<2} {}", index, frame)?;
+    }
+
+    Ok(())
+}
+
+//rip may be anywhere after a bad jump, so the bytes are only read if they are mapped
+fn write_code(out: &mut impl Write, rip: u64) -> fmt::Result {
+    let is_mapped = |address: u64| VirtAddr::try_new(address).map_or(false, |address| paging::translate(address).is_some());
+
+    if !is_mapped(rip) || !rip.checked_add(CODE_BYTES as u64 - 1).map_or(false, is_mapped) {
+        return write!(out, " <not mapped>");
+    }
+
+    let code = unsafe { core::slice::from_raw_parts(rip as *const u8, CODE_BYTES) };
+    for byte in code {
+        write!(out, " {:02x}", byte)?;
+    }
+
+    Ok(())
+}
+
+#[test_case]
+fn report_shows_the_captured_state() {
+    use alloc::string::{String, ToString};
+    use crate::kernel::arch::x86::gdt;
+
+    let mut registers = StackFrame::default();
+    capture_registers(&mut registers);
+
+    assert_eq!({ registers.iret.cs }, gdt::selectors().kernel_code.0 as usize);
+
+    let mut report = String::new();
+    write_report(&mut report, "KERNEL CRASH", format_args!("test"), &registers, StackWalker::current()).unwrap();
+
+    assert!(report.contains("KERNEL CRASH: test"));
+    assert!(report.contains(&registers.iret.rflags().to_string()));
+    assert!(report.contains("RPL 0, kernel"));
+    assert!(!report.contains("<not mapped>"));
+    assert!(report.contains("report_shows_the_captured_state"));
+}
@@ -0,0 +1,278 @@
+use core::arch::asm;
+use core::ptr::{addr_of, addr_of_mut};
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use x86_64::structures::gdt::SegmentSelector;
+use x86_64::instructions::segmentation::{CS, DS, ES, SS};
+use x86_64::registers::segmentation::Segment;
+
+//IST indices are 1-based, 0 in an IDT entry means "don't switch stacks"
+pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
+pub const NON_MASKABLE_INTERRUPT_IST_INDEX: u8 = 2;
+pub const MACHINE_CHECK_IST_INDEX: u8 = 3;
+
+//`sysret` expects the user data segment right after the kernel data segment and the user code segment after that
+pub const KERNEL_CODE_INDEX: u16 = 1;
+pub const KERNEL_DATA_INDEX: u16 = 2;
+pub const USER_DATA_INDEX: u16 = 3;
+pub const USER_CODE_INDEX: u16 = 4;
+
+const IST_STACK_SIZE: usize = 4096 * 5;
+const KERNEL_STACK_SIZE: usize = 4096 * 4;
+const GDT_ENTRIES: usize = 8;
+
+static mut DOUBLE_FAULT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut NON_MASKABLE_INTERRUPT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut MACHINE_CHECK_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+//entered from ring 3 while a thread without a stack of its own runs, e.g. the boot thread
+static mut BOOT_KERNEL_STACK: [u8; KERNEL_STACK_SIZE] = [0; KERNEL_STACK_SIZE];
+
+/// Copy of RSP0 in the TSS, the `syscall` entry has to switch to the kernel stack by hand.
+pub static KERNEL_STACK: AtomicU64 = AtomicU64::new(0);
+
+//the CPU reads the TSS straight from memory, so it lives at a fixed address for the kernel's lifetime
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed(4))]
+pub struct TaskStateSegment {
+    reserved_1: u32,
+    pub privilege_stack_table: [u64; 3],
+    reserved_2: u64,
+    pub interrupt_stack_table: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    pub const fn new() -> TaskStateSegment {
+        TaskStateSegment {
+            reserved_1: 0,
+            privilege_stack_table: [0; 3],
+            reserved_2: 0,
+            interrupt_stack_table: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            //no I/O permission bitmap
+            iomap_base: core::mem::size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+pub struct DescriptorFlags;
+
+impl DescriptorFlags {
+    pub const ACCESSED: u64 = 1 << 40;
+    pub const WRITABLE: u64 = 1 << 41;
+    pub const EXECUTABLE: u64 = 1 << 43;
+    pub const USER_SEGMENT: u64 = 1 << 44;
+    pub const DPL_RING_3: u64 = 3 << 45;
+    pub const PRESENT: u64 = 1 << 47;
+    pub const LONG_MODE: u64 = 1 << 53;
+    pub const DEFAULT_SIZE: u64 = 1 << 54;
+    pub const GRANULARITY: u64 = 1 << 55;
+    pub const LIMIT_0_15: u64 = 0xFFFF;
+    pub const LIMIT_16_19: u64 = 0xF << 48;
+
+    const COMMON: u64 = Self::USER_SEGMENT | Self::PRESENT | Self::WRITABLE | Self::ACCESSED
+        | Self::LIMIT_0_15 | Self::LIMIT_16_19 | Self::GRANULARITY;
+
+    pub const KERNEL_CODE: u64 = Self::COMMON | Self::EXECUTABLE | Self::LONG_MODE;
+    pub const KERNEL_DATA: u64 = Self::COMMON | Self::DEFAULT_SIZE;
+    pub const USER_CODE: u64 = Self::KERNEL_CODE | Self::DPL_RING_3;
+    pub const USER_DATA: u64 = Self::KERNEL_DATA | Self::DPL_RING_3;
+}
+
+pub enum Descriptor {
+    UserSegment(u64),
+    SystemSegment(u64, u64),
+}
+
+impl Descriptor {
+    pub const fn kernel_code_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::KERNEL_CODE)
+    }
+
+    pub const fn kernel_data_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::KERNEL_DATA)
+    }
+
+    pub const fn user_code_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::USER_CODE)
+    }
+
+    pub const fn user_data_segment() -> Descriptor {
+        Descriptor::UserSegment(DescriptorFlags::USER_DATA)
+    }
+
+    pub fn tss_segment(tss: &'static TaskStateSegment) -> Descriptor {
+        let base = tss as *const _ as u64;
+        let limit = (core::mem::size_of::<TaskStateSegment>() - 1) as u64;
+
+        //0x9 is the type of an available 64-bit TSS
+        let low = DescriptorFlags::PRESENT
+            | (0x9 << 40)
+            | (limit & 0xFFFF)
+            | ((base & 0xFF_FFFF) << 16)
+            | (((base >> 24) & 0xFF) << 56);
+        let high = base >> 32;
+
+        Descriptor::SystemSegment(low, high)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed(2))]
+pub struct GdtPointer {
+    pub limit: u16,
+    pub base_addr: u64,
+}
+
+#[inline]
+pub fn load_gdt(gdt_register: &GdtPointer) {
+    unsafe {
+        asm! {
+            "lgdt [{}]",
+            in(reg) gdt_register,
+            options(readonly, nostack, preserves_flags)
+        };
+    }
+}
+
+#[inline]
+pub unsafe fn load_tss(selector: SegmentSelector) {
+    asm! {
+        "ltr {0:x}",
+        in(reg) selector.0,
+        options(nostack, preserves_flags)
+    };
+}
+
+pub struct GlobalDescriptorTable {
+    table: [u64; GDT_ENTRIES],
+    len: usize,
+}
+
+impl GlobalDescriptorTable {
+    //entry 0 is the mandatory null descriptor
+    pub const fn new() -> GlobalDescriptorTable {
+        GlobalDescriptorTable {
+            table: [0; GDT_ENTRIES],
+            len: 1,
+        }
+    }
+
+    pub fn add_entry(&mut self, entry: Descriptor) -> SegmentSelector {
+        let index = match entry {
+            Descriptor::UserSegment(value) => self.push(value),
+            Descriptor::SystemSegment(low, high) => {
+                let index = self.push(low);
+                self.push(high);
+                index
+            }
+        };
+
+        let privilege_level = match entry {
+            Descriptor::UserSegment(value) if value & DescriptorFlags::DPL_RING_3 != 0 => x86_64::PrivilegeLevel::Ring3,
+            _ => x86_64::PrivilegeLevel::Ring0,
+        };
+
+        SegmentSelector::new(index as u16, privilege_level)
+    }
+
+    fn push(&mut self, value: u64) -> usize {
+        assert!(self.len < GDT_ENTRIES, "GDT is full");
+
+        let index = self.len;
+        self.table[index] = value;
+        self.len += 1;
+        index
+    }
+
+    pub fn load(&'static self) {
+        use core::mem::size_of;
+
+        let gdt_register = GdtPointer {
+            limit: (self.len * size_of::<u64>() - 1) as u16,
+            base_addr: self.table.as_ptr() as u64,
+        };
+
+        load_gdt(&gdt_register)
+    }
+}
+
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+lazy_static! {
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        let mut gdt = GlobalDescriptorTable::new();
+
+        let kernel_code = gdt.add_entry(Descriptor::kernel_code_segment());
+        let kernel_data = gdt.add_entry(Descriptor::kernel_data_segment());
+        let user_data = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code = gdt.add_entry(Descriptor::user_code_segment());
+        let tss = gdt.add_entry(Descriptor::tss_segment(unsafe { &*addr_of!(TSS) }));
+
+        assert_eq!(
+            [kernel_code.index(), kernel_data.index(), user_data.index(), user_code.index()],
+            [KERNEL_CODE_INDEX, KERNEL_DATA_INDEX, USER_DATA_INDEX, USER_CODE_INDEX]
+        );
+
+        (gdt, Selectors { kernel_code, kernel_data, user_code, user_data, tss })
+    };
+}
+
+fn stack_top(stack: *const [u8; IST_STACK_SIZE]) -> u64 {
+    stack as u64 + IST_STACK_SIZE as u64
+}
+
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
+}
+
+/// Top of the kernel stack used when ring 3 code running on a thread without its own stack
+/// is interrupted or makes a system call.
+pub fn boot_kernel_stack() -> u64 {
+    //the array is only byte aligned, the ABI wants a 16 byte aligned stack
+    unsafe { (addr_of!(BOOT_KERNEL_STACK) as u64 + KERNEL_STACK_SIZE as u64) & !0xF }
+}
+
+/// Sets the stack the CPU switches to when an interrupt or system call arrives in ring 3.
+/// Every thread that runs user code needs its own, so it changes with every thread switch.
+pub fn set_kernel_stack(top: u64) {
+    unsafe { addr_of_mut!(TSS.privilege_stack_table).cast::<u64>().write_unaligned(top) };
+    KERNEL_STACK.store(top, Ordering::Relaxed);
+}
+
+pub fn kernel_stack() -> u64 {
+    KERNEL_STACK.load(Ordering::Relaxed)
+}
+
+pub fn init() {
+    unsafe {
+        TSS.interrupt_stack_table[(DOUBLE_FAULT_IST_INDEX - 1) as usize] = stack_top(addr_of!(DOUBLE_FAULT_STACK));
+        TSS.interrupt_stack_table[(NON_MASKABLE_INTERRUPT_IST_INDEX - 1) as usize] = stack_top(addr_of!(NON_MASKABLE_INTERRUPT_STACK));
+        TSS.interrupt_stack_table[(MACHINE_CHECK_IST_INDEX - 1) as usize] = stack_top(addr_of!(MACHINE_CHECK_STACK));
+    }
+
+    set_kernel_stack(boot_kernel_stack());
+
+    GDT.0.load();
+
+    let selectors = selectors();
+
+    unsafe {
+        CS::set_reg(selectors.kernel_code);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        SS::set_reg(selectors.kernel_data);
+        load_tss(selectors.tss);
+    }
+}
@@ -0,0 +1,186 @@
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+//the controller answers within microseconds, this only guards against a missing controller
+const TIMEOUT: usize = 100_000;
+
+pub struct ControllerCommands;
+
+impl ControllerCommands {
+    pub const READ_CONFIGURATION: u8 = 0x20;
+    pub const WRITE_CONFIGURATION: u8 = 0x60;
+    pub const DISABLE_SECOND_PORT: u8 = 0xA7;
+    pub const TEST_CONTROLLER: u8 = 0xAA;
+    pub const TEST_FIRST_PORT: u8 = 0xAB;
+    pub const DISABLE_FIRST_PORT: u8 = 0xAD;
+    pub const ENABLE_FIRST_PORT: u8 = 0xAE;
+}
+
+pub struct DeviceCommands;
+
+impl DeviceCommands {
+    pub const SET_SCANCODE_SET: u8 = 0xF0;
+    pub const ENABLE_SCANNING: u8 = 0xF4;
+    pub const RESET: u8 = 0xFF;
+}
+
+pub struct DeviceResponses;
+
+impl DeviceResponses {
+    pub const ACKNOWLEDGE: u8 = 0xFA;
+    pub const SELF_TEST_PASSED: u8 = 0xAA;
+    pub const RESEND: u8 = 0xFE;
+}
+
+pub struct StatusMasks;
+
+impl StatusMasks {
+    pub const OUTPUT_FULL: u8 = 0x1;
+    pub const INPUT_FULL: u8 = 0x2;
+}
+
+pub struct ConfigurationMasks;
+
+impl ConfigurationMasks {
+    pub const FIRST_PORT_INTERRUPT: u8 = 0x1;
+    pub const SECOND_PORT_INTERRUPT: u8 = 0x2;
+    pub const FIRST_PORT_TRANSLATION: u8 = 0x40;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerError {
+    Timeout,
+    SelfTestFailed(u8),
+    PortTestFailed(u8),
+    DeviceResetFailed(u8),
+    UnexpectedResponse(u8),
+}
+
+pub struct Controller {
+    data: Port<u8>,
+    status: PortReadOnly<u8>,
+    command: PortWriteOnly<u8>,
+}
+
+impl Controller {
+    pub const fn new() -> Controller {
+        Controller {
+            data: Port::new(0x60),
+            status: PortReadOnly::new(0x64),
+            command: PortWriteOnly::new(0x64),
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        unsafe { self.status.read() }
+    }
+
+    fn wait_for(&mut self, mask: u8, set: bool) -> Result<(), ControllerError> {
+        for _ in 0..TIMEOUT {
+            if (self.status() & mask != 0) == set {
+                return Ok(());
+            }
+            core::hint::spin_loop();
+        }
+
+        Err(ControllerError::Timeout)
+    }
+
+    pub fn read_data(&mut self) -> Result<u8, ControllerError> {
+        self.wait_for(StatusMasks::OUTPUT_FULL, true)?;
+        Ok(unsafe { self.data.read() })
+    }
+
+    pub fn write_data(&mut self, value: u8) -> Result<(), ControllerError> {
+        self.wait_for(StatusMasks::INPUT_FULL, false)?;
+        unsafe { self.data.write(value) };
+        Ok(())
+    }
+
+    pub fn write_command(&mut self, command: u8) -> Result<(), ControllerError> {
+        self.wait_for(StatusMasks::INPUT_FULL, false)?;
+        unsafe { self.command.write(command) };
+        Ok(())
+    }
+
+    /// Reads the data port without waiting, used by the IRQ handler which knows a byte is there.
+    pub fn read_data_unchecked(&mut self) -> u8 {
+        unsafe { self.data.read() }
+    }
+
+    fn flush_output(&mut self) {
+        while self.status() & StatusMasks::OUTPUT_FULL != 0 {
+            unsafe { self.data.read() };
+        }
+    }
+
+    /// Sends a command to the device on the first port and waits for its acknowledgement.
+    pub fn send_device_command(&mut self, command: u8) -> Result<(), ControllerError> {
+        for _ in 0..3 {
+            self.write_data(command)?;
+
+            match self.read_data()? {
+                DeviceResponses::ACKNOWLEDGE => return Ok(()),
+                DeviceResponses::RESEND => continue,
+                response => return Err(ControllerError::UnexpectedResponse(response)),
+            }
+        }
+
+        Err(ControllerError::Timeout)
+    }
+
+    /// Initializes the controller and the keyboard on its first port. With `translation` the
+    /// controller converts everything to scancode set 1, without it the keyboard is switched to set 2.
+    /// The first port's interrupt is left enabled, the second port (the mouse) stays disabled.
+    pub fn init(&mut self, translation: bool) -> Result<(), ControllerError> {
+        self.write_command(ControllerCommands::DISABLE_FIRST_PORT)?;
+        self.write_command(ControllerCommands::DISABLE_SECOND_PORT)?;
+        self.flush_output();
+
+        //no interrupts while we talk to the controller ourselves
+        self.write_command(ControllerCommands::READ_CONFIGURATION)?;
+        let configuration = self.read_data()? & !(ConfigurationMasks::FIRST_PORT_INTERRUPT
+            | ConfigurationMasks::SECOND_PORT_INTERRUPT
+            | ConfigurationMasks::FIRST_PORT_TRANSLATION);
+        self.write_command(ControllerCommands::WRITE_CONFIGURATION)?;
+        self.write_data(configuration)?;
+
+        self.write_command(ControllerCommands::TEST_CONTROLLER)?;
+        match self.read_data()? {
+            0x55 => {}
+            response => return Err(ControllerError::SelfTestFailed(response)),
+        }
+
+        //the self test can reset the controller on some hardware
+        self.write_command(ControllerCommands::WRITE_CONFIGURATION)?;
+        self.write_data(configuration)?;
+
+        self.write_command(ControllerCommands::TEST_FIRST_PORT)?;
+        match self.read_data()? {
+            0x00 => {}
+            response => return Err(ControllerError::PortTestFailed(response)),
+        }
+
+        self.write_command(ControllerCommands::ENABLE_FIRST_PORT)?;
+
+        self.send_device_command(DeviceCommands::RESET)?;
+        match self.read_data()? {
+            DeviceResponses::SELF_TEST_PASSED => {}
+            response => return Err(ControllerError::DeviceResetFailed(response)),
+        }
+
+        if !translation {
+            self.send_device_command(DeviceCommands::SET_SCANCODE_SET)?;
+            self.send_device_command(2)?;
+        }
+
+        self.send_device_command(DeviceCommands::ENABLE_SCANNING)?;
+
+        let mut configuration = configuration | ConfigurationMasks::FIRST_PORT_INTERRUPT;
+        if translation {
+            configuration |= ConfigurationMasks::FIRST_PORT_TRANSLATION;
+        }
+
+        self.write_command(ControllerCommands::WRITE_CONFIGURATION)?;
+        self.write_data(configuration)
+    }
+}
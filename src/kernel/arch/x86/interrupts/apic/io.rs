@@ -0,0 +1,123 @@
+use core::ptr::{read_volatile, write_volatile};
+use x86_64::VirtAddr;
+use crate::kernel::arch::x86::acpi::{Polarity, TriggerMode};
+
+pub struct IoApicRegister;
+
+impl IoApicRegister {
+    pub const ID: u32 = 0x00;
+    pub const VERSION: u32 = 0x01;
+    //every redirection entry is a pair of 32 bit registers starting here
+    pub const REDIRECTION_TABLE: u32 = 0x10;
+}
+
+pub struct RedirectionMasks;
+
+impl RedirectionMasks {
+    pub const ACTIVE_LOW: u64 = 1 << 13;
+    pub const LEVEL_TRIGGERED: u64 = 1 << 15;
+    pub const MASKED: u64 = 1 << 16;
+    pub const DESTINATION_SHIFT: u64 = 56;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RedirectionEntry {
+    pub vector: u8,
+    pub destination: u8,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+    pub masked: bool,
+}
+
+impl RedirectionEntry {
+    fn to_bits(self) -> u64 {
+        let mut bits = self.vector as u64 | (self.destination as u64) << RedirectionMasks::DESTINATION_SHIFT;
+
+        if self.polarity == Polarity::ActiveLow {
+            bits |= RedirectionMasks::ACTIVE_LOW;
+        }
+
+        if self.trigger_mode == TriggerMode::Level {
+            bits |= RedirectionMasks::LEVEL_TRIGGERED;
+        }
+
+        if self.masked {
+            bits |= RedirectionMasks::MASKED;
+        }
+
+        bits
+    }
+}
+
+pub struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+}
+
+impl IoApic {
+    /// `base` is the virtual address the IOREGSEL/IOWIN register pair is mapped at.
+    pub const fn new(base: VirtAddr, gsi_base: u32) -> IoApic {
+        IoApic { base, gsi_base }
+    }
+
+    unsafe fn read(&self, register: u32) -> u32 {
+        write_volatile(self.base.as_mut_ptr::<u32>(), register);
+        read_volatile((self.base + 0x10u64).as_ptr::<u32>())
+    }
+
+    unsafe fn write(&mut self, register: u32, value: u32) {
+        write_volatile(self.base.as_mut_ptr::<u32>(), register);
+        write_volatile((self.base + 0x10u64).as_mut_ptr::<u32>(), value);
+    }
+
+    pub fn id(&self) -> u8 {
+        unsafe { ((self.read(IoApicRegister::ID) >> 24) & 0xF) as u8 }
+    }
+
+    pub fn redirection_entry_count(&self) -> u32 {
+        unsafe { ((self.read(IoApicRegister::VERSION) >> 16) & 0xFF) + 1 }
+    }
+
+    pub fn handles_gsi(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.redirection_entry_count()
+    }
+
+    fn redirection_register(&self, gsi: u32) -> u32 {
+        IoApicRegister::REDIRECTION_TABLE + (gsi - self.gsi_base) * 2
+    }
+
+    pub fn set_redirection(&mut self, gsi: u32, entry: RedirectionEntry) {
+        let register = self.redirection_register(gsi);
+        let bits = entry.to_bits();
+
+        unsafe {
+            //mask first so the line never fires with a half written entry
+            self.write(register, RedirectionMasks::MASKED as u32);
+            self.write(register + 1, (bits >> 32) as u32);
+            self.write(register, bits as u32);
+        }
+    }
+
+    pub fn mask(&mut self, gsi: u32) {
+        let register = self.redirection_register(gsi);
+        unsafe {
+            let low = self.read(register);
+            self.write(register, low | RedirectionMasks::MASKED as u32);
+        }
+    }
+
+    pub fn unmask(&mut self, gsi: u32) {
+        let register = self.redirection_register(gsi);
+        unsafe {
+            let low = self.read(register);
+            self.write(register, low & !(RedirectionMasks::MASKED as u32));
+        }
+    }
+
+    /// Masks every redirection entry.
+    pub fn mask_all(&mut self) {
+        for gsi in self.gsi_base..self.gsi_base + self.redirection_entry_count() {
+            self.mask(gsi);
+        }
+    }
+}
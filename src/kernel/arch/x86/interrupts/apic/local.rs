@@ -0,0 +1,182 @@
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::ptr::{read_volatile, write_volatile};
+use x86_64::registers::model_specific::Msr;
+use x86_64::VirtAddr;
+
+const IA32_APIC_BASE_MSR: u32 = 0x1B;
+//in x2APIC mode register `offset` is MSR 0x800 + offset / 16
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+pub struct ApicBaseMasks;
+
+impl ApicBaseMasks {
+    pub const X2APIC_ENABLE: u64 = 1 << 10;
+    pub const GLOBAL_ENABLE: u64 = 1 << 11;
+    pub const BASE_ADDRESS: u64 = 0xF_FFFF_F000;
+}
+
+pub struct LocalApicRegister;
+
+impl LocalApicRegister {
+    pub const ID: u32 = 0x20;
+    pub const VERSION: u32 = 0x30;
+    pub const TASK_PRIORITY: u32 = 0x80;
+    pub const END_OF_INTERRUPT: u32 = 0xB0;
+    pub const SPURIOUS_INTERRUPT_VECTOR: u32 = 0xF0;
+    pub const ERROR_STATUS: u32 = 0x280;
+    pub const INTERRUPT_COMMAND_LOW: u32 = 0x300;
+    pub const INTERRUPT_COMMAND_HIGH: u32 = 0x310;
+    pub const LVT_TIMER: u32 = 0x320;
+    pub const LVT_LINT0: u32 = 0x350;
+    pub const LVT_LINT1: u32 = 0x360;
+    pub const LVT_ERROR: u32 = 0x370;
+    pub const TIMER_INITIAL_COUNT: u32 = 0x380;
+    pub const TIMER_CURRENT_COUNT: u32 = 0x390;
+    pub const TIMER_DIVIDE_CONFIGURATION: u32 = 0x3E0;
+}
+
+pub struct LvtMasks;
+
+impl LvtMasks {
+    pub const MASKED: u32 = 1 << 16;
+    pub const TIMER_PERIODIC: u32 = 1 << 17;
+    pub const SOFTWARE_ENABLE: u32 = 1 << 8;
+}
+
+/// Divisors of the bus clock that drives the LAPIC timer, as encoded in the divide configuration register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum TimerDivide {
+    By1 = 0b1011,
+    By2 = 0b0000,
+    By4 = 0b0001,
+    By8 = 0b0010,
+    By16 = 0b0011,
+    By32 = 0b1000,
+    By64 = 0b1001,
+    By128 = 0b1010,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    OneShot,
+    Periodic,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicMode {
+    XApic(VirtAddr),
+    X2Apic,
+}
+
+pub fn supports_xapic() -> bool {
+    unsafe { __cpuid(1).edx & (1 << 9) != 0 }
+}
+
+pub fn supports_x2apic() -> bool {
+    unsafe { __cpuid(1).ecx & (1 << 21) != 0 }
+}
+
+/// The 32 bit x2APIC id of this CPU, readable before the LAPIC is switched to x2APIC mode.
+pub fn x2apic_id() -> u32 {
+    unsafe { __cpuid_count(0xB, 0).edx }
+}
+
+/// Physical address of the xAPIC register page according to IA32_APIC_BASE.
+pub fn base_address() -> u64 {
+    unsafe { Msr::new(IA32_APIC_BASE_MSR).read() & ApicBaseMasks::BASE_ADDRESS }
+}
+
+/// The registers are only ever touched one at a time, so a shared reference is enough and
+/// `end_of_interrupt` can be called from interrupt handlers without taking a lock.
+pub struct LocalApic {
+    mode: ApicMode,
+}
+
+impl LocalApic {
+    pub const fn new(mode: ApicMode) -> LocalApic {
+        LocalApic { mode }
+    }
+
+    pub fn mode(&self) -> ApicMode {
+        self.mode
+    }
+
+    pub unsafe fn read(&self, register: u32) -> u32 {
+        match self.mode {
+            ApicMode::XApic(base) => read_volatile((base + register as u64).as_ptr::<u32>()),
+            ApicMode::X2Apic => Msr::new(X2APIC_MSR_BASE + (register >> 4)).read() as u32,
+        }
+    }
+
+    pub unsafe fn write(&self, register: u32, value: u32) {
+        match self.mode {
+            ApicMode::XApic(base) => write_volatile((base + register as u64).as_mut_ptr::<u32>(), value),
+            ApicMode::X2Apic => Msr::new(X2APIC_MSR_BASE + (register >> 4)).write(value as u64),
+        }
+    }
+
+    /// Enables the LAPIC in its mode and delivers spurious interrupts to `spurious_vector`.
+    pub unsafe fn enable(&self, spurious_vector: u8, error_vector: u8) {
+        let mut apic_base = Msr::new(IA32_APIC_BASE_MSR);
+        let mut value = apic_base.read() | ApicBaseMasks::GLOBAL_ENABLE;
+
+        if self.mode == ApicMode::X2Apic {
+            value |= ApicBaseMasks::X2APIC_ENABLE;
+        }
+
+        apic_base.write(value);
+
+        self.write(LocalApicRegister::SPURIOUS_INTERRUPT_VECTOR, LvtMasks::SOFTWARE_ENABLE | spurious_vector as u32);
+        self.write(LocalApicRegister::LVT_ERROR, error_vector as u32);
+        //the error status register has to be written before it can be read
+        self.write(LocalApicRegister::ERROR_STATUS, 0);
+        self.write(LocalApicRegister::TASK_PRIORITY, 0);
+    }
+
+    pub fn id(&self) -> u32 {
+        let id = unsafe { self.read(LocalApicRegister::ID) };
+
+        match self.mode {
+            //the xAPIC keeps its 8 bit id in the top byte
+            ApicMode::XApic(_) => id >> 24,
+            ApicMode::X2Apic => id,
+        }
+    }
+
+    pub fn end_of_interrupt(&self) {
+        unsafe { self.write(LocalApicRegister::END_OF_INTERRUPT, 0) };
+    }
+
+    pub fn error_status(&self) -> u32 {
+        unsafe {
+            self.write(LocalApicRegister::ERROR_STATUS, 0);
+            self.read(LocalApicRegister::ERROR_STATUS)
+        }
+    }
+
+    pub fn start_timer(&self, vector: u8, mode: TimerMode, divide: TimerDivide, initial_count: u32) {
+        let mode = match mode {
+            TimerMode::OneShot => 0,
+            TimerMode::Periodic => LvtMasks::TIMER_PERIODIC,
+        };
+
+        unsafe {
+            self.write(LocalApicRegister::TIMER_DIVIDE_CONFIGURATION, divide as u32);
+            self.write(LocalApicRegister::LVT_TIMER, mode | vector as u32);
+            //writing the initial count (re)starts the timer
+            self.write(LocalApicRegister::TIMER_INITIAL_COUNT, initial_count);
+        }
+    }
+
+    pub fn stop_timer(&self) {
+        unsafe {
+            self.write(LocalApicRegister::TIMER_INITIAL_COUNT, 0);
+            self.write(LocalApicRegister::LVT_TIMER, LvtMasks::MASKED);
+        }
+    }
+
+    pub fn timer_current_count(&self) -> u32 {
+        unsafe { self.read(LocalApicRegister::TIMER_CURRENT_COUNT) }
+    }
+}
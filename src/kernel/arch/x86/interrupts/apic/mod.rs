@@ -0,0 +1,179 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+use crate::println;
+use crate::kernel::arch::x86::pit;
+use crate::kernel::arch::x86::acpi::{self, MadtInfo, Polarity, TriggerMode, MAX_IO_APICS};
+use crate::kernel::arch::x86::interrupts::irq::{self, IRQ_BASE};
+use crate::kernel::arch::x86::interrupts::pic::{PICS, IRQ_COUNT};
+use crate::kernel::arch::x86::registers::StackFrame;
+use io::{IoApic, RedirectionEntry};
+use local::{ApicMode, LocalApic, TimerDivide, TimerMode};
+
+pub mod local;
+pub mod io;
+
+//the ISA IRQs keep the vectors they had on the PICs, the LAPIC's own vectors go after them
+pub const LOCAL_TIMER_VECTOR: u8 = IRQ_BASE + IRQ_COUNT;
+pub const ERROR_VECTOR: u8 = 0xFE;
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicError {
+    Unsupported,
+    MadtNotFound,
+    NoIoApic,
+    //without interrupt remapping the I/O APIC can only target LAPIC ids below 256
+    UnroutableApicId,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+static MADT: Once<MadtInfo> = Once::new();
+const NO_IO_APIC: Option<IoApic> = None;
+static IO_APICS: Mutex<[Option<IoApic>; MAX_IO_APICS]> = Mutex::new([NO_IO_APIC; MAX_IO_APICS]);
+
+/// Whether interrupts are delivered through the APICs instead of the 8259 PICs.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LOCAL_APIC.get()
+}
+
+/// Switches interrupt delivery from the 8259 PICs over to the local and I/O APICs.
+/// The APIC registers are reached through the bootloader's mapping of all physical memory
+/// at `physical_memory_offset`. ISA IRQs that already have a handler are rerouted.
+pub fn init(physical_memory_offset: VirtAddr) -> Result<(), ApicError> {
+    if !local::supports_xapic() {
+        return Err(ApicError::Unsupported);
+    }
+
+    let madt = acpi::parse_madt(physical_memory_offset).ok_or(ApicError::MadtNotFound)?;
+
+    if madt.io_apics.iter().flatten().next().is_none() {
+        return Err(ApicError::NoIoApic);
+    }
+
+    //x2APIC is driven through MSRs and doesn't need its register page mapped
+    let mode = if local::supports_x2apic() {
+        if local::x2apic_id() > u8::MAX as u32 {
+            return Err(ApicError::UnroutableApicId);
+        }
+
+        ApicMode::X2Apic
+    } else {
+        ApicMode::XApic(physical_memory_offset + local::base_address())
+    };
+
+    interrupts::without_interrupts(|| {
+        unsafe { PICS.lock().disable() };
+
+        let local_apic = LOCAL_APIC.call_once(|| LocalApic::new(mode));
+        unsafe { local_apic.enable(SPURIOUS_VECTOR, ERROR_VECTOR) };
+
+        let mut io_apics = IO_APICS.lock();
+        for (slot, info) in io_apics.iter_mut().zip(madt.io_apics.iter()) {
+            *slot = info.map(|info| {
+                let mut io_apic = IoApic::new(physical_memory_offset + info.address, info.gsi_base);
+                io_apic.mask_all();
+                io_apic
+            });
+        }
+        drop(io_apics);
+
+        MADT.call_once(|| madt);
+        ENABLED.store(true, Ordering::Release);
+
+        irq::register_vector(ERROR_VECTOR, error_interrupt);
+
+        for irq in 0..IRQ_COUNT {
+            if irq::has_handler(IRQ_BASE + irq) {
+                route_isa_irq(irq, false);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn error_interrupt(_stack_frame: &StackFrame) {
+    if let Some(local_apic) = local_apic() {
+        println!("APIC error, error status: {:#x}", local_apic.error_status());
+    }
+}
+
+/// Maps an ISA IRQ to its global system interrupt, honoring the MADT's interrupt source overrides.
+fn isa_irq_to_gsi(irq: u8) -> (u32, Polarity, TriggerMode) {
+    match MADT.get().and_then(|madt| madt.source_override(irq)) {
+        Some(source_override) => (source_override.gsi, source_override.polarity, source_override.trigger_mode),
+        None => (irq as u32, Polarity::ActiveHigh, TriggerMode::Edge),
+    }
+}
+
+fn with_io_apic<F: FnOnce(&mut IoApic)>(gsi: u32, f: F) {
+    interrupts::without_interrupts(|| {
+        let mut io_apics = IO_APICS.lock();
+
+        if let Some(io_apic) = io_apics.iter_mut().flatten().find(|io_apic| io_apic.handles_gsi(gsi)) {
+            f(io_apic);
+        }
+    });
+}
+
+/// Routes ISA `irq` to vector `IRQ_BASE + irq` on this CPU, the same vector the PICs used.
+pub fn route_isa_irq(irq: u8, masked: bool) {
+    let (gsi, polarity, trigger_mode) = isa_irq_to_gsi(irq);
+    //`init` refused ids that don't fit the redirection entry's destination field
+    let destination = local_apic().map_or(0, |local_apic| {
+        u8::try_from(local_apic.id()).expect("LAPIC id isn't routable")
+    });
+
+    with_io_apic(gsi, |io_apic| io_apic.set_redirection(gsi, RedirectionEntry {
+        vector: IRQ_BASE + irq,
+        destination,
+        polarity,
+        trigger_mode,
+        masked,
+    }));
+}
+
+pub fn mask_isa_irq(irq: u8) {
+    let (gsi, _, _) = isa_irq_to_gsi(irq);
+    with_io_apic(gsi, |io_apic| io_apic.mask(gsi));
+}
+
+pub fn end_of_interrupt() {
+    if let Some(local_apic) = local_apic() {
+        local_apic.end_of_interrupt();
+    }
+}
+
+/// Starts the LAPIC timer, which interrupts on `LOCAL_TIMER_VECTOR`.
+pub fn start_local_timer(mode: TimerMode, divide: TimerDivide, initial_count: u32) {
+    if let Some(local_apic) = local_apic() {
+        local_apic.start_timer(LOCAL_TIMER_VECTOR, mode, divide, initial_count);
+    }
+}
+
+pub fn stop_local_timer() {
+    if let Some(local_apic) = local_apic() {
+        local_apic.stop_timer();
+    }
+}
+
+/// Measures how many LAPIC timer ticks pass per millisecond with `divide`, using the PIT as reference.
+/// The LAPIC timer runs off the bus clock, whose frequency the CPU doesn't report.
+pub fn calibrate_local_timer(divide: TimerDivide) -> Option<u32> {
+    let local_apic = local_apic()?;
+    const CALIBRATION_MILLISECONDS: u32 = 10;
+
+    local_apic.start_timer(LOCAL_TIMER_VECTOR, TimerMode::OneShot, divide, u32::MAX);
+    pit::busy_wait_microseconds(CALIBRATION_MILLISECONDS as u64 * 1000);
+    let elapsed = u32::MAX - local_apic.timer_current_count();
+    local_apic.stop_timer();
+
+    Some(elapsed / CALIBRATION_MILLISECONDS)
+}
@@ -0,0 +1,101 @@
+use x86_64::VirtAddr;
+use crate::kernel::arch::x86::interrupts::exception::ExceptionContext;
+use crate::kernel::memory::paging;
+
+/// Carries out the instruction that raised an invalid opcode exception in user mode if it is one
+/// the processor lacks but the kernel can stand in for, and moves `rip` behind it. Returns false for
+/// anything else, the registers are left alone then. The kernel itself never relies on emulation.
+pub fn emulate(context: &mut ExceptionContext) -> bool {
+    if !context.registers.iret.is_user_mode() {
+        return false;
+    }
+
+    //rip may point anywhere, user mode must not get the kernel to read kernel memory
+    let rip = context.registers.iret.rip as u64;
+    let byte = |offset: u64| -> Option<u8> {
+        let address = VirtAddr::try_new(rip.checked_add(offset)?).ok()?;
+
+        if paging::is_user_accessible(address, 1, false) {
+            Some(unsafe { address.as_ptr::<u8>().read() })
+        } else {
+            None
+        }
+    };
+
+    match rdpid(byte) {
+        //IA32_TSC_AUX holds the number of the processor, only the bootstrap processor runs
+        Some((register, length)) if context.registers.set_register(register, 0) => {
+            context.skip_instruction(length);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Decodes `rdpid r64` (`F3 [REX] 0F C7 /7` with a register operand), returns the register
+/// number and the length of the instruction.
+fn rdpid(byte: impl Fn(u64) -> Option<u8>) -> Option<(u8, usize)> {
+    if byte(0)? != 0xf3 {
+        return None;
+    }
+
+    let (rex, opcode_at) = match byte(1)? {
+        rex @ 0x40..=0x4f => (rex, 2),
+        _ => (0, 1),
+    };
+
+    let modrm = byte(opcode_at + 2)?;
+    let (mode, reg, rm) = (modrm >> 6, (modrm >> 3) & 0x7, modrm & 0x7);
+
+    if byte(opcode_at)? != 0x0f || byte(opcode_at + 1)? != 0xc7 || mode != 0b11 || reg != 7 {
+        return None;
+    }
+
+    //REX.B extends the register to r8 - r15
+    Some((rm | ((rex & 0x1) << 3), opcode_at as usize + 3))
+}
+
+#[test_case]
+fn rdpid_is_emulated() {
+    use alloc::boxed::Box;
+    use x86_64::structures::paging::{Page, PageTableFlags};
+    use crate::kernel::arch::x86::gdt;
+    use crate::kernel::arch::x86::interrupts::exception::ExceptionVector;
+    use crate::kernel::arch::x86::registers::StackFrame;
+    use crate::kernel::lib::testing;
+    use crate::kernel::memory;
+    use crate::kernel::memory::paging::{AddressSpace, USER_SPACE_START};
+
+    //rdpid rax, rdpid r9, ud2
+    const CODE: [u8; 11] = [0xf3, 0x0f, 0xc7, 0xf8, 0xf3, 0x41, 0x0f, 0xc7, 0xf9, 0x0f, 0x0b];
+    const START: usize = USER_SPACE_START as usize;
+
+    let mut address_space = Box::new(AddressSpace::new().unwrap());
+    let page = Page::containing_address(VirtAddr::new(USER_SPACE_START));
+    let frame = address_space.map_zeroed(page, PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE).unwrap();
+    unsafe { *memory::phys_to_virt(frame.start_address()).as_mut_ptr::<[u8; 11]>() = CODE };
+
+    //the user page is only reachable while the address space is active
+    testing::run_in(&mut address_space, || {
+        let mut registers = StackFrame::default();
+        registers.iret.rip = START;
+        registers.iret.cs = gdt::selectors().user_code.0 as usize;
+        registers.scratch.rax = usize::MAX;
+        registers.scratch.r9 = usize::MAX;
+
+        let mut context = ExceptionContext::new(ExceptionVector::INVALID_OPCODE, 0, &mut registers);
+        assert!(emulate(&mut context));
+        assert!(emulate(&mut context));
+        assert!(!emulate(&mut context));
+
+        assert_eq!({ registers.iret.rip }, START + 9);
+        assert_eq!({ registers.scratch.rax }, 0);
+        assert_eq!({ registers.scratch.r9 }, 0);
+
+        //an invalid opcode in the kernel is a bug, it isn't emulated
+        registers.iret.rip = START;
+        registers.iret.cs = gdt::selectors().kernel_code.0 as usize;
+        let mut context = ExceptionContext::new(ExceptionVector::INVALID_OPCODE, 0, &mut registers);
+        assert!(!emulate(&mut context));
+    });
+}
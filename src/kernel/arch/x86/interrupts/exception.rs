@@ -1,9 +1,16 @@
 use core::arch::asm;
+use core::fmt;
 use core::ptr::addr_of_mut;
+use x86_64::VirtAddr;
+use x86_64::registers::control::Cr2;
 use crate::{enum_str, println};
-use crate::kernel::arch::x86::interrupts::page_fault;
-use crate::kernel::arch::x86::interrupts::page_fault::{PageFault, PageFaultBuilder, PageFaultErrorCode};
+use crate::kernel::arch::x86::interrupts::{emulate, page_fault};
+use crate::kernel::arch::x86::interrupts::page_fault::{PageFault, PageFaultBuilder};
+use crate::kernel::arch::x86::crash;
 use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::memory::paging::{self, FaultError};
+use crate::kernel::process::{self, Signal};
+use crate::kernel::process::signal;
 
 #[macro_export]
 macro_rules! save_scratch_registers {
@@ -66,15 +73,15 @@ macro_rules! interrupt_error {
         #[naked]
         extern "C" fn wrapper() -> ! {
            unsafe {
-                asm! {
-                    save_scratch_registers!(), //save scratch (caller-saved/volatile) registers
-                    save_preserved_registers!(), //save preserved (callee-saved/non volatile) registers
+                core::arch::asm! {
+                    $crate::save_scratch_registers!(), //save scratch (caller-saved/volatile) registers
+                    $crate::save_preserved_registers!(), //save preserved (callee-saved/non volatile) registers
 
                     "mov rdi, rsp", //rdi is used as the first argument passed to a function so we move rsp to rdi
                     "call {}", //call the function specified by $name with pointer to stack (rdi)
 
-                    restore_preserved_registers!(), //restore preserved (callee-saved/non volatile) registers
-                    restore_scratch_registers!(), //restore scratch (caller-saved/volatile) registers
+                    $crate::restore_preserved_registers!(), //restore preserved (callee-saved/non volatile) registers
+                    $crate::restore_scratch_registers!(), //restore scratch (caller-saved/volatile) registers
 
                     "iretq", //return program control to the program/procedure that was interrupted
                     sym $name,
@@ -86,171 +93,376 @@ macro_rules! interrupt_error {
     }}
 }
 
+/// Makes the IDT entry for exception `vector`, which calls `handler(&mut ExceptionContext)`.
+/// Whether the processor pushes an error code follows from the vector, so the trampoline always
+/// matches the exception. The vector has to be a constant.
 #[macro_export]
-macro_rules! interrupt_error_with_code {
-    ($name: ident) => {{
+macro_rules! exception_entry {
+    ($vector: expr, $handler: path) => {{
+        //calling the handler here checks its signature, `sym` would take any function
+        extern "C" fn entry(registers: &mut $crate::kernel::arch::x86::registers::StackFrame, error_code: usize) {
+            let mut context = $crate::kernel::arch::x86::interrupts::exception::ExceptionContext::new($vector, error_code, registers);
+            $handler(&mut context);
+        }
+
         #[naked]
         extern "C" fn wrapper() -> ! {
             unsafe {
-                asm! {
-                    save_scratch_registers!(), //save scratch (caller-saved/volatile) registers
-                    save_preserved_registers!(), //save preserved (callee-saved/non volatile) registers
-
-                    "mov rsi, [rsp + 15 * 8]", //rsi is used as the second argument passed to a function, so we move the error code into rsi.
-                    "mov rdi, rsp", //rdi is used as the first argument passed to a function so we move rsp to rdi
-                    "call {}",  //call the function specified by $name with pointer to stack (rdi) and error code (rsi)
-
-                    restore_preserved_registers!(), //restore preserved (callee-saved/non volatile) registers
-                    restore_scratch_registers!(), //restore scratch (caller-saved/volatile) registers
-
-                    "add rsp,8", //pop error code
+                core::arch::asm! {
+                    ".if {has_error_code}",
+                    "xchg [rsp], rax", //swap the error code for rax, so that rax sits right below the iret frame like after `push rax`
+                    ".else",
+                    "push rax",
+                    ".endif",
+                    "push rcx", //save the other scratch (caller-saved/volatile) registers, see `save_scratch_registers`
+                    "push rdx",
+                    "push rdi",
+                    "push rsi",
+                    "push r8",
+                    "push r9",
+                    "push r10",
+                    "push r11",
+                    $crate::save_preserved_registers!(), //save preserved (callee-saved/non volatile) registers
+
+                    "mov rsi, rax", //second argument: the error code, meaningless for exceptions without one
+                    "mov rdi, rsp", //first argument: the saved registers, laid out as a `StackFrame`
+                    "call {entry}",
+
+                    $crate::restore_preserved_registers!(), //restore the registers, including changes the handler made
+                    $crate::restore_scratch_registers!(),
 
                     "iretq", //return program control to the program/procedure that was interrupted
-                    sym $name,
+                    has_error_code = const $crate::kernel::arch::x86::interrupts::exception::has_error_code($vector) as u8,
+                    entry = sym entry,
                     options(noreturn)
                 }
             }
         }
-        wrapper
+
+        $crate::kernel::arch::x86::interrupts::idt::ExceptionEntry { vector: $vector, handler: wrapper }
     }}
 }
 
-pub extern "C" fn divide_by_zero(stack_frame: &StackFrame) {
-    println!("\nEXCEPTION: DIVIDE BY ZERO\n");
-    println!("\nRegister dump:");
-    stack_frame.dump();
+pub struct ExceptionVector;
+
+impl ExceptionVector {
+    pub const DIVIDE_ERROR: u8 = 0;
+    pub const DEBUG: u8 = 1;
+    pub const NON_MASKABLE_INTERRUPT: u8 = 2;
+    pub const BREAKPOINT: u8 = 3;
+    pub const OVERFLOW: u8 = 4;
+    pub const BOUND_RANGE_EXCEEDED: u8 = 5;
+    pub const INVALID_OPCODE: u8 = 6;
+    pub const DEVICE_NOT_AVAILABLE: u8 = 7;
+    pub const DOUBLE_FAULT: u8 = 8;
+    pub const INVALID_TSS: u8 = 10;
+    pub const SEGMENT_NOT_PRESENT: u8 = 11;
+    pub const STACK_SEGMENT_FAULT: u8 = 12;
+    pub const GENERAL_PROTECTION_FAULT: u8 = 13;
+    pub const PAGE_FAULT: u8 = 14;
+    pub const X87_FLOATING_POINT: u8 = 16;
+    pub const ALIGNMENT_CHECK: u8 = 17;
+    pub const MACHINE_CHECK: u8 = 18;
+    pub const SIMD_FLOATING_POINT: u8 = 19;
+    pub const VIRTUALIZATION: u8 = 20;
+    pub const CONTROL_PROTECTION: u8 = 21;
+    pub const HYPERVISOR_INJECTION: u8 = 28;
+    pub const VMM_COMMUNICATION: u8 = 29;
+    pub const SECURITY: u8 = 30;
 }
 
-pub extern "C" fn debug(stack_frame: &StackFrame) {
+/// Whether the processor pushes an error code for exception `vector`, see the Intel SDM Vol. 3A, 6.15.
+pub const fn has_error_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
 
+pub fn exception_name(vector: u8) -> &'static str {
+    match vector {
+        ExceptionVector::DIVIDE_ERROR => "divide error",
+        ExceptionVector::DEBUG => "debug",
+        ExceptionVector::NON_MASKABLE_INTERRUPT => "non-maskable interrupt",
+        ExceptionVector::BREAKPOINT => "breakpoint",
+        ExceptionVector::OVERFLOW => "overflow",
+        ExceptionVector::BOUND_RANGE_EXCEEDED => "bound range exceeded",
+        ExceptionVector::INVALID_OPCODE => "invalid opcode",
+        ExceptionVector::DEVICE_NOT_AVAILABLE => "device not available",
+        ExceptionVector::DOUBLE_FAULT => "double fault",
+        ExceptionVector::INVALID_TSS => "invalid TSS",
+        ExceptionVector::SEGMENT_NOT_PRESENT => "segment not present",
+        ExceptionVector::STACK_SEGMENT_FAULT => "stack segment fault",
+        ExceptionVector::GENERAL_PROTECTION_FAULT => "general protection fault",
+        ExceptionVector::PAGE_FAULT => "page fault",
+        ExceptionVector::X87_FLOATING_POINT => "x87 floating point exception",
+        ExceptionVector::ALIGNMENT_CHECK => "alignment check",
+        ExceptionVector::MACHINE_CHECK => "machine check",
+        ExceptionVector::SIMD_FLOATING_POINT => "SIMD floating point exception",
+        ExceptionVector::VIRTUALIZATION => "virtualization exception",
+        ExceptionVector::CONTROL_PROTECTION => "control protection exception",
+        ExceptionVector::HYPERVISOR_INJECTION => "hypervisor injection exception",
+        ExceptionVector::VMM_COMMUNICATION => "VMM communication exception",
+        ExceptionVector::SECURITY => "security exception",
+        _ => "reserved exception",
+    }
 }
 
-pub extern "C" fn non_maskable_interrupt(stack_frame: &StackFrame) {
-    println!("Non-maskable Interrupt!\n");
-    println!("Register dump: ");
-    stack_frame.dump();
+/// Everything a handler gets about an exception. The registers are those of the interrupted code
+/// and are restored from here on return, so changing them changes where and how it goes on.
+pub struct ExceptionContext<'a> {
+    pub vector: u8,
+    pub error_code: Option<usize>,
+    pub registers: &'a mut StackFrame,
+    /// The address that caused a page fault, read from CR2 before anything can fault again.
+    pub fault_address: Option<VirtAddr>,
 }
 
+impl<'a> ExceptionContext<'a> {
+    /// `error_code` is ignored for vectors without one.
+    pub fn new(vector: u8, error_code: usize, registers: &'a mut StackFrame) -> ExceptionContext<'a> {
+        ExceptionContext {
+            vector,
+            error_code: if has_error_code(vector) { Some(error_code) } else { None },
+            fault_address: if vector == ExceptionVector::PAGE_FAULT { Some(Cr2::read()) } else { None },
+            registers,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        exception_name(self.vector)
+    }
 
-pub extern "C" fn breakpoint(stack_frame: &StackFrame) {
-    let rip = stack_frame.iret.rip;
-    println!("\nEXCEPTION: BREAKPOINT at {:#x}\n", rip);
-    println!("Register dump: ");
-    stack_frame.dump();
+    pub fn is_user_mode(&self) -> bool {
+        self.registers.iret.is_user_mode()
+    }
+
+    /// Goes on behind the faulting instruction of `length` bytes instead of retrying it.
+    pub fn skip_instruction(&mut self, length: usize) {
+        self.registers.iret.rip += length;
+    }
 }
 
-pub extern "C" fn overflow(stack_frame: &StackFrame) {
-    println!("overflow exception!\n");
-    println!("Register dump: ");
-    stack_frame.dump();
+/// E.g. `general protection fault (vector 13), error code 0x10`.
+impl fmt::Display for ExceptionContext<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (vector {})", self.name(), self.vector)?;
+
+        if let Some(error_code) = self.error_code {
+            write!(f, ", error code {:#x}", error_code)?;
+        }
+        if let Some(address) = self.fault_address {
+            write!(f, ", address {:#x}", address.as_u64())?;
+        }
+
+        Ok(())
+    }
 }
 
-pub extern "C" fn bound_range_exceeded(stack_frame: &StackFrame) {
-    println!("Bound range exceeded!\n");
-    println!("Register dump: ");
-    stack_frame.dump();
+/// A fault in user mode only concerns the process that caused it. If it has a handler for `signal`,
+/// the thread is redirected there and true is returned, so the exception handler only has to return.
+/// Otherwise the process is killed and the kernel goes on with the next thread. Returns false if the
+/// fault happened in the kernel.
+fn signal_faulting_process(context: &mut ExceptionContext, signal: Signal) -> bool {
+    if !context.is_user_mode() {
+        return false;
+    }
+    if signal::deliver(context.registers, signal) {
+        return true;
+    }
+
+    let iret = &context.registers.iret;
+    println!("process {} killed by {} ({} at {:#x}, CS={}, RFLAGS={})",
+             process::current(), signal.name(), context, { iret.rip }, iret.code_segment(), iret.rflags());
+    process::kill_current(signal);
 }
 
-pub extern "C" fn invalid_opcode(stack_frame: &StackFrame) {
-    println!("Invalid opcode exception!\n");
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn divide_by_zero(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::FloatingPoint) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn device_not_available(stack_frame: &StackFrame) {
-    println!("Device not available exception!\n");
-    println!("Register dump: ");
-    stack_frame.dump();
+//the kernel sets neither the trap flag nor debug registers, so only a debugger of a process expects these
+pub fn debug(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Trap) {
+        return;
+    }
+    crash::exception(context);
 }
 
-//double fault always generate an error code with a value of zero
-pub extern "C" fn double_fault(stack_frame: &StackFrame, error_code: usize) {
-    println!("Double fault occurred! with error code: {}\n", error_code);
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn non_maskable_interrupt(context: &mut ExceptionContext) {
+    //nothing raises NMIs on purpose, they report hardware errors
+    crash::exception(context);
+}
+
+//a trap, the report is shown and execution goes on after the int3
+pub fn breakpoint(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Trap) {
+        return;
+    }
+    crash::log_report(format_args!("{}", context), context.registers);
 }
 
-pub extern "C" fn invalid_tss(stack_frame: &StackFrame) {
-    println!("Invalid TSS fault\n");
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn overflow(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Segfault) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn segment_not_present(stack_frame: &StackFrame, error_code: usize) {
-    println!("Segment not present exception with error code: {}\n", error_code);
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn bound_range_exceeded(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Segfault) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn stack_segment_fault(stack_frame: &StackFrame, error_code: usize) {
-    println!("stack segment fault with error code: {}\n", error_code);
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn invalid_opcode(context: &mut ExceptionContext) {
+    if emulate::emulate(context) || signal_faulting_process(context, Signal::Illegal) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn general_protection_fault(stack_frame: &StackFrame, error_code: usize) {
-    println!("general protection fault with error code: {}\n", error_code);
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn device_not_available(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::FloatingPoint) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn page_fault(stack_frame: &StackFrame, error_code: usize) {
-    let page_fault = PageFaultBuilder::build(error_code);
+//double fault always generate an error code with a value of zero
+//it runs on its own IST stack, so it is still reported when the kernel stack overflowed.
+//a double fault is an abort, there is no state we could return to.
+pub fn double_fault(context: &mut ExceptionContext) -> ! {
+    crash::exception(context);
+}
 
-    println!("\nEXCEPTION: PAGE FAULT while accessing {:#x} with error {:?}\nAccess mode: {}\
-             \nReserved mode: {}\n",
-             page_fault.addr,
-             page_fault.error_code_description.name(),
-             page_fault.access_mode.name(),
-             page_fault.reserved);
+pub fn invalid_tss(context: &mut ExceptionContext) {
+    crash::exception(context);
+}
 
-    if page_fault.caused_by_instruction_fetch {
-        println!("Caused by instruction fetch");
+pub fn segment_not_present(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Bus) {
+        return;
     }
+    crash::exception(context);
+}
 
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn stack_segment_fault(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Bus) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn x87_floating_point_exception(stack_frame: &StackFrame) {
-    println!("x87 Floating-Point Exception!");
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn general_protection_fault(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Segfault) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn alignment_check(stack_frame: &StackFrame) {
-    println!("Alignment Check Exception!");
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn page_fault(context: &mut ExceptionContext) {
+    let page_fault = PageFaultBuilder::build(context.error_code.unwrap_or_default());
+
+    //returning retries the faulting instruction, which only makes sense if the fault was resolved
+    if let Err(error) = paging::handle_page_fault(&page_fault) {
+        if context.is_user_mode() {
+            //running out of memory can't be handled by the process
+            if error != FaultError::OutOfMemory && signal::deliver(context.registers, Signal::Segfault) {
+                return;
+            }
+
+            let signal = if error == FaultError::OutOfMemory { Signal::Kill } else { Signal::Segfault };
+            let iret = &context.registers.iret;
+            println!("process {} killed by {} ({} at {:#x}, CS={}, RFLAGS={})",
+                     process::current(), signal.name(), error.name(), page_fault.addr, iret.code_segment(), iret.rflags());
+            process::kill_current(signal);
+        }
+
+        report_page_fault(context.registers, &page_fault, error);
+        crash::halt();
+    }
 }
 
-pub extern "C" fn simd_floating_point_exception(stack_frame: &StackFrame) {
-    println!("SIMD Floating-Point Exception!");
-    println!("Register dump: ");
-    stack_frame.dump();
+/// Prints the crash report of a page fault that could not be resolved, without halting.
+pub fn report_page_fault(registers: &StackFrame, page_fault: &PageFault, error: FaultError) {
+    crash::print_report(
+        format_args!("page fault while accessing {:#x}: {}, error code {:#x} ({})",
+                     page_fault.addr,
+                     error.name(),
+                     u64::from_le_bytes(page_fault.error_code.into_bytes()),
+                     page_fault.error_code),
+        registers,
+    );
 }
 
-pub extern "C" fn virtualization_exception(stack_frame: &StackFrame) {
-    println!("Virtualization Exception!");
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn x87_floating_point_exception(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::FloatingPoint) {
+        return;
+    }
+    crash::exception(context);
 }
 
-pub extern "C" fn control_protection_exception(stack_frame: &StackFrame) {
+pub fn alignment_check(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Bus) {
+        return;
+    }
+    crash::exception(context);
+}
 
+//a machine check is an abort, the processor state can't be trusted anymore
+pub fn machine_check(context: &mut ExceptionContext) -> ! {
+    crash::exception(context);
 }
 
-pub extern "C" fn hypervisor_injection_exception(stack_frame: &StackFrame) {
+pub fn simd_floating_point_exception(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::FloatingPoint) {
+        return;
+    }
+    crash::exception(context);
+}
 
+pub fn virtualization_exception(context: &mut ExceptionContext) {
+    crash::exception(context);
 }
 
-pub extern "C" fn vmm_communication_exception(stack_frame: &StackFrame) {
+pub fn control_protection_exception(context: &mut ExceptionContext) {
+    if signal_faulting_process(context, Signal::Segfault) {
+        return;
+    }
+    crash::exception(context);
+}
+
+//only raised in guests of a hypervisor that expects them to be handled, which this kernel doesn't
+pub fn hypervisor_injection_exception(context: &mut ExceptionContext) {
+    crash::exception(context);
+}
 
+pub fn vmm_communication_exception(context: &mut ExceptionContext) {
+    crash::exception(context);
 }
 
-pub extern "C" fn security_exception(stack_frame: &StackFrame) {
-    println!("Security Exception!");
-    println!("Register dump: ");
-    stack_frame.dump();
+pub fn security_exception(context: &mut ExceptionContext) {
+    crash::exception(context);
 }
 
+#[test_case]
+fn error_codes_follow_the_vector() {
+    let mut registers = StackFrame::default();
+
+    let context = ExceptionContext::new(ExceptionVector::GENERAL_PROTECTION_FAULT, 0x10, &mut registers);
+    assert_eq!(context.error_code, Some(0x10));
+    assert_eq!(context.fault_address, None);
+
+    let context = ExceptionContext::new(ExceptionVector::INVALID_OPCODE, 0x10, &mut registers);
+    assert_eq!(context.error_code, None);
+
+    assert!(has_error_code(ExceptionVector::INVALID_TSS));
+    assert!(has_error_code(ExceptionVector::ALIGNMENT_CHECK));
+    assert!(has_error_code(ExceptionVector::SECURITY));
+    assert!(has_error_code(ExceptionVector::CONTROL_PROTECTION));
+    assert!(has_error_code(ExceptionVector::VMM_COMMUNICATION));
+    assert!(!has_error_code(ExceptionVector::HYPERVISOR_INJECTION));
+    assert!(!has_error_code(ExceptionVector::MACHINE_CHECK));
+}
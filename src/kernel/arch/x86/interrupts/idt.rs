@@ -5,21 +5,15 @@ use x86_64::structures::gdt::SegmentSelector;
 use modular_bitfield::prelude::*;
 use x86_64::instructions::segmentation::CS;
 use x86_64::registers::segmentation::Segment;
-use crate::kernel::arch::x86::interrupts::{exception, idt};
+use crate::kernel::arch::x86::gdt;
+use crate::kernel::arch::x86::syscall::{syscall_interrupt, SYSCALL_VECTOR};
+use crate::kernel::arch::x86::interrupts::{exception, idt, irq};
 use crate::kernel::arch::x86::interrupts::exception::*;
 
-use crate:: {
-    println,
-    interrupt_error,
-    interrupt_error_with_code,
-    save_scratch_registers,
-    restore_scratch_registers,
-    save_preserved_registers,
-    restore_preserved_registers,
-};
+use crate::{exception_entry, interrupt_error};
 
 pub type HandlerFunction = extern "C" fn() -> !;
-pub struct InterruptDescriptorTable([Entry; 31]);
+pub struct InterruptDescriptorTable([Entry; 256]);
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 #[repr(u8)]
@@ -53,6 +47,12 @@ pub fn load_idt(idt_register: &InterruptPointer) {
     }
 }
 
+/// The trampoline `exception_entry!` made for an exception vector.
+pub struct ExceptionEntry {
+    pub vector: u8,
+    pub handler: HandlerFunction,
+}
+
 pub struct Attributes {
     pub gate_type: GateType,
     pub privilege_level: PrivilegeLevel,
@@ -128,7 +128,7 @@ impl InterruptDescriptorTable {
     }
 
     pub fn new() -> InterruptDescriptorTable {
-        InterruptDescriptorTable([Entry::new(); 31])
+        InterruptDescriptorTable([Entry::new(); 256])
     }
 
     pub fn disable_interrupts(&mut self, entry: usize) {
@@ -145,6 +145,18 @@ impl InterruptDescriptorTable {
         self.0[entry].set_interrupt_stack_table(0);
     }
 
+    pub fn register_exception(&mut self, entry: ExceptionEntry) {
+        self.register_handler(entry.vector as usize, entry.handler);
+    }
+
+    pub fn set_interrupt_stack(&mut self, entry: usize, ist: u8) {
+        self.0[entry].set_interrupt_stack_table(ist);
+    }
+
+    pub fn set_privilege_level(&mut self, entry: usize, privilege_level: PrivilegeLevel) {
+        self.0[entry].attributes = (self.0[entry].attributes & 0x9F) | (privilege_level as u8) << 0x5;
+    }
+
     pub fn set_presentation(&mut self, entry: u8, value: bool) {
         self.0[entry as usize].attributes = (self.0[entry as usize].attributes & 0x7F) | (value as u8) << 0x7;
     }
@@ -154,29 +166,42 @@ lazy_static! {
     pub static ref IDT: idt::InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
 
-        idt.register_handler(0, interrupt_error!(divide_by_zero));
-        idt.register_handler(1, interrupt_error!(debug));
-        idt.register_handler(2, interrupt_error!(non_maskable_interrupt));
-        idt.register_handler(3, interrupt_error!(breakpoint));
-        idt.register_handler(4, interrupt_error!(overflow));
-        idt.register_handler(5, interrupt_error!(bound_range_exceeded));
-        idt.register_handler(6, interrupt_error!(invalid_opcode));
-        idt.register_handler(7, interrupt_error!(device_not_available));
-        idt.register_handler(8, interrupt_error_with_code!(double_fault));
+        idt.register_exception(exception_entry!(ExceptionVector::DIVIDE_ERROR, divide_by_zero));
+        idt.register_exception(exception_entry!(ExceptionVector::DEBUG, debug));
+        idt.register_exception(exception_entry!(ExceptionVector::NON_MASKABLE_INTERRUPT, non_maskable_interrupt));
+        idt.set_interrupt_stack(2, gdt::NON_MASKABLE_INTERRUPT_IST_INDEX);
+        idt.register_exception(exception_entry!(ExceptionVector::BREAKPOINT, breakpoint));
+        idt.register_exception(exception_entry!(ExceptionVector::OVERFLOW, overflow));
+        idt.register_exception(exception_entry!(ExceptionVector::BOUND_RANGE_EXCEEDED, bound_range_exceeded));
+        idt.register_exception(exception_entry!(ExceptionVector::INVALID_OPCODE, invalid_opcode));
+        idt.register_exception(exception_entry!(ExceptionVector::DEVICE_NOT_AVAILABLE, device_not_available));
+        idt.register_exception(exception_entry!(ExceptionVector::DOUBLE_FAULT, double_fault));
+        idt.set_interrupt_stack(8, gdt::DOUBLE_FAULT_IST_INDEX);
         // 9 Coprocessor Segment Overrun, not available anymore
-        idt.register_handler(10, interrupt_error_with_code!(invalid_tss));
-        idt.register_handler(11, interrupt_error_with_code!(segment_not_present));
-        idt.register_handler(12, interrupt_error_with_code!(stack_segment_fault));
-        idt.register_handler(13, interrupt_error_with_code!(general_protection_fault));
-        idt.register_handler(14, interrupt_error_with_code!(page_fault));
+        idt.register_exception(exception_entry!(ExceptionVector::INVALID_TSS, invalid_tss));
+        idt.register_exception(exception_entry!(ExceptionVector::SEGMENT_NOT_PRESENT, segment_not_present));
+        idt.register_exception(exception_entry!(ExceptionVector::STACK_SEGMENT_FAULT, stack_segment_fault));
+        idt.register_exception(exception_entry!(ExceptionVector::GENERAL_PROTECTION_FAULT, general_protection_fault));
+        idt.register_exception(exception_entry!(ExceptionVector::PAGE_FAULT, page_fault));
         // 15 reserved
-        idt.register_handler(16, interrupt_error!(x87_floating_point_exception));
-        idt.register_handler(17, interrupt_error_with_code!(alignment_check));
-        //idt.register_handler(18, interrupt_error!());
-        idt.register_handler(19, interrupt_error!(simd_floating_point_exception));
-        idt.register_handler(20, interrupt_error!(virtualization_exception));
-        // [21..29] reserved
-        idt.register_handler(30, interrupt_error_with_code!(security_exception));
+        idt.register_exception(exception_entry!(ExceptionVector::X87_FLOATING_POINT, x87_floating_point_exception));
+        idt.register_exception(exception_entry!(ExceptionVector::ALIGNMENT_CHECK, alignment_check));
+        idt.register_exception(exception_entry!(ExceptionVector::MACHINE_CHECK, machine_check));
+        idt.set_interrupt_stack(18, gdt::MACHINE_CHECK_IST_INDEX);
+        idt.register_exception(exception_entry!(ExceptionVector::SIMD_FLOATING_POINT, simd_floating_point_exception));
+        idt.register_exception(exception_entry!(ExceptionVector::VIRTUALIZATION, virtualization_exception));
+        idt.register_exception(exception_entry!(ExceptionVector::CONTROL_PROTECTION, control_protection_exception));
+        // [22..27] reserved
+        idt.register_exception(exception_entry!(ExceptionVector::HYPERVISOR_INJECTION, hypervisor_injection_exception));
+        idt.register_exception(exception_entry!(ExceptionVector::VMM_COMMUNICATION, vmm_communication_exception));
+        idt.register_exception(exception_entry!(ExceptionVector::SECURITY, security_exception));
+        // 31 reserved
+
+        irq::register_entries(&mut idt);
+
+        //the only gate user space may trigger with `int`
+        idt.register_handler(SYSCALL_VECTOR, interrupt_error!(syscall_interrupt));
+        idt.set_privilege_level(SYSCALL_VECTOR, PrivilegeLevel::Ring3);
 
         idt
     };
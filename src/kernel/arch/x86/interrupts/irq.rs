@@ -0,0 +1,150 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::interrupt_error;
+use crate::kernel::arch::x86::interrupts::apic;
+use crate::kernel::arch::x86::interrupts::idt::InterruptDescriptorTable;
+use crate::kernel::arch::x86::interrupts::pic::{self, PICS, PIC_1_OFFSET, IRQ_COUNT};
+use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::task;
+
+/// First vector that is not reserved for CPU exceptions.
+pub const IRQ_BASE: u8 = PIC_1_OFFSET;
+pub const VECTOR_COUNT: usize = 256 - IRQ_BASE as usize;
+
+pub type IrqHandler = fn(&StackFrame);
+
+//function pointers stored as usize so that handlers can be (un)registered while interrupts are
+//enabled, without a lock the interrupted code could be holding. 0 means no handler
+static HANDLERS: [AtomicUsize; VECTOR_COUNT] = [const { AtomicUsize::new(0) }; VECTOR_COUNT];
+
+/// Installs `handler` for `vector`, replacing the previous one.
+pub fn register_vector(vector: u8, handler: IrqHandler) {
+    assert!(vector >= IRQ_BASE, "vector {} is reserved for CPU exceptions", vector);
+    HANDLERS[(vector - IRQ_BASE) as usize].store(handler as usize, Ordering::Release);
+}
+
+pub fn unregister_vector(vector: u8) {
+    assert!(vector >= IRQ_BASE, "vector {} is reserved for CPU exceptions", vector);
+    HANDLERS[(vector - IRQ_BASE) as usize].store(0, Ordering::Release);
+}
+
+/// Installs `handler` for the legacy ISA line `irq` and unmasks it on whichever controller is active.
+pub fn register_irq(irq: u8, handler: IrqHandler) {
+    register_vector(IRQ_BASE + irq, handler);
+
+    if apic::is_enabled() {
+        apic::route_isa_irq(irq, false);
+    } else {
+        pic::unmask(irq);
+    }
+}
+
+pub fn unregister_irq(irq: u8) {
+    if apic::is_enabled() {
+        apic::mask_isa_irq(irq);
+    } else {
+        pic::mask(irq);
+    }
+
+    unregister_vector(IRQ_BASE + irq);
+}
+
+pub fn has_handler(vector: u8) -> bool {
+    handler(vector).is_some()
+}
+
+fn handler(vector: u8) -> Option<IrqHandler> {
+    match HANDLERS[(vector - IRQ_BASE) as usize].load(Ordering::Acquire) {
+        0 => None,
+        address => Some(unsafe { core::mem::transmute::<usize, IrqHandler>(address) }),
+    }
+}
+
+/// Common entry point of every hardware interrupt vector.
+pub fn dispatch(vector: u8, stack_frame: &StackFrame) {
+    //spurious interrupts of the local APIC must not be acknowledged
+    if vector == apic::SPURIOUS_VECTOR {
+        return;
+    }
+
+    if apic::is_enabled() {
+        if let Some(handler) = handler(vector) {
+            handler(stack_frame);
+        }
+
+        apic::end_of_interrupt();
+    } else {
+        //the PICs are only touched for the vectors they own
+        let legacy_irq = if vector < IRQ_BASE + IRQ_COUNT { Some(vector - IRQ_BASE) } else { None };
+
+        if let Some(irq) = legacy_irq {
+            if unsafe { PICS.lock().is_spurious(irq) } {
+                return;
+            }
+        }
+
+        if let Some(handler) = handler(vector) {
+            handler(stack_frame);
+        }
+
+        if let Some(irq) = legacy_irq {
+            unsafe { PICS.lock().end_of_interrupt(irq) };
+        }
+    }
+
+    //only switch threads once the interrupt is acknowledged, the next thread may run for a while
+    task::preempt();
+}
+
+macro_rules! irq_entries {
+    ($($vector: literal => $name: ident),* $(,)?) => {
+        $(
+            extern "C" fn $name(stack_frame: &StackFrame) {
+                dispatch($vector, stack_frame);
+            }
+        )*
+
+        /// Points the hardware interrupt vectors of `idt` at `dispatch`.
+        pub fn register_entries(idt: &mut InterruptDescriptorTable) {
+            $(idt.register_handler($vector, interrupt_error!($name));)*
+        }
+    };
+}
+
+irq_entries! {
+    32 => irq_0,
+    33 => irq_1,
+    34 => irq_2,
+    35 => irq_3,
+    36 => irq_4,
+    37 => irq_5,
+    38 => irq_6,
+    39 => irq_7,
+    40 => irq_8,
+    41 => irq_9,
+    42 => irq_10,
+    43 => irq_11,
+    44 => irq_12,
+    45 => irq_13,
+    46 => irq_14,
+    47 => irq_15,
+    48 => local_apic_timer,
+    254 => local_apic_error,
+    255 => local_apic_spurious,
+}
+
+#[test_case]
+fn registered_handler_is_dispatched() {
+    use core::sync::atomic::AtomicBool;
+    use x86_64::instructions::interrupts;
+
+    static CALLED: AtomicBool = AtomicBool::new(false);
+
+    //IRQ 3 (COM2) is never raised by QEMU with our configuration, so dispatch it by hand. The end of
+    //interrupt is a no-op while nothing is in service, which interrupts being disabled guarantees
+    register_vector(IRQ_BASE + 3, |_| CALLED.store(true, Ordering::SeqCst));
+    interrupts::without_interrupts(|| dispatch(IRQ_BASE + 3, &StackFrame::default()));
+    unregister_vector(IRQ_BASE + 3);
+
+    assert!(CALLED.load(Ordering::SeqCst));
+    assert!(!has_handler(IRQ_BASE + 3));
+}
@@ -6,4 +6,8 @@ use crate::println;
 
 pub mod idt;
 pub mod exception;
-mod page_fault;
+pub mod pic;
+pub mod irq;
+pub mod apic;
+pub mod page_fault;
+pub mod emulate;
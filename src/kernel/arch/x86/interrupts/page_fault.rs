@@ -1,41 +1,73 @@
 use core::arch::asm;
+use core::fmt;
+use modular_bitfield::prelude::*;
 use x86_64::structures::paging::Page;
-use crate::enum_str;
-use crate::kernel::arch::x86::interrupts::exception::page_fault;
-
-enum_str! {
-    enum AccessMode {
-        Supervisor = 0x0,
-        User = 0x1,
-        Unknown = 0x2,
-    }
+use x86_64::VirtAddr;
+
+/// The error code the processor pushes for a page fault, see the Intel SDM Vol. 3A, 4.7.
+#[bitfield(bits = 64)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageFaultErrorCode {
+    /// P: the page was present, the fault is a protection violation.
+    pub present: bool,
+    /// W/R: the access was a write.
+    pub write: bool,
+    /// U/S: the access came from user mode.
+    pub user: bool,
+    /// RSVD: a reserved bit was set in a paging structure entry.
+    pub reserved_bit: bool,
+    /// I/D: the access was an instruction fetch.
+    pub instruction_fetch: bool,
+    /// PK: the protection key rights of the page disallowed the access.
+    pub protection_key: bool,
+    /// SS: the access was a shadow stack access.
+    pub shadow_stack: bool,
+    /// HLAT: the fault happened during HLAT paging.
+    pub hlat: bool,
+    #[skip] __: B7,
+    /// SGX: the access violated SGX specific access control.
+    pub sgx: bool,
+    #[skip] __: B48,
 }
 
-enum_str! {
-    enum PageFaultErrorCode {
-        NonPresentRead = 0x0,
-        ProtectionViolationRead = 0x1,
-        NonPresentWrite = 0x2,
-        ProtectionViolationWrite = 0x3,
-        Unknown = 0x4,
+impl PageFaultErrorCode {
+    pub fn from_code(code: usize) -> PageFaultErrorCode {
+        PageFaultErrorCode::from_bytes((code as u64).to_le_bytes())
     }
 }
 
-pub struct PageFaultBitMasks;
+impl fmt::Display for PageFaultErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cause = if self.present() { "protection violation" } else { "non-present page" };
+        let access = match (self.instruction_fetch(), self.write()) {
+            (true, _) => "instruction fetch",
+            (false, true) => "write",
+            (false, false) => "read",
+        };
+        let mode = if self.user() { "user" } else { "supervisor" };
+
+        write!(f, "{} on {} in {} mode", cause, access, mode)?;
+
+        let details = [
+            (self.reserved_bit(), "reserved bit set"),
+            (self.protection_key(), "protection key"),
+            (self.shadow_stack(), "shadow stack"),
+            (self.hlat(), "HLAT paging"),
+            (self.sgx(), "SGX"),
+        ];
 
-impl PageFaultBitMasks {
-    pub const ERROR_CODE: usize = 0x3;
-    pub const ACCES_MODE: usize = 0x4;
-    pub const INSTRUCTION_FETCH: usize = 0x10;
-    pub const RESERVED: usize = 0x9;
+        for (_, name) in details.iter().filter(|(set, _)| *set) {
+            write!(f, ", {}", name)?;
+        }
+
+        Ok(())
+    }
 }
 
 pub struct PageFault {
     pub addr: usize,
-    pub error_code_description: PageFaultErrorCode,
-    pub access_mode: AccessMode,
-    pub caused_by_instruction_fetch: bool,
-    pub reserved: bool,
+    pub page: Page,
+    pub error_code: PageFaultErrorCode,
 }
 
 impl PageFault {
@@ -59,26 +91,35 @@ pub struct PageFaultBuilder;
 
 impl PageFaultBuilder {
     pub fn build(code: usize) -> PageFault {
-        let page_fault_error = match code & PageFaultBitMasks::ERROR_CODE {
-            0x0 =>  PageFaultErrorCode::NonPresentRead,
-            0x1 =>  PageFaultErrorCode::ProtectionViolationRead,
-            0x2 =>  PageFaultErrorCode::NonPresentWrite,
-            0x3 =>  PageFaultErrorCode::ProtectionViolationWrite,
-            _ =>    PageFaultErrorCode::Unknown
-        };
-
-        let access_mode = match code & PageFaultBitMasks::ACCES_MODE {
-            0x0 => AccessMode::Supervisor,
-            0x1 => AccessMode::User,
-            _ => AccessMode::Unknown
-        };
+        let addr = PageFault::get_addr();
 
         PageFault {
-            error_code_description: page_fault_error,
-            access_mode,
-            caused_by_instruction_fetch: (code & PageFaultBitMasks::INSTRUCTION_FETCH) != 0,
-            reserved: (code & PageFaultBitMasks::RESERVED) != 0,
-            addr: PageFault::get_addr()
+            page: Page::containing_address(VirtAddr::new(addr as u64)),
+            error_code: PageFaultErrorCode::from_code(code),
+            addr
         }
     }
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn user_and_reserved_bits_are_decoded_separately() {
+    let user_write = PageFaultErrorCode::from_code(0x6);
+    assert!(user_write.user() && user_write.write() && !user_write.present());
+    assert!(!user_write.reserved_bit());
+
+    let reserved = PageFaultErrorCode::from_code(0x9);
+    assert!(reserved.present() && reserved.reserved_bit() && !reserved.user());
+
+    let sgx = PageFaultErrorCode::from_code(0x8000 | 0x20);
+    assert!(sgx.sgx() && sgx.protection_key() && !sgx.shadow_stack());
+}
+
+#[test_case]
+fn error_code_is_displayed() {
+    use alloc::string::ToString;
+
+    assert_eq!(PageFaultErrorCode::from_code(0x7).to_string(), "protection violation on write in user mode");
+    assert_eq!(PageFaultErrorCode::from_code(0x10).to_string(), "non-present page on instruction fetch in supervisor mode");
+    assert_eq!(PageFaultErrorCode::from_code(0x14).to_string(), "non-present page on instruction fetch in user mode");
+    assert_eq!(PageFaultErrorCode::from_code(0x49).to_string(), "protection violation on read in supervisor mode, reserved bit set, shadow stack");
+}
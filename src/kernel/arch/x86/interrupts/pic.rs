@@ -0,0 +1,163 @@
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+pub const PIC_1_OFFSET: u8 = 32;
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+pub const IRQ_COUNT: u8 = 16;
+
+//the slave is wired to IRQ 2 of the master
+const CASCADE_IRQ: u8 = 2;
+
+pub struct PicCommands;
+
+impl PicCommands {
+    pub const INIT: u8 = 0x11;
+    pub const END_OF_INTERRUPT: u8 = 0x20;
+    pub const READ_IN_SERVICE_REGISTER: u8 = 0x0B;
+    pub const MODE_8086: u8 = 0x01;
+}
+
+pub struct Pic {
+    offset: u8,
+    command: Port<u8>,
+    data: Port<u8>,
+}
+
+impl Pic {
+    const fn new(offset: u8, command: u16, data: u16) -> Pic {
+        Pic {
+            offset,
+            command: Port::new(command),
+            data: Port::new(data),
+        }
+    }
+
+    unsafe fn end_of_interrupt(&mut self) {
+        self.command.write(PicCommands::END_OF_INTERRUPT);
+    }
+
+    unsafe fn in_service(&mut self) -> u8 {
+        self.command.write(PicCommands::READ_IN_SERVICE_REGISTER);
+        self.command.read()
+    }
+
+    unsafe fn read_mask(&mut self) -> u8 {
+        self.data.read()
+    }
+
+    unsafe fn write_mask(&mut self, mask: u8) {
+        self.data.write(mask)
+    }
+}
+
+/// The master/slave pair of 8259 PICs found in every PC.
+pub struct ChainedPics {
+    master: Pic,
+    slave: Pic,
+}
+
+impl ChainedPics {
+    pub const fn new(master_offset: u8, slave_offset: u8) -> ChainedPics {
+        ChainedPics {
+            master: Pic::new(master_offset, 0x20, 0x21),
+            slave: Pic::new(slave_offset, 0xA0, 0xA1),
+        }
+    }
+
+    /// Remaps both PICs to their offsets and masks every line except the cascade.
+    /// The BIOS maps the master onto vectors 8-15, which collide with the CPU exceptions.
+    pub unsafe fn init(&mut self) {
+        //writing to an unused port gives the old PICs time to process each command
+        let mut wait_port: Port<u8> = Port::new(0x80);
+        let mut io_wait = || wait_port.write(0);
+
+        self.master.command.write(PicCommands::INIT);
+        io_wait();
+        self.slave.command.write(PicCommands::INIT);
+        io_wait();
+
+        self.master.data.write(self.master.offset);
+        io_wait();
+        self.slave.data.write(self.slave.offset);
+        io_wait();
+
+        //tell the master where the slave is, and the slave its cascade identity
+        self.master.data.write(1 << CASCADE_IRQ);
+        io_wait();
+        self.slave.data.write(CASCADE_IRQ);
+        io_wait();
+
+        self.master.data.write(PicCommands::MODE_8086);
+        io_wait();
+        self.slave.data.write(PicCommands::MODE_8086);
+        io_wait();
+
+        self.master.write_mask(!(1 << CASCADE_IRQ));
+        self.slave.write_mask(0xFF);
+    }
+
+    pub unsafe fn mask(&mut self, irq: u8) {
+        if irq < 8 {
+            let mask = self.master.read_mask();
+            self.master.write_mask(mask | (1 << irq));
+        } else {
+            let mask = self.slave.read_mask();
+            self.slave.write_mask(mask | (1 << (irq - 8)));
+        }
+    }
+
+    pub unsafe fn unmask(&mut self, irq: u8) {
+        if irq < 8 {
+            let mask = self.master.read_mask();
+            self.master.write_mask(mask & !(1 << irq));
+        } else {
+            let mask = self.slave.read_mask();
+            self.slave.write_mask(mask & !(1 << (irq - 8)));
+        }
+    }
+
+    /// Masks all 16 lines, e.g. before switching over to the APIC.
+    pub unsafe fn disable(&mut self) {
+        self.master.write_mask(0xFF);
+        self.slave.write_mask(0xFF);
+    }
+
+    /// IRQ 7 and 15 fire spuriously when a line is deasserted before the CPU acknowledged it.
+    /// In that case the in-service bit is clear and the spurious IRQ must not be acknowledged,
+    /// except that the master still needs an EOI for the cascade of a spurious IRQ 15.
+    pub unsafe fn is_spurious(&mut self, irq: u8) -> bool {
+        match irq {
+            7 => self.master.in_service() & (1 << 7) == 0,
+            15 => {
+                let spurious = self.slave.in_service() & (1 << 7) == 0;
+                if spurious {
+                    self.master.end_of_interrupt();
+                }
+                spurious
+            }
+            _ => false,
+        }
+    }
+
+    pub unsafe fn end_of_interrupt(&mut self, irq: u8) {
+        if irq >= 8 {
+            self.slave.end_of_interrupt();
+        }
+        self.master.end_of_interrupt();
+    }
+}
+
+pub static PICS: Mutex<ChainedPics> = Mutex::new(ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET));
+
+pub fn init() {
+    interrupts::without_interrupts(|| unsafe { PICS.lock().init() });
+}
+
+pub fn mask(irq: u8) {
+    interrupts::without_interrupts(|| unsafe { PICS.lock().mask(irq) });
+}
+
+pub fn unmask(irq: u8) {
+    interrupts::without_interrupts(|| unsafe { PICS.lock().unmask(irq) });
+}
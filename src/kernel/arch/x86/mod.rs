@@ -1,4 +1,19 @@
 pub mod vga;
-mod serial;
+pub mod gdt;
+pub mod serial;
 pub mod interrupts;
-mod registers;
+pub mod registers;
+pub mod qemu;
+pub mod acpi;
+pub mod pit;
+pub mod i8042;
+pub mod context;
+pub mod syscall;
+pub mod backtrace;
+pub mod crash;
+
+pub fn hlt_loop() -> ! {
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
@@ -0,0 +1,132 @@
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::{Port, PortWriteOnly};
+use crate::kernel::arch::x86::interrupts::irq;
+use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::{task, time};
+
+/// Frequency of the oscillator that drives all three PIT channels.
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+//legacy ISA interrupt line of channel 0
+const PIT_IRQ: u8 = 0;
+
+pub struct PitCommands;
+
+impl PitCommands {
+    pub const CHANNEL_0: u8 = 0b00 << 6;
+    pub const CHANNEL_2: u8 = 0b10 << 6;
+    pub const LATCH_COUNT: u8 = 0b00 << 4;
+    pub const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+    pub const MODE_INTERRUPT_ON_TERMINAL_COUNT: u8 = 0b000 << 1;
+    pub const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+}
+
+pub struct SpeakerMasks;
+
+impl SpeakerMasks {
+    //bits of port 0x61
+    pub const CHANNEL_2_GATE: u8 = 0x1;
+    pub const SPEAKER_ENABLE: u8 = 0x2;
+    pub const CHANNEL_2_OUTPUT: u8 = 0x20;
+}
+
+pub struct Pit {
+    channel_0: Port<u8>,
+    channel_2: Port<u8>,
+    command: PortWriteOnly<u8>,
+    speaker: Port<u8>,
+}
+
+impl Pit {
+    pub const fn new() -> Pit {
+        Pit {
+            channel_0: Port::new(0x40),
+            channel_2: Port::new(0x42),
+            command: PortWriteOnly::new(0x43),
+            speaker: Port::new(0x61),
+        }
+    }
+
+    /// Divisor for `frequency`, a written 0 stands for 65536, the slowest rate of ~18.2 Hz.
+    pub fn divisor(frequency: u32) -> u16 {
+        let divisor = BASE_FREQUENCY / frequency.max(1);
+
+        match divisor {
+            0..=1 => 1,
+            2..=0xFFFF => divisor as u16,
+            _ => 0,
+        }
+    }
+
+    /// Makes channel 0 fire IRQ 0 periodically at (roughly) `frequency` Hz and returns the exact rate.
+    pub fn set_frequency(&mut self, frequency: u32) -> u32 {
+        let divisor = Self::divisor(frequency);
+
+        unsafe {
+            self.command.write(PitCommands::CHANNEL_0 | PitCommands::ACCESS_LOW_HIGH | PitCommands::MODE_RATE_GENERATOR);
+            self.channel_0.write(divisor as u8);
+            self.channel_0.write((divisor >> 8) as u8);
+        }
+
+        match divisor {
+            0 => BASE_FREQUENCY / 0x10000,
+            divisor => BASE_FREQUENCY / divisor as u32,
+        }
+    }
+
+    pub fn read_count(&mut self) -> u16 {
+        unsafe {
+            self.command.write(PitCommands::CHANNEL_0 | PitCommands::LATCH_COUNT);
+            let low = self.channel_0.read() as u16;
+            let high = self.channel_0.read() as u16;
+            high << 8 | low
+        }
+    }
+
+    /// Spins for `microseconds` using channel 2, which works with interrupts disabled.
+    /// Channel 2 can count at most ~54 ms at a time, so longer waits are split up.
+    pub fn busy_wait_microseconds(&mut self, microseconds: u64) {
+        let mut remaining = microseconds * BASE_FREQUENCY as u64 / 1_000_000;
+
+        while remaining > 0 {
+            let count = remaining.min(0xFFFF) as u16;
+            remaining -= count as u64;
+
+            unsafe {
+                //gate off while loading, speaker off so nobody hears it
+                let speaker = self.speaker.read() & !(SpeakerMasks::CHANNEL_2_GATE | SpeakerMasks::SPEAKER_ENABLE);
+                self.speaker.write(speaker);
+
+                self.command.write(PitCommands::CHANNEL_2 | PitCommands::ACCESS_LOW_HIGH | PitCommands::MODE_INTERRUPT_ON_TERMINAL_COUNT);
+                self.channel_2.write(count as u8);
+                self.channel_2.write((count >> 8) as u8);
+
+                //the output goes high once the count reaches zero
+                self.speaker.write(speaker | SpeakerMasks::CHANNEL_2_GATE);
+                while self.speaker.read() & SpeakerMasks::CHANNEL_2_OUTPUT == 0 {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+    }
+}
+
+pub static PIT: Mutex<Pit> = Mutex::new(Pit::new());
+
+fn timer_interrupt(_stack_frame: &StackFrame) {
+    time::tick();
+    task::timer_tick();
+}
+
+/// Programs channel 0 to `frequency` Hz and drives the kernel tick clock from IRQ 0.
+pub fn init(frequency: u32) {
+    let frequency = interrupts::without_interrupts(|| PIT.lock().set_frequency(frequency));
+
+    time::set_tick_frequency(frequency);
+    irq::register_irq(PIT_IRQ, timer_interrupt);
+}
+
+pub fn busy_wait_microseconds(microseconds: u64) {
+    interrupts::without_interrupts(|| PIT.lock().busy_wait_microseconds(microseconds));
+}
@@ -0,0 +1,20 @@
+use x86_64::instructions::port::Port;
+
+//must match the iobase of the isa-debug-exit device in Cargo.toml
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Values written to the isa-debug-exit device, QEMU exits with `(value << 1) | 1`.
+/// `Success` therefore ends up as exit code 33, the `test-success-exit-code` bootimage expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(exit_code: QemuExitCode) {
+    unsafe {
+        let mut port = Port::new(ISA_DEBUG_EXIT_PORT);
+        port.write(exit_code as u32);
+    }
+}
@@ -1,6 +1,8 @@
+use core::fmt;
 use crate::{print, println};
+use crate::kernel::arch::x86::backtrace::{self, StackWalker};
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 #[repr(packed)]
 pub struct ScratchRegisters {
     pub r11: usize,
@@ -28,7 +30,7 @@ impl ScratchRegisters {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 #[repr(packed)]
 pub struct PreservedRegisters {
     pub r15: usize,
@@ -50,7 +52,80 @@ impl PreservedRegisters {
     }
 }
 
-#[derive(Default)]
+//the flags that have a name, IOPL (bits 12 and 13) is printed as a number
+const RFLAGS_NAMES: [(usize, &str); 16] = [
+    (0, "CF"), (2, "PF"), (4, "AF"), (6, "ZF"), (7, "SF"), (8, "TF"), (9, "IF"), (10, "DF"),
+    (11, "OF"), (14, "NT"), (16, "RF"), (17, "VM"), (18, "AC"), (19, "VIF"), (20, "VIP"), (21, "ID"),
+];
+
+/// A saved RFLAGS value, displayed with the flags that are set, e.g. `0x246 [PF ZF IF IOPL=0]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rflags(pub usize);
+
+impl Rflags {
+    pub fn is_set(&self, bit: usize) -> bool {
+        self.0 & (1 << bit) != 0
+    }
+
+    /// The I/O privilege level, the least privileged ring that may use `in`, `out`, `cli` and `sti`.
+    pub fn io_privilege_level(&self) -> usize {
+        (self.0 >> 12) & 0x3
+    }
+}
+
+impl fmt::Display for Rflags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x} [", self.0)?;
+
+        for (bit, name) in RFLAGS_NAMES {
+            if self.is_set(bit) {
+                write!(f, "{} ", name)?;
+            }
+        }
+
+        write!(f, "IOPL={}]", self.io_privilege_level())
+    }
+}
+
+/// A saved segment selector, displayed decoded, e.g. `0x23 (index 4, GDT, RPL 3, user)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selector(pub usize);
+
+impl Selector {
+    /// The descriptor's index in its table.
+    pub fn index(&self) -> usize {
+        (self.0 >> 3) & 0x1fff
+    }
+
+    /// TI: the descriptor is in the LDT rather than the GDT.
+    pub fn is_local(&self) -> bool {
+        self.0 & 0x4 != 0
+    }
+
+    /// RPL, for the CS and SS of an interrupt frame the privilege level the interrupted code ran at.
+    pub fn privilege_level(&self) -> usize {
+        self.0 & 0x3
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.privilege_level() == 3
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let table = if self.is_local() { "LDT" } else { "GDT" };
+        let mode = match self.privilege_level() {
+            0 => "kernel",
+            3 => "user",
+            _ => "unused ring",
+        };
+
+        write!(f, "{:#x} (index {}, {}, RPL {}, {})", self.0, self.index(), table, self.privilege_level(), mode)
+    }
+}
+
+#[derive(Default, Clone, Copy)]
 #[repr(packed)]
 pub struct IretRegisters {
     pub rip: usize,
@@ -61,16 +136,33 @@ pub struct IretRegisters {
 }
 
 impl IretRegisters {
+    pub fn rflags(&self) -> Rflags {
+        Rflags(self.rflags)
+    }
+
+    pub fn code_segment(&self) -> Selector {
+        Selector(self.cs)
+    }
+
+    pub fn stack_segment(&self) -> Selector {
+        Selector(self.ss)
+    }
+
+    /// Whether the interrupted code ran in ring 3, going by the privilege level of its code segment.
+    pub fn is_user_mode(&self) -> bool {
+        self.code_segment().is_user()
+    }
+
     pub fn dump(&self) {
-        println!("RFLAG = 0x{:016x} ", { self.rflags });
-        println!("CS    = 0x{:016x} ", { self.cs });
+        println!("RFLAG = {} ", self.rflags());
+        println!("CS    = {} ", self.code_segment());
         println!("RIP   = 0x{:016x} ", { self.rip });
         println!("RSP   = 0x{:016x} ", { self.rsp });
-        println!("SS    = 0x{:016x} ", { self.ss });
+        println!("SS    = {} ", self.stack_segment());
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy)]
 #[repr(packed)]
 pub struct StackFrame {
     pub preserved: PreservedRegisters,
@@ -79,9 +171,62 @@ pub struct StackFrame {
 }
 
 impl StackFrame {
+    /// Sets a general purpose register by the number instructions encode it with: `rax`, `rcx`,
+    /// `rdx`, `rbx`, `rsp`, `rbp`, `rsi`, `rdi`, then `r8` to `r15`. Returns false for any other number.
+    pub fn set_register(&mut self, number: u8, value: usize) -> bool {
+        match number {
+            0 => self.scratch.rax = value,
+            1 => self.scratch.rcx = value,
+            2 => self.scratch.rdx = value,
+            3 => self.preserved.rbx = value,
+            4 => self.iret.rsp = value,
+            5 => self.preserved.rbp = value,
+            6 => self.scratch.rsi = value,
+            7 => self.scratch.rdi = value,
+            8 => self.scratch.r8 = value,
+            9 => self.scratch.r9 = value,
+            10 => self.scratch.r10 = value,
+            11 => self.scratch.r11 = value,
+            12 => self.preserved.r12 = value,
+            13 => self.preserved.r13 = value,
+            14 => self.preserved.r14 = value,
+            15 => self.preserved.r15 = value,
+            _ => return false,
+        }
+
+        true
+    }
+
     pub fn dump(&self) {
         self.scratch.dump();
         self.preserved.dump();
         self.iret.dump();
+        backtrace::print(StackWalker::new(self.iret.rip as u64, self.preserved.rbp as u64));
     }
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn rflags_are_decoded() {
+    use alloc::string::ToString;
+
+    assert_eq!(Rflags(0x246).to_string(), "0x246 [PF ZF IF IOPL=0]");
+    assert_eq!(Rflags(0x3202).to_string(), "0x3202 [IF IOPL=3]");
+}
+
+#[test_case]
+fn registers_are_set_by_number() {
+    let mut frame = StackFrame::default();
+
+    assert!(frame.set_register(4, 0x1000) && frame.set_register(9, 7));
+    assert_eq!(({ frame.iret.rsp }, { frame.scratch.r9 }), (0x1000, 7));
+    assert!(!frame.set_register(16, 0));
+}
+
+#[test_case]
+fn selectors_are_decoded() {
+    use alloc::string::ToString;
+
+    assert_eq!(Selector(0x8).to_string(), "0x8 (index 1, GDT, RPL 0, kernel)");
+    assert_eq!(Selector(0x23).to_string(), "0x23 (index 4, GDT, RPL 3, user)");
+    assert!(Selector(0x1f).is_local());
+}
@@ -0,0 +1,300 @@
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use lazy_static::lazy_static;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+use crate::kernel::lib::ring_buffer::RingBuffer;
+use crate::kernel::lib::waker_cell::WakerCell;
+use crate::kernel::sync::SpinLock;
+
+pub const COM1: u16 = 0x3F8;
+
+const RECEIVE_BUFFER_SIZE: usize = 256;
+
+//bytes received by the interrupt handler that nobody has read yet
+static RECEIVE_BUFFER: RingBuffer<u8, RECEIVE_BUFFER_SIZE> = RingBuffer::new();
+static RECEIVE_WAKER: WakerCell = WakerCell::new();
+
+/// Baud rates expressed as the divisor of the 115200 Hz UART base clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum BaudRate {
+    B115200 = 1,
+    B57600 = 2,
+    B38400 = 3,
+    B19200 = 6,
+    B9600 = 12,
+    B4800 = 24,
+    B2400 = 48,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum DataBits {
+    Five = 0b00,
+    Six = 0b01,
+    Seven = 0b10,
+    Eight = 0b11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StopBits {
+    One = 0b000,
+    Two = 0b100,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Parity {
+    None = 0b000_000,
+    Odd = 0b001_000,
+    Even = 0b011_000,
+    Mark = 0b101_000,
+    Space = 0b111_000,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    LoopbackFailed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineConfig {
+    pub baud_rate: BaudRate,
+    pub data_bits: DataBits,
+    pub stop_bits: StopBits,
+    pub parity: Parity,
+}
+
+impl LineConfig {
+    //38400 8N1
+    pub const fn new() -> LineConfig {
+        LineConfig {
+            baud_rate: BaudRate::B38400,
+            data_bits: DataBits::Eight,
+            stop_bits: StopBits::One,
+            parity: Parity::None,
+        }
+    }
+
+    fn line_control(&self) -> u8 {
+        self.data_bits as u8 | self.stop_bits as u8 | self.parity as u8
+    }
+}
+
+pub struct LineControlMasks;
+
+impl LineControlMasks {
+    pub const DIVISOR_LATCH_ACCESS: u8 = 0x80;
+}
+
+pub struct LineStatusMasks;
+
+impl LineStatusMasks {
+    pub const DATA_READY: u8 = 0x1;
+    pub const OVERRUN_ERROR: u8 = 0x2;
+    pub const TRANSMITTER_EMPTY: u8 = 0x20;
+}
+
+pub struct InterruptEnableMasks;
+
+impl InterruptEnableMasks {
+    pub const DATA_AVAILABLE: u8 = 0x1;
+}
+
+pub struct ModemControlMasks;
+
+impl ModemControlMasks {
+    pub const DATA_TERMINAL_READY: u8 = 0x1;
+    pub const REQUEST_TO_SEND: u8 = 0x2;
+    //OUT2 gates the UART interrupt line on PC compatibles
+    pub const OUT2: u8 = 0x8;
+    pub const LOOPBACK: u8 = 0x10;
+}
+
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: PortWriteOnly<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: PortReadOnly<u8>,
+    config: LineConfig,
+}
+
+impl SerialPort {
+    /// Creates a handle for the UART at `base`. Nothing is written to the device until `init`.
+    pub const fn new(base: u16) -> SerialPort {
+        SerialPort {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: PortWriteOnly::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: PortReadOnly::new(base + 5),
+            config: LineConfig::new(),
+        }
+    }
+
+    pub fn init(&mut self, config: LineConfig) -> Result<(), SerialError> {
+        unsafe {
+            self.interrupt_enable.write(0x00);
+            self.set_line_config(config);
+
+            //enable the fifo, clear both queues and interrupt once 14 bytes are queued
+            self.fifo_control.write(0xC7);
+
+            //route a test byte back to ourselves to check that the chip is actually there
+            self.modem_control.write(ModemControlMasks::REQUEST_TO_SEND | ModemControlMasks::OUT2 | ModemControlMasks::LOOPBACK);
+            self.data.write(0xAE);
+
+            if self.data.read() != 0xAE {
+                return Err(SerialError::LoopbackFailed);
+            }
+
+            self.modem_control.write(ModemControlMasks::DATA_TERMINAL_READY | ModemControlMasks::REQUEST_TO_SEND | ModemControlMasks::OUT2);
+        }
+
+        Ok(())
+    }
+
+    pub fn set_line_config(&mut self, config: LineConfig) {
+        let divisor = config.baud_rate as u16;
+
+        unsafe {
+            //while DLAB is set the data and interrupt enable registers hold the divisor
+            self.line_control.write(LineControlMasks::DIVISOR_LATCH_ACCESS);
+            self.data.write(divisor as u8);
+            self.interrupt_enable.write((divisor >> 8) as u8);
+            self.line_control.write(config.line_control());
+        }
+
+        self.config = config;
+    }
+
+    pub fn line_config(&self) -> LineConfig {
+        self.config
+    }
+
+    pub fn enable_receive_interrupt(&mut self) {
+        unsafe {
+            let enabled = self.interrupt_enable.read();
+            self.interrupt_enable.write(enabled | InterruptEnableMasks::DATA_AVAILABLE);
+        }
+    }
+
+    pub fn disable_receive_interrupt(&mut self) {
+        unsafe {
+            let enabled = self.interrupt_enable.read();
+            self.interrupt_enable.write(enabled & !InterruptEnableMasks::DATA_AVAILABLE);
+        }
+    }
+
+    fn line_status(&mut self) -> u8 {
+        unsafe { self.line_status.read() }
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        while self.line_status() & LineStatusMasks::TRANSMITTER_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+
+        unsafe { self.data.write(byte) };
+    }
+
+    pub fn try_receive(&mut self) -> Option<u8> {
+        if self.line_status() & LineStatusMasks::DATA_READY == 0 {
+            return None;
+        }
+
+        Some(unsafe { self.data.read() })
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    pub static ref SERIAL1: SpinLock<SerialPort> = {
+        let mut serial_port = SerialPort::new(COM1);
+        serial_port.init(LineConfig::new()).expect("COM1 did not pass the loopback test");
+        SpinLock::new(serial_port)
+    };
+}
+
+/// Reconfigures COM1, e.g. to a different baud rate.
+pub fn configure(config: LineConfig) {
+    SERIAL1.lock().set_line_config(config);
+}
+
+/// Drains the receiver of COM1 into the receive buffer, meant to be called from the COM1 IRQ.
+/// It does not take the `SERIAL1` lock so that it can't deadlock against an interrupted `serial_print!`.
+pub fn handle_interrupt() {
+    let mut receiver = SerialPort::new(COM1);
+
+    while let Some(byte) = receiver.try_receive() {
+        //there is nobody to report an overrun to, so the newest bytes are dropped
+        let _ = RECEIVE_BUFFER.push(byte);
+    }
+
+    RECEIVE_WAKER.wake();
+}
+
+/// Returns the oldest byte received by the interrupt handler, if any.
+pub fn read_byte() -> Option<u8> {
+    RECEIVE_BUFFER.pop()
+}
+
+pub struct NextByte;
+
+impl Future for NextByte {
+    type Output = u8;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<u8> {
+        if let Some(byte) = RECEIVE_BUFFER.pop() {
+            return Poll::Ready(byte);
+        }
+
+        //a byte may have arrived before the waker was registered
+        RECEIVE_WAKER.register(context.waker());
+        match RECEIVE_BUFFER.pop() {
+            Some(byte) => Poll::Ready(byte),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Waits for the next byte received on COM1, for use in async tasks.
+pub fn next_byte() -> NextByte {
+    NextByte
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::kernel::arch::x86::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    match SERIAL1.try_lock() {
+        Some(mut serial) => serial.write_fmt(args).expect("Printing to serial failed"),
+        //only a fault or panic while printing gets here, see `print::_print`
+        None => SerialPort::new(COM1).write_fmt(args).expect("Printing to serial failed"),
+    }
+}
@@ -0,0 +1,110 @@
+use core::sync::atomic::AtomicU64;
+use x86_64::registers::model_specific::{Efer, EferFlags, Msr};
+use crate::kernel::arch::x86::context::RflagsMasks;
+use crate::kernel::arch::x86::gdt;
+use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::memory::paging::USER_SPACE_END;
+use crate::kernel::process::{self, Signal};
+use crate::kernel::process::signal;
+use crate::kernel::syscall::{self, SyscallNumber};
+use crate::{restore_preserved_registers, restore_scratch_registers, save_preserved_registers, save_scratch_registers};
+
+/// Interrupt vector of the `int 0x80` system call gate, callable from ring 3.
+pub const SYSCALL_VECTOR: usize = 0x80;
+
+const IA32_STAR: u32 = 0xC000_0081;
+const IA32_LSTAR: u32 = 0xC000_0082;
+const IA32_FMASK: u32 = 0xC000_0084;
+
+//user stack pointer while the `syscall` entry switches stacks, interrupts are disabled in between
+static USER_STACK_POINTER: AtomicU64 = AtomicU64::new(0);
+
+/// Enables `syscall`/`sysret`, the GDT has to be loaded already.
+pub fn init() {
+    let selectors = gdt::selectors();
+
+    //sysret loads CS from STAR[63:48] + 16 and SS from STAR[63:48] + 8, syscall CS from STAR[47:32] and SS 8 above
+    let star = ((selectors.user_data.0 - 8) as u64) << 48 | (selectors.kernel_code.0 as u64) << 32;
+    //the entry runs on the user stack until it switched, so interrupts have to stay off until then
+    let mask = RflagsMasks::INTERRUPT_ENABLE | RflagsMasks::TRAP | RflagsMasks::DIRECTION | RflagsMasks::ALIGNMENT_CHECK;
+
+    unsafe {
+        Msr::new(IA32_STAR).write(star);
+        Msr::new(IA32_LSTAR).write(syscall_entry as extern "C" fn() -> ! as u64);
+        Msr::new(IA32_FMASK).write(mask as u64);
+        Efer::update(|flags| flags.insert(EferFlags::SYSTEM_CALL_EXTENSIONS));
+    }
+}
+
+#[naked]
+extern "C" fn syscall_entry() -> ! {
+    unsafe {
+        core::arch::asm! {
+            "mov [rip + {user_stack}], rsp", //syscall does not switch stacks, so do it by hand
+            "mov rsp, [rip + {kernel_stack}]",
+
+            //build the frame an interrupt from ring 3 pushes, so that both entries share the handler
+            "push {user_data}", //ss
+            "push qword ptr [rip + {user_stack}]", //rsp
+            "push r11", //syscall saved rflags in r11
+            "push {user_code}", //cs
+            "push rcx", //syscall saved rip in rcx
+
+            save_scratch_registers!(), //save scratch (caller-saved/volatile) registers
+            save_preserved_registers!(), //save preserved (callee-saved/non volatile) registers
+
+            "mov rdi, rsp", //rdi is used as the first argument passed to a function so we move rsp to rdi
+            "call {handler}",
+
+            "cli", //the handler may enable interrupts, but we are on the user stack before sysret
+            "test al, al", //whether the handler needs iretq, popping leaves the flags alone
+            restore_preserved_registers!(), //restore preserved (callee-saved/non volatile) registers
+            restore_scratch_registers!(), //restore scratch (caller-saved/volatile) registers
+            "jnz 2f",
+
+            "pop rcx", //sysret continues at rcx
+            "add rsp, 8", //cs is set by sysret
+            "pop r11", //sysret restores rflags from r11
+            "pop rsp", //back on the user stack, ss is set by sysret
+            "sysretq",
+
+            "2:", //sysret would clobber rcx and r11, the frame is a complete one for iretq
+            "iretq",
+            user_stack = sym USER_STACK_POINTER,
+            kernel_stack = sym gdt::KERNEL_STACK,
+            user_data = const (gdt::USER_DATA_INDEX << 3) | 3,
+            user_code = const (gdt::USER_CODE_INDEX << 3) | 3,
+            handler = sym handle_syscall,
+            options(noreturn)
+        }
+    }
+}
+
+/// Handler of the `int 0x80` gate, it takes the same registers as `syscall`. It always returns
+/// through `iretq`, which restores every register.
+pub extern "C" fn syscall_interrupt(stack_frame: &mut StackFrame) {
+    handle_syscall(stack_frame);
+}
+
+/// The number is passed in `rax` and the arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`
+/// (`rcx` is taken by `syscall`). The result is returned in `rax`.
+///
+/// Returns true if the `syscall` entry has to return through `iretq`. That is the case when
+/// `sigreturn` replaced all registers, as `sysret` takes `rip` and `rflags` from `rcx` and `r11`.
+/// It is also the case when `rip` isn't canonical: `sysret` raises #GP for it in ring 0 but already
+/// on the user stack. A segment may end right at the end of user space, so `rip` can end up there.
+extern "C" fn handle_syscall(stack_frame: &mut StackFrame) -> bool {
+    let scratch = &stack_frame.scratch;
+    let arguments = [scratch.rdi, scratch.rsi, scratch.rdx, scratch.r10, scratch.r8, scratch.r9];
+    let number = scratch.rax;
+
+    if number == SyscallNumber::SIGRETURN {
+        if !signal::restore(stack_frame) {
+            process::kill_current(Signal::Segfault);
+        }
+        return true;
+    }
+
+    stack_frame.scratch.rax = syscall::dispatch(number, &arguments);
+    stack_frame.iret.rip as u64 >= USER_SPACE_END
+}
@@ -0,0 +1,96 @@
+use crate::kernel::drivers::keyboard::{KeyCode, Modifiers};
+
+/// Character the key produces on a US QWERTY keyboard, if any.
+pub fn us_qwerty(key: KeyCode, modifiers: &Modifiers) -> Option<char> {
+    use KeyCode::*;
+
+    let shifted = modifiers.is_shifted();
+
+    //letters are the only keys caps lock applies to
+    if let Some(letter) = letter(key) {
+        let upper = shifted != modifiers.caps_lock;
+        return Some(if upper { letter.to_ascii_uppercase() } else { letter });
+    }
+
+    if let Some(digit) = keypad_digit(key) {
+        return if modifiers.num_lock { Some(digit) } else { None };
+    }
+
+    let (normal, shift) = match key {
+        Backtick => ('`', '~'),
+        Key1 => ('1', '!'),
+        Key2 => ('2', '@'),
+        Key3 => ('3', '#'),
+        Key4 => ('4', '$'),
+        Key5 => ('5', '%'),
+        Key6 => ('6', '^'),
+        Key7 => ('7', '&'),
+        Key8 => ('8', '*'),
+        Key9 => ('9', '('),
+        Key0 => ('0', ')'),
+        Minus => ('-', '_'),
+        Equals => ('=', '+'),
+        LeftBracket => ('[', '{'),
+        RightBracket => (']', '}'),
+        Backslash => ('\\', '|'),
+        Semicolon => (';', ':'),
+        Quote => ('\'', '"'),
+        Comma => (',', '<'),
+        Period => ('.', '>'),
+        Slash => ('/', '?'),
+        Space => (' ', ' '),
+        Tab => ('\t', '\t'),
+        Enter | KeypadEnter => ('\n', '\n'),
+        Backspace => ('\x08', '\x08'),
+        KeypadMultiply => ('*', '*'),
+        KeypadMinus => ('-', '-'),
+        KeypadPlus => ('+', '+'),
+        KeypadDivide => ('/', '/'),
+        KeypadPeriod if modifiers.num_lock => ('.', '.'),
+        _ => return None,
+    };
+
+    Some(if shifted { shift } else { normal })
+}
+
+fn letter(key: KeyCode) -> Option<char> {
+    use KeyCode::*;
+
+    let letter = match key {
+        A => 'a', B => 'b', C => 'c', D => 'd', E => 'e', F => 'f', G => 'g',
+        H => 'h', I => 'i', J => 'j', K => 'k', L => 'l', M => 'm', N => 'n',
+        O => 'o', P => 'p', Q => 'q', R => 'r', S => 's', T => 't', U => 'u',
+        V => 'v', W => 'w', X => 'x', Y => 'y', Z => 'z',
+        _ => return None,
+    };
+
+    Some(letter)
+}
+
+fn keypad_digit(key: KeyCode) -> Option<char> {
+    use KeyCode::*;
+
+    let digit = match key {
+        Keypad0 => '0', Keypad1 => '1', Keypad2 => '2', Keypad3 => '3', Keypad4 => '4',
+        Keypad5 => '5', Keypad6 => '6', Keypad7 => '7', Keypad8 => '8', Keypad9 => '9',
+        _ => return None,
+    };
+
+    Some(digit)
+}
+
+#[test_case]
+fn shift_and_caps_lock() {
+    let mut modifiers = Modifiers::new();
+    assert_eq!(us_qwerty(KeyCode::A, &modifiers), Some('a'));
+    assert_eq!(us_qwerty(KeyCode::Key1, &modifiers), Some('1'));
+
+    modifiers.left_shift = true;
+    assert_eq!(us_qwerty(KeyCode::A, &modifiers), Some('A'));
+    assert_eq!(us_qwerty(KeyCode::Key1, &modifiers), Some('!'));
+
+    //shift cancels caps lock for letters only
+    modifiers.caps_lock = true;
+    assert_eq!(us_qwerty(KeyCode::A, &modifiers), Some('a'));
+    assert_eq!(us_qwerty(KeyCode::Key1, &modifiers), Some('!'));
+}
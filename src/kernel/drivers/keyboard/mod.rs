@@ -0,0 +1,191 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use crate::kernel::arch::x86::i8042::{Controller, ControllerError};
+use crate::kernel::arch::x86::interrupts::irq;
+use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::lib::ring_buffer::RingBuffer;
+use crate::kernel::lib::waker_cell::WakerCell;
+use scancode::{Decoder, ScancodeSet};
+
+pub mod scancode;
+pub mod layout;
+
+//legacy ISA interrupt line of the first PS/2 port
+const KEYBOARD_IRQ: u8 = 1;
+const EVENT_QUEUE_SIZE: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    PrintScreen, ScrollLock, Pause,
+    Backtick, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0, Minus, Equals, Backspace,
+    Tab, Q, W, E, R, T, Y, U, I, O, P, LeftBracket, RightBracket, Backslash,
+    CapsLock, A, S, D, F, G, H, J, K, L, Semicolon, Quote, Enter,
+    LeftShift, Z, X, C, V, B, N, M, Comma, Period, Slash, RightShift,
+    LeftControl, LeftGui, LeftAlt, Space, RightAlt, RightGui, Menu, RightControl,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    ArrowUp, ArrowDown, ArrowLeft, ArrowRight,
+    NumLock, KeypadDivide, KeypadMultiply, KeypadMinus, KeypadPlus, KeypadEnter, KeypadPeriod,
+    Keypad0, Keypad1, Keypad2, Keypad3, Keypad4, Keypad5, Keypad6, Keypad7, Keypad8, Keypad9,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modifiers {
+    pub left_shift: bool,
+    pub right_shift: bool,
+    pub left_control: bool,
+    pub right_control: bool,
+    pub left_alt: bool,
+    pub right_alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+    pub scroll_lock: bool,
+}
+
+impl Modifiers {
+    pub const fn new() -> Modifiers {
+        Modifiers {
+            left_shift: false,
+            right_shift: false,
+            left_control: false,
+            right_control: false,
+            left_alt: false,
+            right_alt: false,
+            caps_lock: false,
+            num_lock: false,
+            scroll_lock: false,
+        }
+    }
+
+    pub fn is_shifted(&self) -> bool {
+        self.left_shift || self.right_shift
+    }
+
+    pub fn is_control(&self) -> bool {
+        self.left_control || self.right_control
+    }
+
+    pub fn is_alt(&self) -> bool {
+        self.left_alt || self.right_alt
+    }
+
+    fn update(&mut self, key: KeyCode, state: KeyState) {
+        let pressed = state == KeyState::Pressed;
+
+        match key {
+            KeyCode::LeftShift => self.left_shift = pressed,
+            KeyCode::RightShift => self.right_shift = pressed,
+            KeyCode::LeftControl => self.left_control = pressed,
+            KeyCode::RightControl => self.right_control = pressed,
+            KeyCode::LeftAlt => self.left_alt = pressed,
+            KeyCode::RightAlt => self.right_alt = pressed,
+            //locks toggle on press, typematic repeats of a held key toggle again like on any PC
+            KeyCode::CapsLock if pressed => self.caps_lock = !self.caps_lock,
+            KeyCode::NumLock if pressed => self.num_lock = !self.num_lock,
+            KeyCode::ScrollLock if pressed => self.scroll_lock = !self.scroll_lock,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// Modifiers after this event was applied.
+    pub modifiers: Modifiers,
+    /// Character of a pressed key in the active layout.
+    pub character: Option<char>,
+}
+
+pub struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+}
+
+impl Keyboard {
+    pub const fn new(set: ScancodeSet) -> Keyboard {
+        Keyboard {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers::new(),
+        }
+    }
+
+    pub fn process_byte(&mut self, byte: u8) -> Option<KeyEvent> {
+        let (code, state) = self.decoder.feed(byte)?;
+        self.modifiers.update(code, state);
+
+        let character = match state {
+            KeyState::Pressed => layout::us_qwerty(code, &self.modifiers),
+            KeyState::Released => None,
+        };
+
+        Some(KeyEvent { code, state, modifiers: self.modifiers, character })
+    }
+}
+
+//the decoder state is only ever touched by the interrupt handler
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard::new(ScancodeSet::Set1));
+static EVENTS: RingBuffer<KeyEvent, EVENT_QUEUE_SIZE> = RingBuffer::new();
+static EVENT_WAKER: WakerCell = WakerCell::new();
+
+fn keyboard_interrupt(_stack_frame: &StackFrame) {
+    let byte = Controller::new().read_data_unchecked();
+
+    if let Some(event) = KEYBOARD.lock().process_byte(byte) {
+        //if nobody reads the events we drop the newest ones
+        let _ = EVENTS.push(event);
+        EVENT_WAKER.wake();
+    }
+}
+
+/// Initializes the PS/2 controller and delivers key events from IRQ 1.
+/// The controller translates to scancode set 1 unless `set` asks for set 2.
+pub fn init(set: ScancodeSet) -> Result<(), ControllerError> {
+    interrupts::without_interrupts(|| {
+        Controller::new().init(set == ScancodeSet::Set1)?;
+        *KEYBOARD.lock() = Keyboard::new(set);
+        Ok(())
+    })?;
+
+    irq::register_irq(KEYBOARD_IRQ, keyboard_interrupt);
+    Ok(())
+}
+
+/// Returns the oldest key event nobody has read yet, it never blocks.
+pub fn read_event() -> Option<KeyEvent> {
+    EVENTS.pop()
+}
+
+pub struct NextEvent;
+
+impl Future for NextEvent {
+    type Output = KeyEvent;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<KeyEvent> {
+        if let Some(event) = EVENTS.pop() {
+            return Poll::Ready(event);
+        }
+
+        //an event may have arrived before the waker was registered
+        EVENT_WAKER.register(context.waker());
+        match EVENTS.pop() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Waits for the next key event, for use in async tasks.
+pub fn next_event() -> NextEvent {
+    NextEvent
+}
@@ -0,0 +1,378 @@
+use crate::kernel::drivers::keyboard::{KeyCode, KeyState};
+
+pub struct ScancodePrefix;
+
+impl ScancodePrefix {
+    pub const EXTENDED: u8 = 0xE0;
+    //only the pause key sends this one
+    pub const PAUSE: u8 = 0xE1;
+    //scancode set 2 only, set 1 marks releases with bit 7
+    pub const RELEASE: u8 = 0xF0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+impl ScancodeSet {
+    //bytes left in the pause sequence after its first 0xE1
+    fn pause_length(&self) -> u8 {
+        match self {
+            ScancodeSet::Set1 => 5,
+            ScancodeSet::Set2 => 7,
+        }
+    }
+}
+
+/// Turns the byte stream of the keyboard into key presses and releases.
+pub struct Decoder {
+    set: ScancodeSet,
+    extended: bool,
+    release: bool,
+    pause_remaining: u8,
+}
+
+impl Decoder {
+    pub const fn new(set: ScancodeSet) -> Decoder {
+        Decoder {
+            set,
+            extended: false,
+            release: false,
+            pause_remaining: 0,
+        }
+    }
+
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+
+    /// Feeds one byte, returns the key event once a sequence is complete.
+    pub fn feed(&mut self, byte: u8) -> Option<(KeyCode, KeyState)> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+
+            //pause has no release, the whole sequence is a single press
+            return match self.pause_remaining {
+                0 => Some((KeyCode::Pause, KeyState::Pressed)),
+                _ => None,
+            };
+        }
+
+        match byte {
+            ScancodePrefix::EXTENDED => {
+                self.extended = true;
+                None
+            }
+            ScancodePrefix::PAUSE => {
+                self.pause_remaining = self.set.pause_length();
+                None
+            }
+            ScancodePrefix::RELEASE if self.set == ScancodeSet::Set2 => {
+                self.release = true;
+                None
+            }
+            byte => {
+                let (code, release) = match self.set {
+                    ScancodeSet::Set1 => (byte & 0x7F, byte & 0x80 != 0),
+                    ScancodeSet::Set2 => (byte, self.release),
+                };
+
+                let key = match (self.set, self.extended) {
+                    (ScancodeSet::Set1, false) => set_1(code),
+                    (ScancodeSet::Set1, true) => set_1_extended(code),
+                    (ScancodeSet::Set2, false) => set_2(code),
+                    (ScancodeSet::Set2, true) => set_2_extended(code),
+                };
+
+                self.extended = false;
+                self.release = false;
+
+                //unknown codes and the fake shifts around extended keys are dropped
+                key.map(|key| (key, if release { KeyState::Released } else { KeyState::Pressed }))
+            }
+        }
+    }
+}
+
+fn set_1(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    let key = match code {
+        0x01 => Escape,
+        0x02 => Key1,
+        0x03 => Key2,
+        0x04 => Key3,
+        0x05 => Key4,
+        0x06 => Key5,
+        0x07 => Key6,
+        0x08 => Key7,
+        0x09 => Key8,
+        0x0A => Key9,
+        0x0B => Key0,
+        0x0C => Minus,
+        0x0D => Equals,
+        0x0E => Backspace,
+        0x0F => Tab,
+        0x10 => Q,
+        0x11 => W,
+        0x12 => E,
+        0x13 => R,
+        0x14 => T,
+        0x15 => Y,
+        0x16 => U,
+        0x17 => I,
+        0x18 => O,
+        0x19 => P,
+        0x1A => LeftBracket,
+        0x1B => RightBracket,
+        0x1C => Enter,
+        0x1D => LeftControl,
+        0x1E => A,
+        0x1F => S,
+        0x20 => D,
+        0x21 => F,
+        0x22 => G,
+        0x23 => H,
+        0x24 => J,
+        0x25 => K,
+        0x26 => L,
+        0x27 => Semicolon,
+        0x28 => Quote,
+        0x29 => Backtick,
+        0x2A => LeftShift,
+        0x2B => Backslash,
+        0x2C => Z,
+        0x2D => X,
+        0x2E => C,
+        0x2F => V,
+        0x30 => B,
+        0x31 => N,
+        0x32 => M,
+        0x33 => Comma,
+        0x34 => Period,
+        0x35 => Slash,
+        0x36 => RightShift,
+        0x37 => KeypadMultiply,
+        0x38 => LeftAlt,
+        0x39 => Space,
+        0x3A => CapsLock,
+        0x3B => F1,
+        0x3C => F2,
+        0x3D => F3,
+        0x3E => F4,
+        0x3F => F5,
+        0x40 => F6,
+        0x41 => F7,
+        0x42 => F8,
+        0x43 => F9,
+        0x44 => F10,
+        0x45 => NumLock,
+        0x46 => ScrollLock,
+        0x47 => Keypad7,
+        0x48 => Keypad8,
+        0x49 => Keypad9,
+        0x4A => KeypadMinus,
+        0x4B => Keypad4,
+        0x4C => Keypad5,
+        0x4D => Keypad6,
+        0x4E => KeypadPlus,
+        0x4F => Keypad1,
+        0x50 => Keypad2,
+        0x51 => Keypad3,
+        0x52 => Keypad0,
+        0x53 => KeypadPeriod,
+        0x57 => F11,
+        0x58 => F12,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_1_extended(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    let key = match code {
+        0x1C => KeypadEnter,
+        0x1D => RightControl,
+        0x35 => KeypadDivide,
+        0x37 => PrintScreen,
+        0x38 => RightAlt,
+        0x47 => Home,
+        0x48 => ArrowUp,
+        0x49 => PageUp,
+        0x4B => ArrowLeft,
+        0x4D => ArrowRight,
+        0x4F => End,
+        0x50 => ArrowDown,
+        0x51 => PageDown,
+        0x52 => Insert,
+        0x53 => Delete,
+        0x5B => LeftGui,
+        0x5C => RightGui,
+        0x5D => Menu,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_2(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    let key = match code {
+        0x01 => F9,
+        0x03 => F5,
+        0x04 => F3,
+        0x05 => F1,
+        0x06 => F2,
+        0x07 => F12,
+        0x09 => F10,
+        0x0A => F8,
+        0x0B => F6,
+        0x0C => F4,
+        0x0D => Tab,
+        0x0E => Backtick,
+        0x11 => LeftAlt,
+        0x12 => LeftShift,
+        0x14 => LeftControl,
+        0x15 => Q,
+        0x16 => Key1,
+        0x1A => Z,
+        0x1B => S,
+        0x1C => A,
+        0x1D => W,
+        0x1E => Key2,
+        0x21 => C,
+        0x22 => X,
+        0x23 => D,
+        0x24 => E,
+        0x25 => Key4,
+        0x26 => Key3,
+        0x29 => Space,
+        0x2A => V,
+        0x2B => F,
+        0x2C => T,
+        0x2D => R,
+        0x2E => Key5,
+        0x31 => N,
+        0x32 => B,
+        0x33 => H,
+        0x34 => G,
+        0x35 => Y,
+        0x36 => Key6,
+        0x3A => M,
+        0x3B => J,
+        0x3C => U,
+        0x3D => Key7,
+        0x3E => Key8,
+        0x41 => Comma,
+        0x42 => K,
+        0x43 => I,
+        0x44 => O,
+        0x45 => Key0,
+        0x46 => Key9,
+        0x49 => Period,
+        0x4A => Slash,
+        0x4B => L,
+        0x4C => Semicolon,
+        0x4D => P,
+        0x4E => Minus,
+        0x52 => Quote,
+        0x54 => LeftBracket,
+        0x55 => Equals,
+        0x58 => CapsLock,
+        0x59 => RightShift,
+        0x5A => Enter,
+        0x5B => RightBracket,
+        0x5D => Backslash,
+        0x66 => Backspace,
+        0x69 => Keypad1,
+        0x6B => Keypad4,
+        0x6C => Keypad7,
+        0x70 => Keypad0,
+        0x71 => KeypadPeriod,
+        0x72 => Keypad2,
+        0x73 => Keypad5,
+        0x74 => Keypad6,
+        0x75 => Keypad8,
+        0x76 => Escape,
+        0x77 => NumLock,
+        0x78 => F11,
+        0x79 => KeypadPlus,
+        0x7A => Keypad3,
+        0x7B => KeypadMinus,
+        0x7C => KeypadMultiply,
+        0x7D => Keypad9,
+        0x7E => ScrollLock,
+        0x83 => F7,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+fn set_2_extended(code: u8) -> Option<KeyCode> {
+    use KeyCode::*;
+
+    let key = match code {
+        0x11 => RightAlt,
+        0x14 => RightControl,
+        0x1F => LeftGui,
+        0x27 => RightGui,
+        0x2F => Menu,
+        0x4A => KeypadDivide,
+        0x5A => KeypadEnter,
+        0x69 => End,
+        0x6B => ArrowLeft,
+        0x6C => Home,
+        0x70 => Insert,
+        0x71 => Delete,
+        0x72 => ArrowDown,
+        0x74 => ArrowRight,
+        0x75 => ArrowUp,
+        0x7A => PageDown,
+        0x7C => PrintScreen,
+        0x7D => PageUp,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[test_case]
+fn set_1_press_and_release() {
+    let mut decoder = Decoder::new(ScancodeSet::Set1);
+
+    assert_eq!(decoder.feed(0x1E), Some((KeyCode::A, KeyState::Pressed)));
+    assert_eq!(decoder.feed(0x9E), Some((KeyCode::A, KeyState::Released)));
+}
+
+#[test_case]
+fn set_1_extended_keys() {
+    let mut decoder = Decoder::new(ScancodeSet::Set1);
+
+    assert_eq!(decoder.feed(0xE0), None);
+    assert_eq!(decoder.feed(0x48), Some((KeyCode::ArrowUp, KeyState::Pressed)));
+    assert_eq!(decoder.feed(0xE0), None);
+    assert_eq!(decoder.feed(0xC8), Some((KeyCode::ArrowUp, KeyState::Released)));
+    //the prefix only applies to a single key
+    assert_eq!(decoder.feed(0x48), Some((KeyCode::Keypad8, KeyState::Pressed)));
+}
+
+#[test_case]
+fn set_2_release_and_pause() {
+    let mut decoder = Decoder::new(ScancodeSet::Set2);
+
+    assert_eq!(decoder.feed(0x1C), Some((KeyCode::A, KeyState::Pressed)));
+    assert_eq!(decoder.feed(0xF0), None);
+    assert_eq!(decoder.feed(0x1C), Some((KeyCode::A, KeyState::Released)));
+
+    let pause = [0xE1, 0x14, 0x77, 0xE1, 0xF0, 0x14, 0xF0];
+    for byte in pause {
+        assert_eq!(decoder.feed(byte), None);
+    }
+    assert_eq!(decoder.feed(0x77), Some((KeyCode::Pause, KeyState::Pressed)));
+}
@@ -1,2 +1,5 @@
 pub mod print;
-pub mod enum_utils;
\ No newline at end of file
+pub mod enum_utils;
+pub mod ring_buffer;
+pub mod testing;
+pub mod waker_cell;
@@ -1,7 +1,7 @@
 use lazy_static::lazy_static;
-use spin::Mutex;
 use core::fmt;
 use crate::kernel::arch::x86::vga;
+use crate::kernel::sync::SpinLock;
 
 pub struct Writer {
     column_position: usize,
@@ -10,15 +10,32 @@ pub struct Writer {
 }
 
 lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: vga::ColorCode::new(vga::Color::Green, vga::Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut vga::Buffer) },
-    });
+    pub static ref WRITER: SpinLock<Writer> = SpinLock::new(Writer::new());
 }
 
 
 impl Writer {
+    /// A writer starting at the beginning of the bottom row of the VGA text buffer.
+    pub fn new() -> Writer {
+        Writer {
+            column_position: 0,
+            color_code: vga::ColorCode::new(vga::Color::Green, vga::Color::Black),
+            buffer: unsafe { &mut *(0xb8000 as *mut vga::Buffer) },
+        }
+    }
+
+    pub fn set_color(&mut self, foreground: vga::Color, background: vga::Color) {
+        self.color_code = vga::ColorCode::new(foreground, background);
+    }
+
+    /// Blanks every row in the current colors and starts over at the bottom row.
+    pub fn clear_screen(&mut self) {
+        for row in 0..vga::BUFFER_HEIGHT {
+            self.clear_row(row);
+        }
+        self.column_position = 0;
+    }
+
     pub fn write_byte(&mut self, byte: u8) {
         match byte {
             b'\n' => self.new_line(),
@@ -93,6 +110,30 @@ macro_rules! println {
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    WRITER.lock().write_fmt(args).unwrap();
+
+    match WRITER.try_lock() {
+        Some(mut writer) => writer.write_fmt(args).unwrap(),
+        //the lock keeps interrupts disabled, so it is only taken if the code holding it faulted
+        //(or panicked) and the handler prints. Waiting would deadlock, write to a fresh line instead
+        None => {
+            let mut writer = Writer::new();
+            writer.new_line();
+            writer.write_fmt(args).unwrap();
+        }
+    }
+}
+
+#[test_case]
+fn println_while_writer_is_held() {
+    let message = "printed while the writer is held";
+    let writer = WRITER.lock();
+    crate::println!("{}", message);
+
+    //the message got a fresh line, which its newline then scrolled up by one row
+    for (column, byte) in message.bytes().enumerate() {
+        let character = writer.buffer.chars[vga::BUFFER_HEIGHT - 2][column].read();
+        assert_eq!(character.ascii_character, byte);
+    }
+    assert!(WRITER.is_locked());
 }
 
@@ -0,0 +1,95 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Fixed size single-producer/single-consumer queue.
+///
+/// The producer (usually an interrupt handler) only ever touches `tail` and the consumer
+/// only ever touches `head`, so neither side needs a lock and an interrupt can never
+/// deadlock against the code it interrupted.
+/// One slot is always kept free to tell a full buffer apart from an empty one.
+pub struct RingBuffer<T: Copy, const N: usize> {
+    buffer: UnsafeCell<[MaybeUninit<T>; N]>,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> RingBuffer<T, N> {
+        RingBuffer {
+            buffer: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends `value`, handing it back if the buffer is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+
+        if next == self.head.load(Ordering::Acquire) {
+            return Err(value);
+        }
+
+        unsafe { (*self.buffer.get())[tail] = MaybeUninit::new(value) };
+        self.tail.store(next, Ordering::Release);
+
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = unsafe { (*self.buffer.get())[head].assume_init() };
+        self.head.store((head + 1) % N, Ordering::Release);
+
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+
+        (tail + N - head) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+}
+
+#[test_case]
+fn push_then_pop_keeps_order() {
+    let buffer: RingBuffer<u8, 4> = RingBuffer::new();
+
+    assert_eq!(buffer.push(1), Ok(()));
+    assert_eq!(buffer.push(2), Ok(()));
+    assert_eq!(buffer.len(), 2);
+    assert_eq!(buffer.pop(), Some(1));
+    assert_eq!(buffer.pop(), Some(2));
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test_case]
+fn push_into_full_buffer_fails() {
+    let buffer: RingBuffer<u8, 4> = RingBuffer::new();
+
+    for i in 0..buffer.capacity() {
+        assert_eq!(buffer.push(i as u8), Ok(()));
+    }
+
+    assert_eq!(buffer.push(42), Err(42));
+    assert_eq!(buffer.pop(), Some(0));
+    assert_eq!(buffer.push(42), Ok(()));
+}
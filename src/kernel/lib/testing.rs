@@ -0,0 +1,54 @@
+use core::panic::PanicInfo;
+use crate::{serial_print, serial_println};
+use crate::kernel::arch::x86::{context, hlt_loop};
+use crate::kernel::arch::x86::qemu::{exit_qemu, QemuExitCode};
+use crate::kernel::memory::paging::AddressSpace;
+use crate::kernel::task;
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+
+    for test in tests {
+        test.run();
+    }
+
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// Runs `function` on a thread of its own in `address_space` and waits until that thread ended,
+/// e.g. to reach user pages that are only mapped there.
+pub fn run_in<F: FnOnce() + Send + 'static>(address_space: &mut AddressSpace, function: F) {
+    let thread = task::spawn_in("test", address_space, function);
+
+    while task::is_alive(thread) {
+        task::yield_now();
+    }
+}
+
+/// Runs ring 3 code of `address_space` from `entry` with the stack at `stack_pointer` until it
+/// exits or is killed.
+pub fn run_user_mode(address_space: &mut AddressSpace, entry: u64, stack_pointer: u64) {
+    run_in(address_space, move || unsafe { context::enter_user_mode(entry, stack_pointer) });
+}
+
+/// Reports the panicking test as failed and makes QEMU exit with a failure code.
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    exit_qemu(QemuExitCode::Failed);
+
+    //in case QEMU was started without the isa-debug-exit device
+    hlt_loop();
+}
@@ -0,0 +1,35 @@
+use core::task::Waker;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// Holds the waker of the task waiting for an interrupt driven event, so that the interrupt
+/// handler can wake it up.
+pub struct WakerCell {
+    waker: Mutex<Option<Waker>>,
+}
+
+impl WakerCell {
+    pub const fn new() -> WakerCell {
+        WakerCell { waker: Mutex::new(None) }
+    }
+
+    /// Replaces the stored waker, a task has to do this every time before it returns `Pending`.
+    pub fn register(&self, waker: &Waker) {
+        interrupts::without_interrupts(|| {
+            let mut slot = self.waker.lock();
+
+            if !slot.as_ref().map_or(false, |current| current.will_wake(waker)) {
+                *slot = Some(waker.clone());
+            }
+        });
+    }
+
+    /// Wakes the registered task, if any. Safe to call from interrupt handlers.
+    pub fn wake(&self) {
+        let waker = interrupts::without_interrupts(|| self.waker.lock().take());
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
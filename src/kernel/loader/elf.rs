@@ -0,0 +1,424 @@
+use core::mem::size_of;
+use crate::kernel::memory::frame::FRAME_SIZE;
+use crate::kernel::memory::paging::{USER_SPACE_END, USER_SPACE_START};
+
+pub const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+pub struct Ident;
+
+impl Ident {
+    pub const CLASS_64: u8 = 2;
+    pub const LITTLE_ENDIAN: u8 = 1;
+    pub const VERSION_CURRENT: u8 = 1;
+}
+
+pub struct FileType;
+
+impl FileType {
+    pub const EXECUTABLE: u16 = 2;
+}
+
+pub const MACHINE_X86_64: u16 = 62;
+
+pub struct SegmentType;
+
+impl SegmentType {
+    pub const LOAD: u32 = 1;
+    pub const PROGRAM_HEADERS: u32 = 6;
+}
+
+pub struct SegmentFlags;
+
+impl SegmentFlags {
+    pub const EXECUTE: u32 = 1;
+    pub const WRITE: u32 = 2;
+    pub const READ: u32 = 4;
+}
+
+pub struct SectionType;
+
+impl SectionType {
+    pub const SYMBOL_TABLE: u32 = 2;
+    pub const STRING_TABLE: u32 = 3;
+}
+
+pub struct SymbolType;
+
+impl SymbolType {
+    pub const FUNCTION: u8 = 2;
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FileHeader {
+    pub ident: [u8; 16],
+    pub file_type: u16,
+    pub machine: u16,
+    pub version: u32,
+    pub entry: u64,
+    pub program_header_offset: u64,
+    pub section_header_offset: u64,
+    pub flags: u32,
+    pub header_size: u16,
+    pub program_header_size: u16,
+    pub program_header_count: u16,
+    pub section_header_size: u16,
+    pub section_header_count: u16,
+    pub section_names_index: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ProgramHeader {
+    pub segment_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub virtual_address: u64,
+    pub physical_address: u64,
+    pub file_size: u64,
+    pub memory_size: u64,
+    pub align: u64,
+}
+
+impl ProgramHeader {
+    pub fn is_load(&self) -> bool {
+        self.segment_type == SegmentType::LOAD
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.flags & SegmentFlags::WRITE != 0
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.flags & SegmentFlags::EXECUTE != 0
+    }
+
+    /// Whether `address` lies in the memory image of the segment.
+    pub fn contains(&self, address: u64) -> bool {
+        self.virtual_address <= address && address - self.virtual_address < self.memory_size
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SectionHeader {
+    pub name: u32,
+    pub section_type: u32,
+    pub flags: u64,
+    pub address: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub align: u64,
+    pub entry_size: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SymbolEntry {
+    pub name: u32,
+    pub info: u8,
+    pub other: u8,
+    pub section_index: u16,
+    pub value: u64,
+    pub size: u64,
+}
+
+impl SymbolEntry {
+    pub fn symbol_type(&self) -> u8 {
+        self.info & 0xf
+    }
+
+    pub fn is_function(&self) -> bool {
+        self.symbol_type() == SymbolType::FUNCTION
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    TooShort,
+    BadMagic,
+    NotElf64,
+    NotLittleEndian,
+    UnsupportedVersion,
+    NotExecutable,
+    WrongMachine,
+    BadProgramHeaders,
+    /// A segment's file contents lie outside the file, or its file size exceeds its memory size.
+    BadSegment,
+    /// A segment would be loaded outside of user space.
+    SegmentNotInUserSpace,
+    /// A segment's offset and address differ modulo the page size, so it can't be mapped.
+    MisalignedSegment,
+    /// A segment starts before the previous one ends, they have to be sorted by address and must not overlap.
+    UnorderedSegments,
+    EntryNotExecutable,
+}
+
+impl ElfError {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ElfError::TooShort => "file too short",
+            ElfError::BadMagic => "not an ELF file",
+            ElfError::NotElf64 => "not a 64-bit ELF file",
+            ElfError::NotLittleEndian => "not little endian",
+            ElfError::UnsupportedVersion => "unsupported ELF version",
+            ElfError::NotExecutable => "not a statically linked executable",
+            ElfError::WrongMachine => "not an x86_64 executable",
+            ElfError::BadProgramHeaders => "invalid program headers",
+            ElfError::BadSegment => "invalid segment",
+            ElfError::SegmentNotInUserSpace => "segment outside of user space",
+            ElfError::MisalignedSegment => "misaligned segment",
+            ElfError::UnorderedSegments => "overlapping or unsorted segments",
+            ElfError::EntryNotExecutable => "entry point not in an executable segment",
+        }
+    }
+}
+
+/// A validated ELF64 executable, borrowed from the file contents.
+pub struct Elf<'a> {
+    data: &'a [u8],
+    header: FileHeader,
+}
+
+impl<'a> Elf<'a> {
+    /// Parses and checks the file header and the program headers of an executable that is loaded
+    /// into user space.
+    pub fn parse(data: &'a [u8]) -> Result<Elf<'a>, ElfError> {
+        let elf = Elf::parse_image(data)?;
+
+        //position independent executables (ET_DYN) would need relocating
+        if elf.header.file_type != FileType::EXECUTABLE {
+            return Err(ElfError::NotExecutable);
+        }
+
+        //the loader only lets a segment share a page with the one before it
+        let mut previous_end = 0;
+        for segment in elf.segments() {
+            check_segment(&segment, data.len() as u64)?;
+
+            if segment.virtual_address < previous_end {
+                return Err(ElfError::UnorderedSegments);
+            }
+            previous_end = segment.virtual_address + segment.memory_size;
+        }
+
+        if !elf.segments().any(|segment| segment.is_executable() && segment.contains(elf.header.entry)) {
+            return Err(ElfError::EntryNotExecutable);
+        }
+
+        Ok(elf)
+    }
+
+    /// Parses the file header and checks that the program headers lie in the file, but not where
+    /// the segments go. Enough to read the sections of images that are not loaded as user
+    /// programs, like the kernel's own.
+    pub fn parse_image(data: &'a [u8]) -> Result<Elf<'a>, ElfError> {
+        let header: FileHeader = read(data, 0).ok_or(ElfError::TooShort)?;
+
+        if header.ident[0..4] != MAGIC {
+            return Err(ElfError::BadMagic);
+        }
+        if header.ident[4] != Ident::CLASS_64 {
+            return Err(ElfError::NotElf64);
+        }
+        if header.ident[5] != Ident::LITTLE_ENDIAN {
+            return Err(ElfError::NotLittleEndian);
+        }
+        if header.ident[6] != Ident::VERSION_CURRENT || header.version != Ident::VERSION_CURRENT as u32 {
+            return Err(ElfError::UnsupportedVersion);
+        }
+        if header.machine != MACHINE_X86_64 {
+            return Err(ElfError::WrongMachine);
+        }
+
+        let table_size = header.program_header_count as u64 * size_of::<ProgramHeader>() as u64;
+        let table_in_file = header.program_header_offset
+            .checked_add(table_size)
+            .map_or(false, |end| end <= data.len() as u64);
+
+        if header.program_header_size as usize != size_of::<ProgramHeader>() || !table_in_file {
+            return Err(ElfError::BadProgramHeaders);
+        }
+
+        Ok(Elf { data, header })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    pub fn entry(&self) -> u64 {
+        self.header.entry
+    }
+
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let data = self.data;
+        let offset = self.header.program_header_offset as usize;
+
+        (0..self.header.program_header_count as usize)
+            .map(move |index| read(data, offset + index * size_of::<ProgramHeader>()).unwrap())
+    }
+
+    /// The PT_LOAD program headers.
+    pub fn segments(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        self.program_headers().filter(ProgramHeader::is_load)
+    }
+
+    /// The bytes of `segment` stored in the file, the rest of its memory image is zeroed.
+    pub fn segment_data(&self, segment: &ProgramHeader) -> &'a [u8] {
+        &self.data[segment.offset as usize..(segment.offset + segment.file_size) as usize]
+    }
+
+    /// Where the program headers end up in memory, if a segment loads them.
+    pub fn program_headers_address(&self) -> Option<u64> {
+        if let Some(phdr) = self.program_headers().find(|header| header.segment_type == SegmentType::PROGRAM_HEADERS) {
+            return Some(phdr.virtual_address);
+        }
+
+        let offset = self.header.program_header_offset;
+        self.segments()
+            .find(|segment| segment.offset <= offset && offset < segment.offset + segment.file_size)
+            .map(|segment| segment.virtual_address + (offset - segment.offset))
+    }
+
+    /// The section headers, none if the table is missing or does not fit in the file.
+    pub fn section_headers(&self) -> impl Iterator<Item = SectionHeader> + 'a {
+        let data = self.data;
+        let offset = self.header.section_header_offset as usize;
+        let count = self.header.section_header_count as usize;
+
+        let table_in_file = count.checked_mul(size_of::<SectionHeader>())
+            .and_then(|size| offset.checked_add(size))
+            .map_or(false, |end| end <= data.len());
+        let valid = self.header.section_header_size as usize == size_of::<SectionHeader>() && table_in_file;
+
+        (0..if valid { count } else { 0 })
+            .map(move |index| read(data, offset + index * size_of::<SectionHeader>()).unwrap())
+    }
+
+    /// The contents of `section`, if they lie in the file.
+    pub fn section_data(&self, section: &SectionHeader) -> Option<&'a [u8]> {
+        let start = usize::try_from(section.offset).ok()?;
+        let end = start.checked_add(usize::try_from(section.size).ok()?)?;
+
+        self.data.get(start..end)
+    }
+
+    /// The entries of the symbol table with their names. Unnamed entries and those whose name is
+    /// no valid string are left out.
+    pub fn symbols(&self) -> impl Iterator<Item = (&'a str, SymbolEntry)> + 'a {
+        let table = self.section_headers().find(|section| section.section_type == SectionType::SYMBOL_TABLE);
+        let entries = table
+            .filter(|table| table.entry_size as usize == size_of::<SymbolEntry>())
+            .and_then(|table| self.section_data(&table))
+            .unwrap_or(&[]);
+        let names = table
+            .and_then(|table| self.section_headers().nth(table.link as usize))
+            .filter(|names| names.section_type == SectionType::STRING_TABLE)
+            .and_then(|names| self.section_data(&names))
+            .unwrap_or(&[]);
+
+        (0..entries.len() / size_of::<SymbolEntry>()).filter_map(move |index| {
+            let entry: SymbolEntry = read(entries, index * size_of::<SymbolEntry>())?;
+            let name = string_at(names, entry.name as usize).filter(|name| !name.is_empty())?;
+
+            Some((name, entry))
+        })
+    }
+}
+
+fn check_segment(segment: &ProgramHeader, file_size: u64) -> Result<(), ElfError> {
+    let file_end = segment.offset.checked_add(segment.file_size);
+    if segment.file_size > segment.memory_size || file_end.map_or(true, |end| end > file_size) {
+        return Err(ElfError::BadSegment);
+    }
+
+    //the last page of user space stays unmapped, code running off its end would continue at a non-canonical address
+    let memory_end = segment.virtual_address.checked_add(segment.memory_size);
+    if segment.virtual_address < USER_SPACE_START || memory_end.map_or(true, |end| end > USER_SPACE_END - FRAME_SIZE) {
+        return Err(ElfError::SegmentNotInUserSpace);
+    }
+
+    if segment.offset % FRAME_SIZE != segment.virtual_address % FRAME_SIZE {
+        return Err(ElfError::MisalignedSegment);
+    }
+
+    Ok(())
+}
+
+/// Reads a `T` at `offset`, the file contents have no particular alignment.
+fn read<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+
+    if end > data.len() {
+        return None;
+    }
+
+    Some(unsafe { core::ptr::read_unaligned(data[offset..].as_ptr() as *const T) })
+}
+
+/// The NUL terminated string at `offset` of a string table.
+fn string_at(table: &[u8], offset: usize) -> Option<&str> {
+    let bytes = table.get(offset..)?;
+    let len = bytes.iter().position(|&byte| byte == 0)?;
+
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+#[cfg(test)]
+const TEST_PROGRAM: &[u8] = include_bytes!("programs/args");
+
+#[test_case]
+fn test_program_is_valid() {
+    let elf = Elf::parse(TEST_PROGRAM).unwrap();
+
+    assert_eq!(elf.entry(), 0x80_0001_0000);
+    assert_eq!(elf.segments().count(), 3);
+    assert!(elf.segments().any(|segment| segment.is_writable() && segment.memory_size > segment.file_size));
+}
+
+#[test_case]
+fn symbols_are_read_from_the_symbol_table() {
+    let elf = Elf::parse(TEST_PROGRAM).unwrap();
+
+    assert!(elf.symbols().any(|(name, symbol)| name == "_start" && symbol.value == elf.entry()));
+    assert!(elf.symbols().any(|(name, symbol)| name == "results" && symbol.value == 0x80_0003_0000));
+}
+
+#[test_case]
+fn broken_headers_are_rejected() {
+    use alloc::vec::Vec;
+
+    let broken = |offset: usize, value: u8| {
+        let mut data: Vec<u8> = TEST_PROGRAM.to_vec();
+        data[offset] = value;
+        Elf::parse(&data).err()
+    };
+
+    assert_eq!(Elf::parse(&TEST_PROGRAM[..32]).err(), Some(ElfError::TooShort));
+    assert_eq!(broken(1, b'X'), Some(ElfError::BadMagic));
+    assert_eq!(broken(4, 1), Some(ElfError::NotElf64));
+    assert_eq!(broken(16, 3), Some(ElfError::NotExecutable));
+    assert_eq!(broken(18, 3), Some(ElfError::WrongMachine));
+    //the entry point, now in the read only data segment
+    assert_eq!(broken(25, 0x10), Some(ElfError::EntryNotExecutable));
+    //the memory size of the text segment, which then runs into the read only data segment
+    assert_eq!(broken(64 + 41, 0x10), Some(ElfError::UnorderedSegments));
+}
+
+#[test_case]
+fn unsorted_segments_are_rejected() {
+    use alloc::vec::Vec;
+
+    let offset = Elf::parse(TEST_PROGRAM).unwrap().header().program_header_offset as usize;
+    let size = size_of::<ProgramHeader>();
+
+    //the read only data segment before the text segment
+    let mut data: Vec<u8> = TEST_PROGRAM.to_vec();
+    let (text, rodata) = data[offset..offset + 2 * size].split_at_mut(size);
+    text.swap_with_slice(rodata);
+
+    assert_eq!(Elf::parse(&data).err(), Some(ElfError::UnorderedSegments));
+}
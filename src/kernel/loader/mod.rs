@@ -0,0 +1,306 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::{Page, PageTableFlags};
+use x86_64::VirtAddr;
+use elf::{Elf, ElfError, ProgramHeader};
+use crate::kernel::memory;
+use crate::kernel::memory::frame::FRAME_SIZE;
+use crate::kernel::memory::paging::{AddressSpace, USER_SPACE_END};
+use crate::kernel::memory::vma::{Vma, VmaKind};
+
+pub mod elf;
+
+/// End of the initial user stack, the last page of user space stays unmapped.
+pub const USER_STACK_TOP: u64 = USER_SPACE_END - FRAME_SIZE;
+pub const USER_STACK_SIZE: u64 = 16 * FRAME_SIZE;
+pub const USER_STACK_MAX_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Types of the auxiliary vector entries passed on the initial stack.
+pub struct AuxType;
+
+impl AuxType {
+    pub const NULL: u64 = 0;
+    pub const PROGRAM_HEADERS: u64 = 3;
+    pub const PROGRAM_HEADER_SIZE: u64 = 4;
+    pub const PROGRAM_HEADER_COUNT: u64 = 5;
+    pub const PAGE_SIZE: u64 = 6;
+    pub const ENTRY: u64 = 9;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    Elf(ElfError),
+    OutOfMemory,
+    OverlappingSegments,
+    /// Two segments share a page that would have to be both writable and executable.
+    WritableAndExecutable,
+    /// argv and envp don't fit on the initial stack.
+    ArgumentsTooLarge,
+}
+
+impl From<ElfError> for LoadError {
+    fn from(error: ElfError) -> LoadError {
+        LoadError::Elf(error)
+    }
+}
+
+/// A program loaded into its own address space, ready to run.
+pub struct Program {
+    pub address_space: AddressSpace,
+    pub entry: VirtAddr,
+    pub stack_pointer: VirtAddr,
+}
+
+/// Loads the statically linked ELF64 executable `data` into a fresh address space and sets up its
+/// stack with `argv`, `envp` and the auxiliary vector, like the System V ABI describes it.
+pub fn load(data: &[u8], argv: &[&str], envp: &[&str]) -> Result<Program, LoadError> {
+    let elf = Elf::parse(data)?;
+    let mut address_space = AddressSpace::new().ok_or(LoadError::OutOfMemory)?;
+
+    for segment in elf.segments() {
+        load_segment(&mut address_space, &elf, &segment)?;
+    }
+
+    let stack_pointer = setup_stack(&mut address_space, &elf, argv, envp)?;
+
+    Ok(Program {
+        address_space,
+        entry: VirtAddr::new(elf.entry()),
+        stack_pointer,
+    })
+}
+
+fn user_flags(writable: bool, executable: bool) -> PageTableFlags {
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+
+    if writable {
+        flags |= PageTableFlags::WRITABLE;
+    }
+
+    //the bit is reserved unless no-execute is enabled
+    if !executable && Efer::read().contains(EferFlags::NO_EXECUTE_ENABLE) {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+
+    flags
+}
+
+fn load_segment(address_space: &mut AddressSpace, elf: &Elf, segment: &ProgramHeader) -> Result<(), LoadError> {
+    if segment.memory_size == 0 {
+        return Ok(());
+    }
+
+    let flags = user_flags(segment.is_writable(), segment.is_executable());
+    let mut start = VirtAddr::new(segment.virtual_address).align_down(FRAME_SIZE);
+    let end = VirtAddr::new(segment.virtual_address + segment.memory_size).align_up(FRAME_SIZE);
+
+    //segments are sorted by address, the previous one may end in the page this one starts in
+    if let Some(&previous) = address_space.vmas.find(start) {
+        share_page(address_space, previous, start, flags)?;
+        start += FRAME_SIZE;
+    }
+
+    if start < end {
+        let vma = Vma::new(start, end, flags, VmaKind::Program);
+        address_space.vmas.insert(vma).map_err(|_| LoadError::OverlappingSegments)?;
+
+        for page in Page::range(Page::containing_address(start), Page::containing_address(end)) {
+            address_space.map_zeroed(page, vma.flags).map_err(|_| LoadError::OutOfMemory)?;
+        }
+    }
+
+    //the rest of the memory image (.bss) stays zeroed
+    write_bytes(address_space, VirtAddr::new(segment.virtual_address), elf.segment_data(segment));
+    Ok(())
+}
+
+/// Gives the page at `page_start`, the last one of the segment area `previous`, the permissions of
+/// both segments that share it. The page moves into an area of its own, so that the rest of either
+/// segment keeps its own permissions. Code and writable data can't share a page.
+fn share_page(address_space: &mut AddressSpace, previous: Vma, page_start: VirtAddr, flags: PageTableFlags) -> Result<(), LoadError> {
+    if previous.kind != VmaKind::Program || previous.end != page_start + FRAME_SIZE {
+        return Err(LoadError::OverlappingSegments);
+    }
+
+    let writable = previous.flags.contains(PageTableFlags::WRITABLE) || flags.contains(PageTableFlags::WRITABLE);
+    let executable = !previous.flags.contains(PageTableFlags::NO_EXECUTE) || !flags.contains(PageTableFlags::NO_EXECUTE);
+    if writable && executable {
+        return Err(LoadError::WritableAndExecutable);
+    }
+
+    let shared = Vma::new(page_start, previous.end, user_flags(writable, executable), VmaKind::Program);
+
+    address_space.vmas.remove(previous.start).map_err(|_| LoadError::OverlappingSegments)?;
+    if previous.start < page_start {
+        let rest = Vma::new(previous.start, page_start, previous.flags, VmaKind::Program);
+        address_space.vmas.insert(rest).map_err(|_| LoadError::OverlappingSegments)?;
+    }
+    address_space.vmas.insert(shared).map_err(|_| LoadError::OverlappingSegments)?;
+
+    address_space.update_flags(Page::containing_address(page_start), shared.flags)
+        .expect("Segment pages are mapped when they are loaded");
+    Ok(())
+}
+
+/// Copies `bytes` into mapped pages of `address_space`, which doesn't have to be active.
+fn write_bytes(address_space: &AddressSpace, address: VirtAddr, bytes: &[u8]) {
+    let mut written = 0;
+
+    while written < bytes.len() {
+        let current = address + written as u64;
+        let physical = address_space.translate(current).expect("Writing to an unmapped user page");
+        let len = (FRAME_SIZE - u64::from(current.page_offset())).min((bytes.len() - written) as u64) as usize;
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                bytes[written..].as_ptr(),
+                memory::phys_to_virt(physical).as_mut_ptr::<u8>(),
+                len,
+            );
+        }
+
+        written += len;
+    }
+}
+
+/// Builds the initial stack, from the top: the strings, then (16 byte aligned) argc, argv, envp and
+/// the auxiliary vector. Returns the stack pointer, which points at argc.
+fn setup_stack(address_space: &mut AddressSpace, elf: &Elf, argv: &[&str], envp: &[&str]) -> Result<VirtAddr, LoadError> {
+    let top = VirtAddr::new(USER_STACK_TOP);
+    let start = top - USER_STACK_SIZE;
+    let vma = Vma::new(start, top, user_flags(true, false), VmaKind::Stack { max_size: USER_STACK_MAX_SIZE });
+
+    address_space.vmas.insert(vma).map_err(|_| LoadError::OverlappingSegments)?;
+
+    for page in Page::range(Page::containing_address(start), Page::containing_address(top)) {
+        address_space.map_zeroed(page, vma.flags).map_err(|_| LoadError::OutOfMemory)?;
+    }
+
+    let mut pointer = top.as_u64();
+    let mut push_string = |string: &str| -> Result<u64, LoadError> {
+        //strings are passed NUL terminated
+        pointer = pointer.checked_sub(string.len() as u64 + 1).filter(|pointer| *pointer >= start.as_u64())
+            .ok_or(LoadError::ArgumentsTooLarge)?;
+
+        write_bytes(address_space, VirtAddr::new(pointer), string.as_bytes());
+        Ok(pointer)
+    };
+
+    let argv_pointers = argv.iter().map(|argument| push_string(argument)).collect::<Result<Vec<u64>, _>>()?;
+    let envp_pointers = envp.iter().map(|variable| push_string(variable)).collect::<Result<Vec<u64>, _>>()?;
+
+    let mut words = Vec::new();
+    words.push(argv.len() as u64);
+    words.extend_from_slice(&argv_pointers);
+    words.push(0);
+    words.extend_from_slice(&envp_pointers);
+    words.push(0);
+
+    let mut auxiliary = Vec::new();
+    if let Some(address) = elf.program_headers_address() {
+        auxiliary.push((AuxType::PROGRAM_HEADERS, address));
+    }
+    auxiliary.push((AuxType::PROGRAM_HEADER_SIZE, size_of::<ProgramHeader>() as u64));
+    auxiliary.push((AuxType::PROGRAM_HEADER_COUNT, elf.header().program_header_count as u64));
+    auxiliary.push((AuxType::PAGE_SIZE, FRAME_SIZE));
+    auxiliary.push((AuxType::ENTRY, elf.entry()));
+    auxiliary.push((AuxType::NULL, 0));
+
+    for (key, value) in auxiliary {
+        words.push(key);
+        words.push(value);
+    }
+
+    let size = (words.len() * size_of::<u64>()) as u64;
+    let stack_pointer = pointer.checked_sub(size).map(|pointer| pointer & !0xF).filter(|pointer| *pointer >= start.as_u64())
+        .ok_or(LoadError::ArgumentsTooLarge)?;
+
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    write_bytes(address_space, VirtAddr::new(stack_pointer), &bytes);
+
+    Ok(VirtAddr::new(stack_pointer))
+}
+
+#[test_case]
+fn loaded_program_runs_in_user_mode() {
+    use crate::kernel::lib::testing;
+
+    //see programs/args.s, `results` is placed at this address by its linker script
+    const RESULTS: u64 = 0x80_0003_0000;
+    const MESSAGE: &str = "hello from an ELF\n";
+
+    let program = load(include_bytes!("programs/args"), &["args", MESSAGE], &["A=1", "B=2"]).unwrap();
+    let (entry, stack_pointer) = (program.entry.as_u64(), program.stack_pointer.as_u64());
+    let mut address_space = program.address_space;
+
+    testing::run_user_mode(&mut address_space, entry, stack_pointer);
+
+    let results = address_space.translate(VirtAddr::new(RESULTS)).unwrap();
+    let results = unsafe { &*memory::phys_to_virt(results).as_ptr::<[u64; 6]>() };
+
+    assert_eq!(results[0], 0, "stack pointer is not 16 byte aligned");
+    assert_eq!(results[1], 2);
+    assert_eq!(results[2], MESSAGE.len() as u64);
+    assert_eq!(results[3], 2);
+    assert_eq!(results[4], FRAME_SIZE);
+    assert_eq!(results[5], 0x1122334455667788);
+}
+
+#[test_case]
+fn segments_are_mapped_with_their_permissions() {
+    let program = load(include_bytes!("programs/args"), &[], &[]).unwrap();
+    let flags = |address: u64| program.address_space.translate_page(Page::containing_address(VirtAddr::new(address))).unwrap().1;
+
+    let text = flags(0x80_0001_0000);
+    let rodata = flags(0x80_0001_1000);
+    let data = flags(0x80_0003_0000);
+    //bss beyond the file contents
+    let bss = flags(0x80_0003_2000);
+
+    assert!(text.contains(PageTableFlags::USER_ACCESSIBLE) && !text.contains(PageTableFlags::WRITABLE));
+    assert!(!text.contains(PageTableFlags::NO_EXECUTE));
+    assert!(!rodata.contains(PageTableFlags::WRITABLE));
+    assert!(data.contains(PageTableFlags::WRITABLE) && bss.contains(PageTableFlags::WRITABLE));
+}
+
+#[test_case]
+fn segments_may_share_a_page() {
+    use elf::{SegmentFlags, SegmentType};
+
+    const TEXT: u64 = 0x80_0001_0000;
+    let segment = |address: u64, memory_size: u64, flags: u32| ProgramHeader {
+        segment_type: SegmentType::LOAD,
+        flags: SegmentFlags::READ | flags,
+        offset: 0,
+        virtual_address: address,
+        physical_address: address,
+        file_size: 0,
+        memory_size,
+        align: FRAME_SIZE,
+    };
+
+    let elf = Elf::parse(include_bytes!("programs/args")).unwrap();
+    let mut address_space = AddressSpace::new().unwrap();
+    load_segment(&mut address_space, &elf, &segment(TEXT, 0x2800, SegmentFlags::EXECUTE)).unwrap();
+    load_segment(&mut address_space, &elf, &segment(TEXT + 0x2800, 0x1000, 0)).unwrap();
+    load_segment(&mut address_space, &elf, &segment(TEXT + 0x3800, 0x1000, SegmentFlags::WRITE)).unwrap();
+
+    let flags = |address: u64| address_space.translate_page(Page::containing_address(VirtAddr::new(address))).unwrap().1;
+    let (text, code_and_rodata, rodata_and_data) = (flags(TEXT + 0x1000), flags(TEXT + 0x2000), flags(TEXT + 0x3000));
+
+    assert!(!text.contains(PageTableFlags::WRITABLE) && !text.contains(PageTableFlags::NO_EXECUTE));
+    assert!(!code_and_rodata.contains(PageTableFlags::WRITABLE) && !code_and_rodata.contains(PageTableFlags::NO_EXECUTE));
+    assert!(rodata_and_data.contains(PageTableFlags::WRITABLE));
+    assert_eq!(address_space.vmas.find(VirtAddr::new(TEXT + 0x2000)).unwrap().flags, code_and_rodata);
+
+    //only the last page of a segment can be shared
+    let overlapping = segment(TEXT + 0x800, 0x1000, 0);
+    assert_eq!(load_segment(&mut address_space, &elf, &overlapping), Err(LoadError::OverlappingSegments));
+
+    //code followed by writable data on the same page would make it writable and executable
+    let mut writable_code = AddressSpace::new().unwrap();
+    load_segment(&mut writable_code, &elf, &segment(TEXT, 0x2800, SegmentFlags::EXECUTE)).unwrap();
+    let data = segment(TEXT + 0x2800, 0x1000, SegmentFlags::WRITE);
+    assert_eq!(load_segment(&mut writable_code, &elf, &data), Err(LoadError::WritableAndExecutable));
+}
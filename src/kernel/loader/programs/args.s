@@ -0,0 +1,75 @@
+# Test program for the ELF loader. It reports what it found on its initial stack in `results`
+# (see link.ld for the fixed address), prints argv[1] and exits. Rebuild with build.sh.
+
+    .set SYS_EXIT, 0
+    .set SYS_WRITE, 1
+    .set STDOUT, 1
+    .set AT_NULL, 0
+    .set AT_PAGESZ, 6
+
+    .section .text
+    .global _start
+_start:
+    mov %rsp, %rax
+    and $15, %rax
+    mov %rax, results + 0(%rip)         # stack alignment, 0 expected
+
+    mov (%rsp), %rcx
+    mov %rcx, results + 8(%rip)         # argc
+
+    # print argv[1]
+    mov 16(%rsp), %rsi
+    xor %edx, %edx
+1:  cmpb $0, (%rsi, %rdx)
+    je 2f
+    inc %rdx
+    jmp 1b
+2:  mov $SYS_WRITE, %eax
+    mov $STDOUT, %edi
+    syscall
+    mov %rax, results + 16(%rip)        # bytes written
+
+    # skip argv and its null pointer, then count envp
+    mov (%rsp), %rcx
+    lea 16(%rsp, %rcx, 8), %rbx
+    xor %ecx, %ecx
+3:  cmpq $0, (%rbx)
+    lea 8(%rbx), %rbx
+    je 4f
+    inc %rcx
+    jmp 3b
+4:  mov %rcx, results + 24(%rip)        # envc
+
+    # look up AT_PAGESZ in the auxiliary vector
+5:  mov (%rbx), %rax
+    cmp $AT_NULL, %rax
+    je 7f
+    cmp $AT_PAGESZ, %rax
+    je 6f
+    add $16, %rbx
+    jmp 5b
+6:  mov 8(%rbx), %rax
+    mov %rax, results + 32(%rip)        # page size
+
+    # initialized data and zeroed bss have to be there
+7:  mov magic(%rip), %rax
+    add zeroed(%rip), %rax
+    mov %rax, results + 40(%rip)
+
+    mov $SYS_EXIT, %eax
+    xor %edi, %edi
+    syscall
+
+    .section .rodata
+    .ascii "read only"
+
+    .section .data
+    .global results
+results:
+    .fill 6, 8, 0xffffffffffffffff
+magic:
+    .quad 0x1122334455667788
+
+    .section .bss
+zeroed:
+    .zero 4096 * 2
@@ -0,0 +1,10 @@
+#!/bin/sh
+# Builds the test programs embedded by the loader and process tests, needs GNU as and ld.
+set -e
+cd "$(dirname "$0")"
+
+for program in args status signal; do
+    as --64 -o $program.o $program.s
+    ld -static -nostdlib -z max-page-size=0x1000 --build-id=none -T link.ld -o $program $program.o
+    rm $program.o
+done
@@ -0,0 +1,48 @@
+# Test program for signal handlers. It catches the segmentation fault of a write to address 0,
+# the handler skips the write and stores 7 in the saved rcx, which the program exits with.
+# Returning through sysret would clobber rcx, so this also checks that sigreturn restores all
+# registers. Rebuild with build.sh.
+
+    .set SYS_EXIT, 0
+    .set SYS_SIGNAL, 9
+    .set SYS_SIGRETURN, 10
+    .set SIGSEGV, 11
+    # offsets into the saved registers, which are laid out like the kernel's StackFrame
+    .set SAVED_RCX, 13 * 8
+    .set SAVED_RIP, 15 * 8
+
+    .section .text
+    .global _start
+_start:
+    mov $SYS_SIGNAL, %eax
+    mov $SIGSEGV, %edi
+    lea handler(%rip), %rsi
+    lea restorer(%rip), %rdx
+    syscall
+    test %rax, %rax
+    jnz fail
+
+    xor %ecx, %ecx
+    movq $0, 0
+resume:
+    mov $SYS_EXIT, %eax
+    mov %ecx, %edi
+    syscall
+
+# handler(signal, registers)
+handler:
+    cmp $SIGSEGV, %rdi
+    jne fail
+    lea resume(%rip), %rax
+    mov %rax, SAVED_RIP(%rsi)
+    movq $7, SAVED_RCX(%rsi)
+    ret
+
+restorer:
+    mov $SYS_SIGRETURN, %eax
+    syscall
+
+fail:
+    mov $SYS_EXIT, %eax
+    mov $1, %edi
+    syscall
@@ -0,0 +1,14 @@
+# Test program for processes. Without arguments it exits with status 42, with any argument it
+# writes to address 0, which has to kill it. Rebuild with build.sh.
+
+    .set SYS_EXIT, 0
+
+    .section .text
+    .global _start
+_start:
+    cmpq $1, (%rsp)
+    je 1f
+    movq $0, 0
+1:  mov $SYS_EXIT, %eax
+    mov $42, %edi
+    syscall
@@ -0,0 +1,291 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::PhysAddr;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
+
+pub const FRAME_SIZE: u64 = 4096;
+const BITS_PER_WORD: usize = 64;
+
+/// A range of usable physical memory, `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysRegion {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl PhysRegion {
+    pub fn frame_count(&self) -> usize {
+        ((self.end - self.start) / FRAME_SIZE) as usize
+    }
+}
+
+/// The regions of the bootloader's memory map that are free for the kernel to use.
+pub fn usable_regions(memory_map: &'static MemoryMap) -> impl Iterator<Item = PhysRegion> + Clone {
+    memory_map.iter()
+        .filter(|region| region.region_type == MemoryRegionType::Usable)
+        .map(|region| PhysRegion { start: region.range.start_addr(), end: region.range.end_addr() })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    pub used: usize,
+}
+
+/// Physical frame allocator with one bit per 4 KiB frame, a set bit means the frame is in use.
+/// Frames that are not part of a usable region start out set and can never be handed out.
+/// Allocated frames carry a reference count, so that they can be shared between address spaces.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    reference_counts: &'static mut [u16],
+    total: usize,
+    free: usize,
+    //word to start the next search at, everything before it is known to be in use
+    next: usize,
+}
+
+impl BitmapFrameAllocator {
+    /// Number of bitmap words needed to track physical memory up to `end`.
+    pub fn words_needed(end: u64) -> usize {
+        let frames = ((end + FRAME_SIZE - 1) / FRAME_SIZE) as usize;
+        (frames + BITS_PER_WORD - 1) / BITS_PER_WORD
+    }
+
+    /// Number of reference counts needed for the frames of `words_needed(end)` bitmap words.
+    pub fn reference_counts_needed(end: u64) -> usize {
+        Self::words_needed(end) * BITS_PER_WORD
+    }
+
+    /// Builds the allocator on top of `bitmap` and `reference_counts`, making all frames of `regions`
+    /// that the bitmap covers allocatable.
+    pub fn new<I: Iterator<Item = PhysRegion>>(bitmap: &'static mut [u64], reference_counts: &'static mut [u16], regions: I) -> BitmapFrameAllocator {
+        assert!(reference_counts.len() >= bitmap.len() * BITS_PER_WORD, "Not enough reference counts for the bitmap");
+
+        for word in bitmap.iter_mut() {
+            *word = u64::MAX;
+        }
+
+        for count in reference_counts.iter_mut() {
+            *count = 0;
+        }
+
+        let mut allocator = BitmapFrameAllocator {
+            bitmap,
+            reference_counts,
+            total: 0,
+            free: 0,
+            next: 0,
+        };
+
+        for region in regions {
+            //only whole frames are usable
+            let first = (region.start + FRAME_SIZE - 1) / FRAME_SIZE;
+            let last = region.end / FRAME_SIZE;
+
+            for frame in first..last {
+                let index = frame as usize;
+
+                if index < allocator.capacity() && allocator.is_used(index) {
+                    allocator.set_used(index, false);
+                    allocator.total += 1;
+                    allocator.free += 1;
+                }
+            }
+        }
+
+        allocator
+    }
+
+    fn capacity(&self) -> usize {
+        self.bitmap.len() * BITS_PER_WORD
+    }
+
+    fn is_used(&self, index: usize) -> bool {
+        self.bitmap[index / BITS_PER_WORD] & (1 << (index % BITS_PER_WORD)) != 0
+    }
+
+    fn set_used(&mut self, index: usize, used: bool) {
+        let word = &mut self.bitmap[index / BITS_PER_WORD];
+        let bit = 1 << (index % BITS_PER_WORD);
+
+        if used {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    fn frame(index: usize) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+    }
+
+    fn index(frame: PhysFrame) -> usize {
+        (frame.start_address().as_u64() / FRAME_SIZE) as usize
+    }
+
+    /// Takes `frame` out of the pool, e.g. because it holds data that has to survive.
+    /// Returns false if the frame was not free.
+    pub fn reserve(&mut self, frame: PhysFrame) -> bool {
+        let index = Self::index(frame);
+
+        if index >= self.capacity() || self.is_used(index) {
+            return false;
+        }
+
+        self.set_used(index, true);
+        self.reference_counts[index] = 1;
+        self.free -= 1;
+        true
+    }
+
+    pub fn allocate(&mut self) -> Option<PhysFrame> {
+        if self.free == 0 {
+            return None;
+        }
+
+        for word_index in (self.next..self.bitmap.len()).chain(0..self.next) {
+            let word = self.bitmap[word_index];
+
+            if word != u64::MAX {
+                let index = word_index * BITS_PER_WORD + (!word).trailing_zeros() as usize;
+
+                self.set_used(index, true);
+                self.reference_counts[index] = 1;
+                self.free -= 1;
+                self.next = word_index;
+
+                return Some(Self::frame(index));
+            }
+        }
+
+        None
+    }
+
+    /// Adds a reference to an allocated frame and returns the new count, None if it can't take any more.
+    pub fn share(&mut self, frame: PhysFrame) -> Option<u16> {
+        let index = Self::index(frame);
+
+        assert!(index < self.capacity() && self.reference_counts[index] > 0, "sharing frame {:?} which is not allocated", frame);
+
+        //a wrapped count would free the frame while it is still mapped
+        self.reference_counts[index] = self.reference_counts[index].checked_add(1)?;
+        Some(self.reference_counts[index])
+    }
+
+    /// References to `frame`, 0 if it is free or not managed by the allocator.
+    pub fn reference_count(&self, frame: PhysFrame) -> u16 {
+        self.reference_counts.get(Self::index(frame)).copied().unwrap_or(0)
+    }
+
+    /// Drops a reference to `frame`, it only becomes free again with the last one.
+    pub fn free(&mut self, frame: PhysFrame) {
+        let index = Self::index(frame);
+
+        assert!(index < self.capacity() && self.is_used(index), "freeing frame {:?} which is not allocated", frame);
+
+        if self.reference_counts[index] > 1 {
+            self.reference_counts[index] -= 1;
+            return;
+        }
+
+        self.reference_counts[index] = 0;
+        self.set_used(index, false);
+        self.free += 1;
+
+        if index / BITS_PER_WORD < self.next {
+            self.next = index / BITS_PER_WORD;
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            free: self.free,
+            used: self.total - self.free,
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.allocate()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.free(frame)
+    }
+}
+
+#[cfg(test)]
+fn test_allocator() -> BitmapFrameAllocator {
+    use alloc::vec;
+
+    //frames 1-3 and 64-65, frame 0 and the partial frame at the end of the second region are not usable
+    let regions = [
+        PhysRegion { start: FRAME_SIZE, end: 4 * FRAME_SIZE },
+        PhysRegion { start: 64 * FRAME_SIZE, end: 66 * FRAME_SIZE + 100 },
+    ];
+
+    BitmapFrameAllocator::new(vec![0; 2].leak(), vec![0; 128].leak(), regions.iter().copied())
+}
+
+#[cfg(test)]
+fn with_kernel_allocator<R>(f: impl FnOnce(&mut BitmapFrameAllocator) -> R) -> R {
+    use x86_64::instructions::interrupts;
+    use crate::kernel::memory::FRAME_ALLOCATOR;
+
+    interrupts::without_interrupts(|| f(FRAME_ALLOCATOR.lock().as_mut().expect("Frame allocator is not initialized")))
+}
+
+#[test_case]
+fn only_usable_frames_are_allocated() {
+    let mut allocator = test_allocator();
+
+    assert_eq!(allocator.stats(), FrameStats { total: 5, free: 5, used: 0 });
+
+    let expected = [1, 2, 3, 64, 65];
+    for index in expected {
+        assert_eq!(allocator.allocate(), Some(BitmapFrameAllocator::frame(index)));
+    }
+
+    assert_eq!(allocator.allocate(), None);
+    assert_eq!(allocator.stats(), FrameStats { total: 5, free: 0, used: 5 });
+}
+
+#[test_case]
+fn freed_frames_are_reused() {
+    with_kernel_allocator(|allocator| {
+        let used = allocator.stats().used;
+        let first = allocator.allocate().unwrap();
+        let second = allocator.allocate().unwrap();
+        allocator.free(first);
+
+        assert_eq!(allocator.stats().used, used + 1);
+        assert_eq!(allocator.allocate(), Some(first));
+
+        allocator.free(first);
+        allocator.free(second);
+        assert_eq!(allocator.stats().used, used);
+    });
+}
+
+#[test_case]
+fn reserved_frames_are_skipped() {
+    with_kernel_allocator(|allocator| {
+        let frame = allocator.allocate().unwrap();
+        allocator.free(frame);
+
+        assert!(allocator.reserve(frame));
+        assert!(!allocator.reserve(frame));
+        //the bootloader never reports frame 0 as usable
+        assert!(!allocator.reserve(BitmapFrameAllocator::frame(0)));
+
+        let next = allocator.allocate().unwrap();
+        assert_ne!(next, frame);
+
+        allocator.free(next);
+        allocator.free(frame);
+    });
+}
@@ -0,0 +1,206 @@
+use core::alloc::Layout;
+use core::mem;
+use core::ptr;
+
+//every free region starts with its own list node, so smaller pieces can't be tracked
+const MIN_REGION_SIZE: usize = mem::size_of::<ListNode>();
+
+struct ListNode {
+    size: usize,
+    next: Option<&'static mut ListNode>,
+}
+
+impl ListNode {
+    const fn new(size: usize) -> ListNode {
+        ListNode { size, next: None }
+    }
+
+    fn start(&self) -> usize {
+        self as *const ListNode as usize
+    }
+
+    fn end(&self) -> usize {
+        self.start() + self.size
+    }
+}
+
+/// First fit allocator over a list of free regions, sorted by address so that neighbours
+/// can be merged again when memory is freed.
+pub struct LinkedListAllocator {
+    head: ListNode,
+    free: usize,
+}
+
+impl LinkedListAllocator {
+    pub const fn new() -> LinkedListAllocator {
+        LinkedListAllocator {
+            head: ListNode::new(0),
+            free: 0,
+        }
+    }
+
+    /// Number of bytes in the free list.
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// Size and alignment a layout really occupies, large enough to hold a list node once freed.
+    pub fn size_align(layout: Layout) -> (usize, usize) {
+        let layout = layout
+            .align_to(mem::align_of::<ListNode>())
+            .expect("Adjusting the alignment failed")
+            .pad_to_align();
+
+        (layout.size().max(MIN_REGION_SIZE), layout.align())
+    }
+
+    /// Hands the memory `[address, address + size)` to the allocator.
+    /// The memory must be unused and valid for the lifetime of the allocator.
+    pub unsafe fn add_free_region(&mut self, address: usize, size: usize) {
+        assert_eq!(align_up(address, mem::align_of::<ListNode>()), address, "Free region is not aligned");
+        assert!(size >= MIN_REGION_SIZE, "Free region is too small");
+
+        self.free += size;
+
+        let mut previous = &mut self.head;
+        while previous.next.as_ref().map_or(false, |next| next.start() < address) {
+            previous = previous.next.as_mut().unwrap();
+        }
+
+        //merge with the following region
+        let mut size = size;
+        if previous.next.as_ref().map_or(false, |next| next.start() == address + size) {
+            let next = previous.next.take().unwrap();
+            size += next.size;
+            previous.next = next.next.take();
+        }
+
+        //merge with the preceding region, the head is not a real region
+        if previous.size != 0 && previous.end() == address {
+            previous.size += size;
+            return;
+        }
+
+        let node = address as *mut ListNode;
+        ptr::write(node, ListNode { size, next: previous.next.take() });
+        previous.next = Some(&mut *node);
+    }
+
+    /// Start of an allocation of `size` bytes with `align` inside `region`, if it fits.
+    /// Whatever is left on either side has to be big enough to become a region of its own.
+    fn allocation_start(region: &ListNode, size: usize, align: usize) -> Option<usize> {
+        let mut start = align_up(region.start(), align);
+
+        while start != region.start() && start - region.start() < MIN_REGION_SIZE {
+            start += align;
+        }
+
+        let end = start.checked_add(size)?;
+        if end > region.end() {
+            return None;
+        }
+
+        let excess = region.end() - end;
+        if excess > 0 && excess < MIN_REGION_SIZE {
+            return None;
+        }
+
+        Some(start)
+    }
+
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::size_align(layout);
+
+        let mut previous = &mut self.head;
+        loop {
+            let start = match previous.next.as_ref() {
+                Some(region) => Self::allocation_start(region, size, align),
+                None => return ptr::null_mut(),
+            };
+
+            match start {
+                Some(start) => {
+                    let region = previous.next.take().unwrap();
+                    let (region_start, region_end) = (region.start(), region.end());
+                    previous.next = region.next.take();
+                    self.free -= region_end - region_start;
+
+                    unsafe {
+                        if start > region_start {
+                            self.add_free_region(region_start, start - region_start);
+                        }
+                        if start + size < region_end {
+                            self.add_free_region(start + size, region_end - start - size);
+                        }
+                    }
+
+                    return start as *mut u8;
+                }
+                None => previous = previous.next.as_mut().unwrap(),
+            }
+        }
+    }
+
+    /// Returns memory from `allocate` with the same `layout`.
+    pub unsafe fn deallocate(&mut self, pointer: *mut u8, layout: Layout) {
+        let (size, _) = Self::size_align(layout);
+        self.add_free_region(pointer as usize, size);
+    }
+}
+
+pub fn align_up(address: usize, align: usize) -> usize {
+    (address + align - 1) & !(align - 1)
+}
+
+//tests run on a frame from the kernel's frame allocator, reached through the physical memory mapping
+#[cfg(test)]
+fn test_allocator(frame: x86_64::structures::paging::PhysFrame) -> LinkedListAllocator {
+    use crate::kernel::memory::{self, frame::FRAME_SIZE};
+
+    let mut allocator = LinkedListAllocator::new();
+    unsafe { allocator.add_free_region(memory::phys_to_virt(frame.start_address()).as_u64() as usize, FRAME_SIZE as usize) };
+    allocator
+}
+
+#[test_case]
+fn freed_regions_are_merged() {
+    use crate::kernel::memory;
+
+    let frame = memory::allocate_frame().expect("Out of physical memory");
+    let mut allocator = test_allocator(frame);
+    let size = allocator.free();
+    let layout = Layout::from_size_align(256, 8).unwrap();
+
+    let first = allocator.allocate(layout);
+    let second = allocator.allocate(layout);
+    assert!(!first.is_null() && !second.is_null());
+    assert_eq!(allocator.free(), size - 512);
+
+    unsafe {
+        allocator.deallocate(first, layout);
+        allocator.deallocate(second, layout);
+    }
+
+    //only a single region covering everything can satisfy this
+    let all = Layout::from_size_align(size, 8).unwrap();
+    assert_eq!(allocator.allocate(all), first);
+
+    memory::free_frame(frame);
+}
+
+#[test_case]
+fn alignment_is_respected() {
+    use crate::kernel::memory;
+
+    let frame = memory::allocate_frame().expect("Out of physical memory");
+    let mut allocator = test_allocator(frame);
+
+    //the frame itself is page aligned, so skip past its start to make the alignment matter
+    let padding = allocator.allocate(Layout::from_size_align(64, 8).unwrap());
+    let pointer = allocator.allocate(Layout::from_size_align(64, 1024).unwrap());
+    assert!(!padding.is_null() && !pointer.is_null());
+    assert_eq!(pointer as usize % 1024, 0);
+    assert!(allocator.allocate(Layout::from_size_align(8192, 8).unwrap()).is_null());
+
+    memory::free_frame(frame);
+}
@@ -0,0 +1,266 @@
+#[cfg(test)]
+use alloc::{boxed::Box, vec::Vec};
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags};
+use x86_64::VirtAddr;
+use linked_list::{align_up, LinkedListAllocator};
+use slab::{Slab, BLOCK_SIZES, CHUNK_SIZE};
+use crate::kernel::memory::{self, frame::FRAME_SIZE, paging};
+
+pub mod linked_list;
+pub mod slab;
+
+/// The heap lives in its own level 4 entry of the kernel half, so it is visible in every address space.
+pub const HEAP_START: u64 = 0xFFFF_9000_0000_0000;
+pub const HEAP_MAX_SIZE: u64 = 64 * 1024 * 1024;
+pub const HEAP_INITIAL_SIZE: u64 = 1024 * 1024;
+//the heap grows by at least this much at a time, so that small allocations don't map page by page
+const HEAP_MIN_GROWTH: u64 = 256 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// Bytes of memory backing the heap.
+    pub size: usize,
+    /// Bytes handed out to callers, as requested by their layouts.
+    pub used: usize,
+    /// Bytes in the free list of the linked list allocator.
+    pub free: usize,
+    /// Bytes in freed slab blocks, available for small allocations only.
+    pub cached: usize,
+    pub allocations: usize,
+}
+
+/// Slab caches for small allocations on top of a linked list allocator for everything else,
+/// which also provides the chunks the caches are filled with.
+pub struct Heap {
+    list: LinkedListAllocator,
+    slabs: [Slab; BLOCK_SIZES.len()],
+    size: usize,
+    used: usize,
+    allocations: usize,
+}
+
+impl Heap {
+    pub const fn new() -> Heap {
+        Heap {
+            list: LinkedListAllocator::new(),
+            slabs: [
+                Slab::new(BLOCK_SIZES[0]),
+                Slab::new(BLOCK_SIZES[1]),
+                Slab::new(BLOCK_SIZES[2]),
+                Slab::new(BLOCK_SIZES[3]),
+                Slab::new(BLOCK_SIZES[4]),
+                Slab::new(BLOCK_SIZES[5]),
+                Slab::new(BLOCK_SIZES[6]),
+                Slab::new(BLOCK_SIZES[7]),
+                Slab::new(BLOCK_SIZES[8]),
+            ],
+            size: 0,
+            used: 0,
+            allocations: 0,
+        }
+    }
+
+    /// Adds `[start, start + size)` to the heap, the memory must be mapped and unused.
+    pub unsafe fn extend(&mut self, start: usize, size: usize) {
+        self.list.add_free_region(start, size);
+        self.size += size;
+    }
+
+    /// Returns a null pointer if the heap is out of memory.
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let pointer = match Slab::index_for(layout.size(), layout.align()) {
+            Some(index) => {
+                let slab = &mut self.slabs[index];
+
+                if slab.free() == 0 {
+                    let chunk = self.list.allocate(Layout::from_size_align(CHUNK_SIZE, CHUNK_SIZE).unwrap());
+                    if chunk.is_null() {
+                        return ptr::null_mut();
+                    }
+                    unsafe { slab.add_chunk(chunk) };
+                }
+
+                slab.allocate()
+            }
+            None => self.list.allocate(layout),
+        };
+
+        if !pointer.is_null() {
+            self.used += layout.size();
+            self.allocations += 1;
+        }
+
+        pointer
+    }
+
+    /// Returns memory from `allocate` with the same `layout`.
+    pub unsafe fn deallocate(&mut self, pointer: *mut u8, layout: Layout) {
+        match Slab::index_for(layout.size(), layout.align()) {
+            Some(index) => self.slabs[index].deallocate(pointer),
+            None => self.list.deallocate(pointer, layout),
+        }
+
+        self.used -= layout.size();
+        self.allocations -= 1;
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        HeapStats {
+            size: self.size,
+            used: self.used,
+            free: self.list.free(),
+            cached: self.slabs.iter().map(|slab| slab.free() * slab.block_size()).sum(),
+            allocations: self.allocations,
+        }
+    }
+}
+
+/// Maps the pages of `[start, start + size)` to fresh frames and returns how many bytes were mapped,
+/// which is less than `size` if physical memory runs out.
+fn map_heap_pages(start: u64, size: u64) -> u64 {
+    let mut mapper = paging::kernel_mapper();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    let mut mapped = 0;
+
+    while mapped < size {
+        let page = Page::containing_address(VirtAddr::new(start + mapped));
+        let frame = match memory::allocate_frame() {
+            Some(frame) => frame,
+            None => break,
+        };
+
+        match unsafe { mapper.map_to(page, frame, flags, &mut paging::GlobalFrameAllocator) } {
+            Ok(flush) => flush.flush(),
+            Err(_) => {
+                memory::free_frame(frame);
+                break;
+            }
+        }
+
+        mapped += FRAME_SIZE;
+    }
+
+    mapped
+}
+
+/// Maps more memory at the end of the heap, enough for at least `layout`.
+fn grow(heap: &mut Heap, layout: Layout) -> bool {
+    let end = HEAP_START + heap.size() as u64;
+    let (size, align) = LinkedListAllocator::size_align(layout);
+    //an allocation for a slab cache needs a whole aligned chunk
+    let needed = (size.max(CHUNK_SIZE) + align.max(CHUNK_SIZE)) as u64;
+    let size = (align_up(needed as usize, FRAME_SIZE as usize) as u64)
+        .max(HEAP_MIN_GROWTH)
+        .min(HEAP_START + HEAP_MAX_SIZE - end);
+
+    if size == 0 {
+        return false;
+    }
+
+    let mapped = map_heap_pages(end, size);
+    if mapped > 0 {
+        unsafe { heap.extend(end as usize, mapped as usize) };
+    }
+
+    mapped > 0
+}
+
+/// The kernel's global allocator, which maps more of the heap region when it runs out.
+pub struct KernelHeap {
+    heap: Mutex<Heap>,
+}
+
+impl KernelHeap {
+    pub const fn new() -> KernelHeap {
+        KernelHeap {
+            heap: Mutex::new(Heap::new()),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        //interrupt handlers allocate as well, they must not find the lock taken
+        interrupts::without_interrupts(|| {
+            let mut heap = self.heap.lock();
+            let mut pointer = heap.allocate(layout);
+
+            //the heap may have to grow more than once, when the new memory doesn't merge with a big enough free region
+            while pointer.is_null() && grow(&mut heap, layout) {
+                pointer = heap.allocate(layout);
+            }
+
+            pointer
+        })
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        interrupts::without_interrupts(|| self.heap.lock().deallocate(pointer, layout));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: KernelHeap = KernelHeap::new();
+
+/// Maps the initial part of the heap region, paging has to be set up already.
+pub fn init() {
+    let mapped = map_heap_pages(HEAP_START, HEAP_INITIAL_SIZE);
+    assert_eq!(mapped, HEAP_INITIAL_SIZE, "Out of memory while mapping the kernel heap");
+
+    interrupts::without_interrupts(|| unsafe {
+        ALLOCATOR.heap.lock().extend(HEAP_START as usize, mapped as usize)
+    });
+}
+
+pub fn stats() -> HeapStats {
+    interrupts::without_interrupts(|| ALLOCATOR.heap.lock().stats())
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    panic!("Kernel heap allocation failed: {:?}, {:?}", layout, stats())
+}
+
+#[test_case]
+fn boxes_and_vectors_work() {
+    let before = stats();
+
+    {
+        let value = Box::new(41);
+        let mut vector: Vec<u64> = (0..1000).collect();
+        vector.push(*value + 1);
+
+        assert_eq!(vector.iter().sum::<u64>(), 999 * 1000 / 2 + 42);
+        assert!(stats().used > before.used);
+    }
+
+    assert_eq!(stats().used, before.used);
+    assert_eq!(stats().allocations, before.allocations);
+}
+
+#[test_case]
+fn heap_grows_on_demand() {
+    let size = HEAP_INITIAL_SIZE as usize * 2;
+    let vector = alloc::vec![0xAAu8; size];
+
+    assert!(stats().size > size);
+    assert!(vector.iter().all(|&byte| byte == 0xAA));
+}
+
+#[test_case]
+fn small_allocations_are_reused() {
+    let first = Box::new(1u64);
+    let address = &*first as *const u64;
+    drop(first);
+
+    let second = Box::new(2u64);
+    assert_eq!(&*second as *const u64, address);
+}
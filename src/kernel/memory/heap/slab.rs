@@ -0,0 +1,104 @@
+use core::ptr;
+
+/// Block sizes of the slab caches, everything larger goes to the linked list allocator.
+pub const BLOCK_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// Size of the chunks a cache takes from the linked list allocator when it runs empty.
+pub const CHUNK_SIZE: usize = 4096;
+
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+/// Cache of equally sized blocks. Freed blocks stay in the cache for the next allocation
+/// of the same size instead of going back to the linked list allocator.
+pub struct Slab {
+    block_size: usize,
+    free_list: *mut FreeBlock,
+    free: usize,
+}
+
+//the blocks are only ever touched behind the heap lock
+unsafe impl Send for Slab {}
+
+impl Slab {
+    pub const fn new(block_size: usize) -> Slab {
+        Slab {
+            block_size,
+            free_list: ptr::null_mut(),
+            free: 0,
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Number of cached blocks.
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// Index of the smallest cache that fits `size` with `align`.
+    pub fn index_for(size: usize, align: usize) -> Option<usize> {
+        let required = size.max(align);
+        BLOCK_SIZES.iter().position(|&block_size| block_size >= required)
+    }
+
+    pub fn allocate(&mut self) -> *mut u8 {
+        let block = self.free_list;
+
+        if !block.is_null() {
+            self.free_list = unsafe { (*block).next };
+            self.free -= 1;
+        }
+
+        block as *mut u8
+    }
+
+    /// Puts a block back into the cache, it has to be `block_size` bytes and aligned to it.
+    pub unsafe fn deallocate(&mut self, pointer: *mut u8) {
+        let block = pointer as *mut FreeBlock;
+        ptr::write(block, FreeBlock { next: self.free_list });
+        self.free_list = block;
+        self.free += 1;
+    }
+
+    /// Splits a `CHUNK_SIZE` chunk aligned to `CHUNK_SIZE` into blocks.
+    pub unsafe fn add_chunk(&mut self, chunk: *mut u8) {
+        for offset in (0..CHUNK_SIZE).step_by(self.block_size).rev() {
+            self.deallocate(chunk.add(offset));
+        }
+    }
+}
+
+#[test_case]
+fn chunks_are_split_into_blocks() {
+    use crate::kernel::memory;
+
+    //a frame is exactly one chunk and aligned like one
+    let frame = memory::allocate_frame().expect("Out of physical memory");
+    let chunk = memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+
+    let mut slab = Slab::new(512);
+    unsafe { slab.add_chunk(chunk) };
+    assert_eq!(slab.free(), CHUNK_SIZE / 512);
+
+    //blocks come out in address order and freed ones are reused first
+    let first = slab.allocate();
+    let second = slab.allocate();
+    assert_eq!((first, second), (chunk, unsafe { chunk.add(512) }));
+
+    unsafe { slab.deallocate(first) };
+    assert_eq!(slab.allocate(), first);
+
+    memory::free_frame(frame);
+}
+
+#[test_case]
+fn sizes_pick_the_smallest_cache() {
+    assert_eq!(Slab::index_for(1, 1), Some(0));
+    assert_eq!(Slab::index_for(24, 8), Some(2));
+    assert_eq!(Slab::index_for(8, 256), Some(5));
+    assert_eq!(Slab::index_for(4096, 8), None);
+}
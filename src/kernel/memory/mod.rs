@@ -0,0 +1,132 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use bootloader::BootInfo;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::{PhysAddr, VirtAddr};
+use frame::{BitmapFrameAllocator, FrameStats, FRAME_SIZE};
+
+pub mod frame;
+pub mod heap;
+pub mod paging;
+pub mod vma;
+
+//the bootloader maps all of physical memory at this offset
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
+
+pub static FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+
+pub fn physical_memory_offset() -> VirtAddr {
+    VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed))
+}
+
+pub fn phys_to_virt(address: PhysAddr) -> VirtAddr {
+    physical_memory_offset() + address.as_u64()
+}
+
+/// Sets up the physical frame allocator from the bootloader's memory map, takes over its page tables
+/// and maps the kernel heap.
+/// The bitmap is stored in the first usable region that is large enough to hold it.
+pub fn init(boot_info: &'static BootInfo) {
+    PHYSICAL_MEMORY_OFFSET.store(boot_info.physical_memory_offset, Ordering::Relaxed);
+
+    let regions = frame::usable_regions(&boot_info.memory_map);
+    let end = regions.clone().map(|region| region.end).max().unwrap_or(0);
+
+    //the reference counts follow right after the bitmap
+    let words = BitmapFrameAllocator::words_needed(end);
+    let counts = BitmapFrameAllocator::reference_counts_needed(end);
+    let bitmap_size = (words * core::mem::size_of::<u64>() + counts * core::mem::size_of::<u16>()) as u64;
+    let bitmap_region = regions.clone()
+        .find(|region| region.end - region.start >= bitmap_size)
+        .expect("No usable memory region is large enough for the frame bitmap");
+
+    let (bitmap, reference_counts) = unsafe {
+        let start = phys_to_virt(PhysAddr::new(bitmap_region.start)).as_mut_ptr::<u64>();
+        (core::slice::from_raw_parts_mut(start, words),
+         core::slice::from_raw_parts_mut(start.add(words) as *mut u16, counts))
+    };
+
+    let mut allocator = BitmapFrameAllocator::new(bitmap, reference_counts, regions);
+
+    let first = PhysFrame::containing_address(PhysAddr::new(bitmap_region.start));
+    let last = PhysFrame::containing_address(PhysAddr::new(bitmap_region.start + bitmap_size + FRAME_SIZE - 1));
+    for frame in PhysFrame::range(first, last) {
+        allocator.reserve(frame);
+    }
+
+    interrupts::without_interrupts(|| *FRAME_ALLOCATOR.lock() = Some(allocator));
+
+    paging::init();
+    heap::init();
+}
+
+pub fn allocate_frame() -> Option<PhysFrame> {
+    interrupts::without_interrupts(|| FRAME_ALLOCATOR.lock().as_mut()?.allocate())
+}
+
+/// Adds a reference to an allocated frame, which then takes one more `free_frame` to be released.
+/// Returns None if the frame already has as many references as it can count.
+pub fn share_frame(frame: PhysFrame) -> Option<u16> {
+    interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_mut().expect("Frame allocator is not initialized").share(frame)
+    })
+}
+
+pub fn frame_reference_count(frame: PhysFrame) -> u16 {
+    interrupts::without_interrupts(|| FRAME_ALLOCATOR.lock().as_ref().map_or(0, |allocator| allocator.reference_count(frame)))
+}
+
+/// Drops a reference to `frame`, it is free again once the last one is gone.
+pub fn free_frame(frame: PhysFrame) {
+    interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_mut().expect("Frame allocator is not initialized").free(frame)
+    });
+}
+
+pub fn frame_stats() -> FrameStats {
+    interrupts::without_interrupts(|| {
+        FRAME_ALLOCATOR.lock().as_ref().map_or(FrameStats { total: 0, free: 0, used: 0 }, |allocator| allocator.stats())
+    })
+}
+
+#[test_case]
+fn allocate_and_free_updates_stats() {
+    let before = frame_stats();
+    let frame = allocate_frame().expect("Out of physical memory");
+
+    assert_eq!(frame_stats().free, before.free - 1);
+    free_frame(frame);
+    assert_eq!(frame_stats(), before);
+}
+
+#[test_case]
+fn shared_frames_are_freed_with_the_last_reference() {
+    let before = frame_stats();
+    let frame = allocate_frame().expect("Out of physical memory");
+    assert_eq!(share_frame(frame), Some(2));
+
+    free_frame(frame);
+    assert_eq!(frame_reference_count(frame), 1);
+    assert_eq!(frame_stats().used, before.used + 1);
+
+    free_frame(frame);
+    assert_eq!(frame_reference_count(frame), 0);
+    assert_eq!(frame_stats(), before);
+}
+
+#[test_case]
+fn reference_counts_do_not_wrap() {
+    let frame = allocate_frame().expect("Out of physical memory");
+    for _ in 1..u16::MAX {
+        share_frame(frame).expect("Reference count is saturated too early");
+    }
+
+    assert_eq!(share_frame(frame), None);
+    assert_eq!(frame_reference_count(frame), u16::MAX);
+
+    for _ in 0..u16::MAX {
+        free_frame(frame);
+    }
+    assert_eq!(frame_reference_count(frame), 0);
+}
@@ -0,0 +1,562 @@
+use alloc::vec::Vec;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use spin::Once;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3, Cr3Flags};
+use x86_64::structures::paging::mapper::{FlagUpdateError, MapToError, MappedFrame, TranslateResult, UnmapError};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+use x86_64::instructions::tlb;
+use crate::kernel::arch::x86::interrupts::page_fault::PageFault;
+use crate::kernel::memory::{self, frame::FRAME_SIZE};
+use crate::kernel::memory::vma::{self, StackGrowth, VmaList};
+#[cfg(test)]
+use crate::kernel::memory::vma::{Vma, VmaKind};
+
+/// Lowest user space address, the first level 4 entry belongs to the kernel image.
+pub const USER_SPACE_START: u64 = 0x0000_0080_0000_0000;
+/// End of the lower canonical half, everything above it is kernel space.
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// Marks a page that was writable before its frame got shared, one of the bits left to the OS.
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+const FIRST_USER_ENTRY: usize = 1;
+const FIRST_KERNEL_HIGH_ENTRY: usize = 256;
+
+static KERNEL_LEVEL_4_FRAME: Once<PhysFrame> = Once::new();
+//the address space loaded in CR3, null while the kernel's own page tables are active
+static ACTIVE: AtomicPtr<AddressSpace> = AtomicPtr::new(ptr::null_mut());
+
+/// Hands out frames from the global frame allocator, for page tables created by the mapper.
+pub struct GlobalFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        memory::allocate_frame()
+    }
+}
+
+fn is_user_entry(index: usize) -> bool {
+    (FIRST_USER_ENTRY..FIRST_KERNEL_HIGH_ENTRY).contains(&index)
+}
+
+pub fn is_user_address(address: VirtAddr) -> bool {
+    (USER_SPACE_START..USER_SPACE_END).contains(&address.as_u64())
+}
+
+unsafe fn table_at(frame: PhysFrame) -> &'static mut PageTable {
+    &mut *memory::phys_to_virt(frame.start_address()).as_mut_ptr::<PageTable>()
+}
+
+fn allocate_zeroed_frame() -> Option<PhysFrame> {
+    let frame = memory::allocate_frame()?;
+    unsafe { zero_frame(frame) };
+    Some(frame)
+}
+
+pub unsafe fn zero_frame(frame: PhysFrame) {
+    core::ptr::write_bytes(memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, FRAME_SIZE as usize);
+}
+
+pub unsafe fn copy_frame(source: PhysFrame, destination: PhysFrame) {
+    core::ptr::copy_nonoverlapping(
+        memory::phys_to_virt(source.start_address()).as_ptr::<u8>(),
+        memory::phys_to_virt(destination.start_address()).as_mut_ptr::<u8>(),
+        FRAME_SIZE as usize,
+    );
+}
+
+/// Takes over the bootloader's page tables as the kernel address space.
+/// Every kernel level 4 entry of the higher half gets a level 3 table up front, so that kernel
+/// mappings made later show up in all address spaces, which share these tables.
+pub fn init() {
+    let (frame, _) = Cr3::read();
+    let table = unsafe { table_at(frame) };
+
+    for entry in table.iter_mut().skip(FIRST_KERNEL_HIGH_ENTRY) {
+        if entry.is_unused() {
+            let level_3 = allocate_zeroed_frame().expect("Out of memory while preparing the kernel page tables");
+            entry.set_frame(level_3, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        }
+    }
+
+    //without write protection the kernel's own writes would ignore copy-on-write pages
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+
+    KERNEL_LEVEL_4_FRAME.call_once(|| frame);
+}
+
+/// Switches back to the kernel's page tables, which have no areas to resolve faults with.
+pub fn activate_kernel() {
+    let frame = *KERNEL_LEVEL_4_FRAME.get().expect("Paging is not initialized");
+
+    ACTIVE.store(ptr::null_mut(), Ordering::Release);
+    unsafe { Cr3::write(frame, Cr3Flags::empty()) };
+}
+
+/// Whether the page tables of an `AddressSpace` are loaded rather than the kernel's.
+pub fn is_user_space_active() -> bool {
+    !ACTIVE.load(Ordering::Acquire).is_null()
+}
+
+/// Mapper for the kernel's own page tables.
+pub fn kernel_mapper() -> OffsetPageTable<'static> {
+    let frame = *KERNEL_LEVEL_4_FRAME.get().expect("Paging is not initialized");
+    unsafe { OffsetPageTable::new(table_at(frame), memory::physical_memory_offset()) }
+}
+
+/// Mapper for the page tables that are currently loaded, e.g. those of a user process.
+/// The caller must not keep it across an address space switch.
+pub unsafe fn active_mapper() -> OffsetPageTable<'static> {
+    let (frame, _) = Cr3::read();
+    OffsetPageTable::new(table_at(frame), memory::physical_memory_offset())
+}
+
+/// Translates `address` through the page tables that are currently loaded.
+pub fn translate(address: VirtAddr) -> Option<PhysAddr> {
+    if !is_initialized() {
+        return None;
+    }
+
+    unsafe { active_mapper() }.translate_addr(address)
+}
+
+pub fn is_initialized() -> bool {
+    KERNEL_LEVEL_4_FRAME.get().is_some()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultError {
+    /// No area of the active address space contains the address.
+    NotMapped,
+    /// The area does not permit this kind of access.
+    AccessViolation,
+    /// The access hit the guard pages below a stack.
+    StackOverflow,
+    /// A paging structure entry has a reserved bit set, the page tables are corrupt.
+    ReservedBit,
+    OutOfMemory,
+}
+
+impl FaultError {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultError::NotMapped => "address is not mapped",
+            FaultError::AccessViolation => "access is not permitted",
+            FaultError::StackOverflow => "stack overflow",
+            FaultError::ReservedBit => "reserved bit set in a page table entry",
+            FaultError::OutOfMemory => "out of memory",
+        }
+    }
+}
+
+/// Checks that user code may access `len` bytes at `start` in the active address space: the range
+/// lies in user space and every page is mapped accordingly or belongs to an area that maps it on
+/// the first access. Meant for pointers passed to system calls.
+pub fn is_user_accessible(start: VirtAddr, len: u64, write: bool) -> bool {
+    let end = match start.as_u64().checked_add(len) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    if start.as_u64() < USER_SPACE_START || end > USER_SPACE_END {
+        return false;
+    }
+
+    if len == 0 {
+        return true;
+    }
+
+    let address_space = match unsafe { ACTIVE.load(Ordering::Acquire).as_ref() } {
+        Some(address_space) => address_space,
+        None => return false,
+    };
+
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(VirtAddr::new(end - 1));
+
+    Page::range_inclusive(first, last).all(|page| {
+        //copy on write pages are mapped read only, so the area decides
+        match (address_space.vmas.find(page.start_address()), address_space.translate_page(page)) {
+            (Some(vma), _) => vma.allows(write, true, false),
+            (None, Some((_, flags))) => vma::allows(flags, write, true, false),
+            (None, None) => false,
+        }
+    })
+}
+
+/// Tries to resolve a page fault, on success the faulting instruction can be retried.
+/// Faults on pages that the page tables already permit the access to are left over from a stale
+/// TLB entry, everything else is looked up in the areas of the active address space.
+pub fn handle_page_fault(page_fault: &PageFault) -> Result<(), FaultError> {
+    if !is_initialized() {
+        return Err(FaultError::NotMapped);
+    }
+
+    let write = page_fault.error_code.write();
+    let user = page_fault.error_code.user();
+    let instruction_fetch = page_fault.error_code.instruction_fetch();
+    let address = VirtAddr::new(page_fault.addr as u64);
+
+    //these faults happen on entries that permit the access, retrying them would fault forever
+    if page_fault.error_code.reserved_bit() {
+        return Err(FaultError::ReservedBit);
+    }
+    if page_fault.error_code.protection_key() {
+        return Err(FaultError::AccessViolation);
+    }
+
+    let mapper = unsafe { active_mapper() };
+    if let TranslateResult::Mapped { flags, .. } = mapper.translate(page_fault.page.start_address()) {
+        if vma::allows(flags, write, user, instruction_fetch) {
+            tlb::flush(page_fault.page.start_address());
+            return Ok(());
+        }
+    }
+
+    let address_space = match unsafe { ACTIVE.load(Ordering::Acquire).as_mut() } {
+        Some(address_space) => address_space,
+        None => return Err(FaultError::NotMapped),
+    };
+
+    let vma = match address_space.vmas.find(address) {
+        Some(vma) => *vma,
+        None => match address_space.vmas.stack_growth(address) {
+            StackGrowth::Grow { stack_start } => address_space.vmas
+                .grow_stack(stack_start, address)
+                .map_err(|_| FaultError::NotMapped)?,
+            StackGrowth::Overflow => return Err(FaultError::StackOverflow),
+            StackGrowth::None => return Err(FaultError::NotMapped),
+        },
+    };
+
+    if !vma.allows(write, user, instruction_fetch) {
+        return Err(FaultError::AccessViolation);
+    }
+
+    if let Some((frame, flags)) = address_space.translate_page(page_fault.page) {
+        //a write to a page shared since the address space was cloned
+        if page_fault.error_code.present() && write && flags.contains(COPY_ON_WRITE) {
+            return address_space.break_copy_on_write(page_fault.page, frame, flags);
+        }
+    }
+
+    //a present page that the area permits the access to was handled above
+    if address_space.translate_page(page_fault.page).is_some() {
+        return Err(FaultError::AccessViolation);
+    }
+
+    address_space.map_zeroed(page_fault.page, vma.flags).map_err(|_| FaultError::OutOfMemory)?;
+    Ok(())
+}
+
+/// A level 4 page table that shares the kernel half with every other address space and owns
+/// the user half, including every frame mapped there.
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    /// Memory that is mapped lazily by the page fault handler.
+    pub vmas: VmaList,
+}
+
+impl AddressSpace {
+    /// Creates an address space with an empty user half.
+    pub fn new() -> Option<AddressSpace> {
+        let kernel_frame = *KERNEL_LEVEL_4_FRAME.get().expect("Paging is not initialized");
+        let level_4_frame = allocate_zeroed_frame()?;
+
+        let (kernel_table, table) = unsafe { (table_at(kernel_frame), table_at(level_4_frame)) };
+        for (index, entry) in kernel_table.iter().enumerate() {
+            if !is_user_entry(index) {
+                table[index] = entry.clone();
+            }
+        }
+
+        Some(AddressSpace { level_4_frame, vmas: VmaList::new() })
+    }
+
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        unsafe { OffsetPageTable::new(table_at(self.level_4_frame), memory::physical_memory_offset()) }
+    }
+
+    /// Maps `page` to `frame`. A user page takes ownership of `frame`.
+    pub fn map(&mut self, page: Page, frame: PhysFrame, flags: PageTableFlags) -> Result<(), MapToError<Size4KiB>> {
+        //the intermediate tables need the user bit as well, otherwise ring 3 can't reach the page
+        let table_flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | (flags & PageTableFlags::USER_ACCESSIBLE);
+
+        unsafe {
+            self.mapper()
+                .map_to_with_table_flags(page, frame, flags, table_flags, &mut GlobalFrameAllocator)?
+                .flush();
+        }
+
+        Ok(())
+    }
+
+    /// Backs `page` with a freshly allocated, zeroed frame.
+    pub fn map_zeroed(&mut self, page: Page, flags: PageTableFlags) -> Result<PhysFrame, MapToError<Size4KiB>> {
+        let frame = allocate_zeroed_frame().ok_or(MapToError::FrameAllocationFailed)?;
+
+        if let Err(error) = self.map(page, frame, flags) {
+            memory::free_frame(frame);
+            return Err(error);
+        }
+
+        Ok(frame)
+    }
+
+    /// Removes the mapping of `page` and hands its frame back to the caller.
+    pub fn unmap(&mut self, page: Page) -> Result<PhysFrame, UnmapError> {
+        let (frame, flush) = self.mapper().unmap(page)?;
+        flush.flush();
+        Ok(frame)
+    }
+
+    pub fn update_flags(&mut self, page: Page, flags: PageTableFlags) -> Result<(), FlagUpdateError> {
+        unsafe { self.mapper().update_flags(page, flags)?.flush() };
+        Ok(())
+    }
+
+    pub fn translate(&self, address: VirtAddr) -> Option<PhysAddr> {
+        self.translate_page(Page::containing_address(address))
+            .map(|(frame, _)| frame.start_address() + (address.as_u64() & (FRAME_SIZE - 1)))
+    }
+
+    /// Frame and flags of a 4 KiB mapping of `page`.
+    pub fn translate_page(&self, page: Page) -> Option<(PhysFrame, PageTableFlags)> {
+        let table = unsafe { OffsetPageTable::new(table_at(self.level_4_frame), memory::physical_memory_offset()) };
+
+        match table.translate(page.start_address()) {
+            TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => Some((frame, flags)),
+            _ => None,
+        }
+    }
+
+    /// Calls `f` for every 4 KiB page mapped in the user half.
+    pub fn for_each_user_page<F: FnMut(Page, PhysFrame, PageTableFlags)>(&self, mut f: F) {
+        let level_4 = unsafe { table_at(self.level_4_frame) };
+
+        for (i4, e4) in level_4.iter().enumerate().filter(|(i, e)| is_user_entry(*i) && !e.is_unused()) {
+            let level_3 = unsafe { table_at(e4.frame().unwrap()) };
+
+            for (i3, e3) in level_3.iter().enumerate().filter(|(_, e)| !e.is_unused()) {
+                let level_2 = unsafe { table_at(e3.frame().expect("Huge pages are not supported in user space")) };
+
+                for (i2, e2) in level_2.iter().enumerate().filter(|(_, e)| !e.is_unused()) {
+                    let level_1 = unsafe { table_at(e2.frame().expect("Huge pages are not supported in user space")) };
+
+                    for (i1, e1) in level_1.iter().enumerate().filter(|(_, e)| !e.is_unused()) {
+                        let address = (i4 << 39) | (i3 << 30) | (i2 << 21) | (i1 << 12);
+                        let page = Page::containing_address(VirtAddr::new(address as u64));
+                        f(page, e1.frame().unwrap(), e1.flags());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates a copy of this address space that shares every user page with it. Writable pages become
+    /// read-only copy-on-write pages in both, the first write to one gives the writer its own copy.
+    pub fn try_clone(&mut self) -> Option<AddressSpace> {
+        let mut clone = AddressSpace::new()?;
+        clone.vmas = self.vmas.clone();
+
+        let mut pages = Vec::new();
+        self.for_each_user_page(|page, frame, flags| pages.push((page, frame, flags)));
+
+        for (page, frame, mut flags) in pages {
+            //a frame that can't take another reference fails the clone like running out of memory
+            memory::share_frame(frame)?;
+
+            if flags.contains(PageTableFlags::WRITABLE) {
+                flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+                self.update_flags(page, flags).expect("Page vanished while cloning the address space");
+            }
+
+            //dropping the partial clone releases the frames it shares, the pages left copy-on-write
+            //here just get writable again on their next write
+            if clone.map(page, frame, flags).is_err() {
+                memory::free_frame(frame);
+                return None;
+            }
+        }
+
+        Some(clone)
+    }
+
+    /// Gives `page` a private, writable frame, copying the shared one unless nobody else uses it anymore.
+    fn break_copy_on_write(&mut self, page: Page, frame: PhysFrame, flags: PageTableFlags) -> Result<(), FaultError> {
+        let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+
+        if memory::frame_reference_count(frame) == 1 {
+            return self.update_flags(page, flags).map_err(|_| FaultError::NotMapped);
+        }
+
+        let copy = memory::allocate_frame().ok_or(FaultError::OutOfMemory)?;
+        unsafe { copy_frame(frame, copy) };
+
+        //the page tables are already there, so mapping again can't run out of memory
+        self.unmap(page).map_err(|_| FaultError::NotMapped)?;
+        self.map(page, copy, flags).expect("Remapping a copy-on-write page failed");
+
+        memory::free_frame(frame);
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    /// Switches to this address space, page faults are resolved through its areas from now on.
+    /// The caller has to keep it alive and in place for as long as it is active.
+    pub unsafe fn activate(&mut self) {
+        ACTIVE.store(self, Ordering::Release);
+        Cr3::write(self.level_4_frame, Cr3Flags::empty());
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        assert!(!self.is_active(), "Dropping the active address space");
+
+        self.for_each_user_page(|_, frame, _| memory::free_frame(frame));
+
+        //then the tables themselves, bottom up
+        let level_4 = unsafe { table_at(self.level_4_frame) };
+        for e4 in level_4.iter().enumerate().filter(|(i, e)| is_user_entry(*i) && !e.is_unused()).map(|(_, e)| e) {
+            let level_3_frame = e4.frame().unwrap();
+            let level_3 = unsafe { table_at(level_3_frame) };
+
+            for e3 in level_3.iter().filter(|e| !e.is_unused()) {
+                let level_2_frame = e3.frame().unwrap();
+                let level_2 = unsafe { table_at(level_2_frame) };
+
+                for e2 in level_2.iter().filter(|e| !e.is_unused()) {
+                    memory::free_frame(e2.frame().unwrap());
+                }
+                memory::free_frame(level_2_frame);
+            }
+            memory::free_frame(level_3_frame);
+        }
+
+        memory::free_frame(self.level_4_frame);
+    }
+}
+
+#[test_case]
+fn map_translate_and_unmap() {
+    let mut address_space = AddressSpace::new().expect("Out of memory");
+    let page = Page::containing_address(VirtAddr::new(USER_SPACE_START + 0x1000));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+
+    let frame = address_space.map_zeroed(page, flags).expect("Mapping failed");
+    assert_eq!(address_space.translate(page.start_address() + 0x123u64), Some(frame.start_address() + 0x123u64));
+
+    assert_eq!(address_space.unmap(page).ok(), Some(frame));
+    assert_eq!(address_space.translate(page.start_address()), None);
+    memory::free_frame(frame);
+}
+
+#[test_case]
+fn reserved_bit_faults_are_not_retried() {
+    use crate::kernel::arch::x86::interrupts::page_fault::{PageFault, PageFaultErrorCode};
+
+    //a mapped kernel page, which the stale TLB path would otherwise accept
+    static MAPPED: u64 = 0;
+    let address = VirtAddr::from_ptr(&MAPPED);
+    let fault = |code| PageFault {
+        addr: address.as_u64() as usize,
+        page: Page::containing_address(address),
+        error_code: PageFaultErrorCode::from_code(code),
+    };
+
+    assert_eq!(handle_page_fault(&fault(0x1)), Ok(()));
+    assert_eq!(handle_page_fault(&fault(0x9)), Err(FaultError::ReservedBit));
+    assert_eq!(handle_page_fault(&fault(0x21)), Err(FaultError::AccessViolation));
+}
+
+#[test_case]
+fn clone_shares_pages_until_written() {
+    let before = memory::frame_stats();
+
+    {
+        let mut address_space = alloc::boxed::Box::new(AddressSpace::new().expect("Out of memory"));
+        let start = VirtAddr::new(USER_SPACE_START);
+        let page = Page::containing_address(start);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+        address_space.vmas.insert(Vma::new(start, start + FRAME_SIZE, flags, VmaKind::Anonymous)).unwrap();
+
+        let frame = address_space.map_zeroed(page, flags).expect("Mapping failed");
+        unsafe { *memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u64>() = 42 };
+
+        let mut clone = alloc::boxed::Box::new(address_space.try_clone().expect("Out of memory"));
+        let (shared, shared_flags) = clone.translate_page(page).expect("Page was not cloned");
+
+        assert_eq!(shared, frame);
+        assert!(shared_flags.contains(COPY_ON_WRITE) && !shared_flags.contains(PageTableFlags::WRITABLE));
+        assert_eq!(memory::frame_reference_count(frame), 2);
+
+        unsafe {
+            clone.activate();
+            core::ptr::write_volatile(start.as_mut_ptr::<u64>(), 7);
+        }
+        activate_kernel();
+
+        let (copy, copy_flags) = clone.translate_page(page).unwrap();
+        assert_ne!(copy, frame);
+        assert!(copy_flags.contains(PageTableFlags::WRITABLE));
+        assert_eq!(memory::frame_reference_count(frame), 1);
+        assert_eq!(unsafe { *memory::phys_to_virt(frame.start_address()).as_ptr::<u64>() }, 42);
+        assert_eq!(unsafe { *memory::phys_to_virt(copy.start_address()).as_ptr::<u64>() }, 7);
+    }
+
+    //both address spaces gave back every frame they used
+    assert_eq!(memory::frame_stats(), before);
+}
+
+#[test_case]
+fn anonymous_memory_is_mapped_on_first_access() {
+    let mut address_space = alloc::boxed::Box::new(AddressSpace::new().expect("Out of memory"));
+    let start = VirtAddr::new(USER_SPACE_START);
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    address_space.vmas.insert(Vma::new(start, start + 2 * FRAME_SIZE, flags, VmaKind::Anonymous)).unwrap();
+
+    unsafe {
+        address_space.activate();
+
+        let pointer = (start + FRAME_SIZE).as_mut_ptr::<u64>();
+        assert_eq!(core::ptr::read_volatile(pointer), 0);
+        core::ptr::write_volatile(pointer, 42);
+        assert_eq!(core::ptr::read_volatile(pointer), 42);
+    }
+
+    activate_kernel();
+
+    //only the page that was touched got a frame
+    assert!(address_space.translate_page(Page::containing_address(start)).is_none());
+    assert!(address_space.translate_page(Page::containing_address(start + FRAME_SIZE)).is_some());
+}
+
+#[test_case]
+fn stacks_grow_on_demand() {
+    let mut address_space = alloc::boxed::Box::new(AddressSpace::new().expect("Out of memory"));
+    let top = VirtAddr::new(USER_SPACE_START + 64 * FRAME_SIZE);
+    let stack = Vma::new(top - FRAME_SIZE, top, PageTableFlags::WRITABLE, VmaKind::Stack { max_size: 16 * FRAME_SIZE });
+    address_space.vmas.insert(stack).unwrap();
+
+    unsafe {
+        address_space.activate();
+        core::ptr::write_volatile((top - 3 * FRAME_SIZE).as_mut_ptr::<u64>(), 1);
+    }
+
+    activate_kernel();
+
+    let stack = address_space.vmas.find(top - 3 * FRAME_SIZE).expect("Stack did not grow");
+    assert_eq!(stack.start, top - 3 * FRAME_SIZE);
+}
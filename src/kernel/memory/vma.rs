@@ -0,0 +1,179 @@
+use alloc::collections::BTreeMap;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::VirtAddr;
+use crate::kernel::memory::frame::FRAME_SIZE;
+
+/// Unmapped pages kept between a stack and the area below it, a fault there is a stack overflow.
+pub const STACK_GUARD_PAGES: u64 = 1;
+
+/// Whether page table `flags` permit an access.
+pub fn allows(flags: PageTableFlags, write: bool, user: bool, instruction_fetch: bool) -> bool {
+    (!write || flags.contains(PageTableFlags::WRITABLE))
+        && (!user || flags.contains(PageTableFlags::USER_ACCESSIBLE))
+        && (!instruction_fetch || !flags.contains(PageTableFlags::NO_EXECUTE))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaKind {
+    /// Zeroed memory, backed by frames the first time a page is touched.
+    Anonymous,
+    /// Anonymous memory that grows downwards, up to `max_size` bytes below its end.
+    Stack { max_size: u64 },
+    /// A segment of an executable, all of its pages are mapped when the program is loaded.
+    Program,
+}
+
+/// A range of virtual memory `[start, end)` with the flags its pages are mapped with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vma {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub flags: PageTableFlags,
+    pub kind: VmaKind,
+}
+
+impl Vma {
+    pub fn new(start: VirtAddr, end: VirtAddr, flags: PageTableFlags, kind: VmaKind) -> Vma {
+        assert!(start.is_aligned(FRAME_SIZE) && end.is_aligned(FRAME_SIZE), "Areas have to be page aligned");
+        assert!(start < end, "Areas can't be empty");
+
+        Vma { start, end, flags: flags | PageTableFlags::PRESENT, kind }
+    }
+
+    pub fn contains(&self, address: VirtAddr) -> bool {
+        self.start <= address && address < self.end
+    }
+
+    pub fn allows(&self, write: bool, user: bool, instruction_fetch: bool) -> bool {
+        allows(self.flags, write, user, instruction_fetch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmaError {
+    Overlap,
+    NotFound,
+}
+
+/// Result of looking up a fault address that no area contains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackGrowth {
+    /// The stack starting at `stack_start` can grow down to the address.
+    Grow { stack_start: VirtAddr },
+    /// The address is in the guard pages below a stack.
+    Overflow,
+    None,
+}
+
+/// The areas of an address space, ordered by their start address.
+#[derive(Debug, Clone, Default)]
+pub struct VmaList {
+    areas: BTreeMap<u64, Vma>,
+}
+
+impl VmaList {
+    pub fn new() -> VmaList {
+        VmaList { areas: BTreeMap::new() }
+    }
+
+    pub fn insert(&mut self, vma: Vma) -> Result<(), VmaError> {
+        let overlaps = self.areas
+            .range(..vma.end.as_u64())
+            .next_back()
+            .map_or(false, |(_, other)| other.end > vma.start);
+
+        if overlaps {
+            return Err(VmaError::Overlap);
+        }
+
+        self.areas.insert(vma.start.as_u64(), vma);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, start: VirtAddr) -> Result<Vma, VmaError> {
+        self.areas.remove(&start.as_u64()).ok_or(VmaError::NotFound)
+    }
+
+    pub fn find(&self, address: VirtAddr) -> Option<&Vma> {
+        self.areas
+            .range(..=address.as_u64())
+            .next_back()
+            .map(|(_, vma)| vma)
+            .filter(|vma| vma.contains(address))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vma> {
+        self.areas.values()
+    }
+
+    /// Checks whether a stack directly above `address` may grow down to it.
+    pub fn stack_growth(&self, address: VirtAddr) -> StackGrowth {
+        let stack = match self.areas.range(address.as_u64()..).next() {
+            Some((_, vma)) => *vma,
+            None => return StackGrowth::None,
+        };
+
+        let max_size = match stack.kind {
+            VmaKind::Stack { max_size } => max_size,
+            VmaKind::Anonymous | VmaKind::Program => return StackGrowth::None,
+        };
+
+        let guard_size = STACK_GUARD_PAGES * FRAME_SIZE;
+        let mut limit = stack.end.as_u64().saturating_sub(max_size);
+
+        //the stack must not come closer to the area below it than the guard pages
+        if let Some((_, below)) = self.areas.range(..address.as_u64()).next_back() {
+            limit = limit.max(below.end.as_u64() + guard_size);
+        }
+
+        if address.as_u64() >= limit {
+            StackGrowth::Grow { stack_start: stack.start }
+        } else if address.as_u64() + guard_size >= limit {
+            StackGrowth::Overflow
+        } else {
+            StackGrowth::None
+        }
+    }
+
+    /// Moves the start of the stack at `stack_start` down to the page containing `address`.
+    pub fn grow_stack(&mut self, stack_start: VirtAddr, address: VirtAddr) -> Result<Vma, VmaError> {
+        let mut stack = self.remove(stack_start)?;
+        stack.start = address.align_down(FRAME_SIZE);
+
+        self.areas.insert(stack.start.as_u64(), stack);
+        Ok(stack)
+    }
+}
+
+#[cfg(test)]
+fn test_area(start: u64, end: u64, kind: VmaKind) -> Vma {
+    Vma::new(VirtAddr::new(start), VirtAddr::new(end), PageTableFlags::WRITABLE, kind)
+}
+
+#[test_case]
+fn areas_are_found_and_must_not_overlap() {
+    let mut areas = VmaList::new();
+    areas.insert(test_area(0x1000, 0x3000, VmaKind::Anonymous)).unwrap();
+    areas.insert(test_area(0x5000, 0x6000, VmaKind::Anonymous)).unwrap();
+
+    assert_eq!(areas.insert(test_area(0x2000, 0x5000, VmaKind::Anonymous)), Err(VmaError::Overlap));
+    assert_eq!(areas.find(VirtAddr::new(0x2fff)).map(|vma| vma.start), Some(VirtAddr::new(0x1000)));
+    assert_eq!(areas.find(VirtAddr::new(0x3000)), None);
+    assert_eq!(areas.find(VirtAddr::new(0x5000)).map(|vma| vma.end), Some(VirtAddr::new(0x6000)));
+}
+
+#[test_case]
+fn stacks_grow_until_the_guard_pages() {
+    let mut areas = VmaList::new();
+    areas.insert(test_area(0x1000, 0x2000, VmaKind::Anonymous)).unwrap();
+    areas.insert(test_area(0x8000, 0x9000, VmaKind::Stack { max_size: 0x10000 })).unwrap();
+
+    let stack_start = VirtAddr::new(0x8000);
+    assert_eq!(areas.stack_growth(VirtAddr::new(0x7ff8)), StackGrowth::Grow { stack_start });
+    assert_eq!(areas.stack_growth(VirtAddr::new(0x3000)), StackGrowth::Grow { stack_start });
+    assert_eq!(areas.stack_growth(VirtAddr::new(0x2800)), StackGrowth::Overflow);
+
+    let stack = areas.grow_stack(stack_start, VirtAddr::new(0x6010)).unwrap();
+    assert_eq!(stack.start, VirtAddr::new(0x6000));
+    assert!(areas.find(VirtAddr::new(0x6010)).is_some());
+}
@@ -1,2 +1,11 @@
 pub mod lib;
 pub mod arch;
+pub mod time;
+pub mod drivers;
+pub mod memory;
+pub mod task;
+pub mod sync;
+pub mod syscall;
+pub mod loader;
+pub mod process;
+pub mod symbols;
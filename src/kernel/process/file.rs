@@ -0,0 +1,120 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use crate::kernel::arch::x86::serial;
+use crate::print;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileError {
+    BadDescriptor,
+    NotReadable,
+    NotWritable,
+    InvalidData,
+}
+
+/// Something a file descriptor refers to.
+pub trait File: Send + Sync {
+    /// Reads up to `buffer.len()` bytes, returns how many were read.
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FileError>;
+
+    /// Writes `buffer`, returns how many bytes were written.
+    fn write(&self, buffer: &[u8]) -> Result<usize, FileError>;
+}
+
+/// Writes UTF-8 text to the screen and reads what arrived on the serial port, without blocking.
+pub struct Console;
+
+impl File for Console {
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, FileError> {
+        let mut count = 0;
+
+        while count < buffer.len() {
+            match serial::read_byte() {
+                Some(byte) => buffer[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, FileError> {
+        let text = core::str::from_utf8(buffer).map_err(|_| FileError::InvalidData)?;
+
+        print!("{}", text);
+        Ok(buffer.len())
+    }
+}
+
+pub const STDIN: usize = 0;
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+/// The open files of a process, indexed by file descriptor. Cloning shares the files.
+#[derive(Clone, Default)]
+pub struct FileTable {
+    files: Vec<Option<Arc<dyn File>>>,
+}
+
+impl FileTable {
+    pub fn new() -> FileTable {
+        FileTable { files: Vec::new() }
+    }
+
+    /// A table with stdin, stdout and stderr on the console.
+    pub fn with_console() -> FileTable {
+        let console: Arc<dyn File> = Arc::new(Console);
+        let mut table = FileTable::new();
+
+        for _ in STDIN..=STDERR {
+            table.insert(console.clone());
+        }
+
+        table
+    }
+
+    pub fn get(&self, fd: usize) -> Result<Arc<dyn File>, FileError> {
+        self.files.get(fd).cloned().flatten().ok_or(FileError::BadDescriptor)
+    }
+
+    /// Opens `file` at the lowest free descriptor, which is returned.
+    pub fn insert(&mut self, file: Arc<dyn File>) -> usize {
+        match self.files.iter().position(Option::is_none) {
+            Some(fd) => {
+                self.files[fd] = Some(file);
+                fd
+            }
+            None => {
+                self.files.push(Some(file));
+                self.files.len() - 1
+            }
+        }
+    }
+
+    pub fn close(&mut self, fd: usize) -> Result<(), FileError> {
+        match self.files.get_mut(fd) {
+            Some(file) if file.is_some() => {
+                *file = None;
+                Ok(())
+            }
+            _ => Err(FileError::BadDescriptor),
+        }
+    }
+
+    pub fn open_count(&self) -> usize {
+        self.files.iter().filter(|file| file.is_some()).count()
+    }
+}
+
+#[test_case]
+fn descriptors_are_reused_lowest_first() {
+    let mut table = FileTable::with_console();
+    assert_eq!(table.open_count(), 3);
+
+    table.close(STDIN).unwrap();
+    assert_eq!(table.close(STDIN), Err(FileError::BadDescriptor));
+    assert!(table.get(STDIN).is_err());
+
+    assert_eq!(table.insert(Arc::new(Console)), STDIN);
+    assert_eq!(table.insert(Arc::new(Console)), 3);
+}
@@ -0,0 +1,423 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use x86_64::instructions::interrupts;
+use file::FileTable;
+use signal::{SignalAction, SignalActions};
+use crate::kernel::arch::x86::context;
+use crate::kernel::loader::{self, LoadError};
+use crate::kernel::memory::paging::AddressSpace;
+use crate::kernel::sync::{SpinLock, WaitQueue};
+use crate::kernel::task::{self, ThreadId};
+
+pub mod file;
+pub mod signal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProcessId(pub u64);
+
+impl ProcessId {
+    fn next() -> ProcessId {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        ProcessId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ProcessId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Owns the kernel threads, it never exits.
+pub const KERNEL_PROCESS: ProcessId = ProcessId(0);
+
+/// Why a process was killed, numbered like the POSIX signals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Signal {
+    Illegal = 4,
+    Trap = 5,
+    Bus = 7,
+    FloatingPoint = 8,
+    Kill = 9,
+    Segfault = 11,
+}
+
+impl Signal {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Illegal => "illegal instruction",
+            Signal::Trap => "trap",
+            Signal::Bus => "bus error",
+            Signal::FloatingPoint => "arithmetic exception",
+            Signal::Kill => "killed",
+            Signal::Segfault => "segmentation fault",
+        }
+    }
+
+    pub fn from_number(number: usize) -> Option<Signal> {
+        match number {
+            4 => Some(Signal::Illegal),
+            5 => Some(Signal::Trap),
+            7 => Some(Signal::Bus),
+            8 => Some(Signal::FloatingPoint),
+            9 => Some(Signal::Kill),
+            11 => Some(Signal::Segfault),
+            _ => None,
+        }
+    }
+
+    /// Whether a process may install a handler for the signal, `Kill` always ends it.
+    pub fn is_catchable(&self) -> bool {
+        *self != Signal::Kill
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Exited(i32),
+    Killed(Signal),
+}
+
+impl ExitStatus {
+    /// Encoded like POSIX `wait` does: the exit code in bits 8..16, or the signal in the low bits.
+    pub fn to_wait_status(&self) -> usize {
+        match *self {
+            ExitStatus::Exited(code) => ((code & 0xff) as usize) << 8,
+            ExitStatus::Killed(signal) => signal as usize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    /// Exited, but the parent did not collect the status yet.
+    Zombie(ExitStatus),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitError {
+    /// The process has no children, or none with the requested id.
+    NoChild,
+}
+
+pub struct Process {
+    pub id: ProcessId,
+    pub name: String,
+    /// None once the parent exited, such a process is reaped as soon as it exits.
+    pub parent: Option<ProcessId>,
+    /// For a child of the kernel process, the kernel thread that started it and may wait for it.
+    pub spawner: Option<ThreadId>,
+    pub children: Vec<ProcessId>,
+    pub threads: Vec<ThreadId>,
+    pub files: FileTable,
+    pub signal_actions: SignalActions,
+    pub state: ProcessState,
+    //boxed so that the pointers the threads hold stay valid, freed when the process exits
+    address_space: Option<Box<AddressSpace>>,
+}
+
+struct ProcessTable {
+    processes: BTreeMap<ProcessId, Process>,
+    //the process of every thread, kernel threads are not listed
+    owners: BTreeMap<ThreadId, ProcessId>,
+}
+
+impl ProcessTable {
+    fn new() -> ProcessTable {
+        let kernel = Process {
+            id: KERNEL_PROCESS,
+            name: "kernel".to_string(),
+            parent: None,
+            spawner: None,
+            children: Vec::new(),
+            threads: Vec::new(),
+            files: FileTable::with_console(),
+            signal_actions: SignalActions::new(),
+            state: ProcessState::Running,
+            address_space: None,
+        };
+
+        let mut processes = BTreeMap::new();
+        processes.insert(KERNEL_PROCESS, kernel);
+
+        ProcessTable { processes, owners: BTreeMap::new() }
+    }
+
+    fn current(&self) -> ProcessId {
+        self.owners.get(&task::current_id()).copied().unwrap_or(KERNEL_PROCESS)
+    }
+
+    /// Marks `id` as exited and takes its resources, which the caller frees, and its other threads,
+    /// which the caller kills once the table is unlocked.
+    fn exit(&mut self, id: ProcessId, status: ExitStatus) -> (Vec<ThreadId>, (Option<Box<AddressSpace>>, FileTable)) {
+        let current_thread = task::current_id();
+        let process = self.processes.get_mut(&id).expect("Exiting process vanished");
+
+        process.state = ProcessState::Zombie(status);
+        let resources = (process.address_space.take(), core::mem::take(&mut process.files));
+        let mut threads = core::mem::take(&mut process.threads);
+        let children = core::mem::take(&mut process.children);
+        let orphan = process.parent.is_none();
+
+        for thread in &threads {
+            self.owners.remove(thread);
+        }
+        threads.retain(|thread| *thread != current_thread);
+
+        //nobody can wait for the children anymore
+        for child in children {
+            let child = self.processes.get_mut(&child).unwrap();
+            child.parent = None;
+
+            if let ProcessState::Zombie(_) = child.state {
+                let child = child.id;
+                self.processes.remove(&child);
+            }
+        }
+
+        if orphan {
+            self.processes.remove(&id);
+        }
+
+        self.reap_unwaited();
+        (threads, resources)
+    }
+
+    /// Reaps the zombie children of the kernel process whose spawning thread is gone, nothing can
+    /// wait for them anymore.
+    fn reap_unwaited(&mut self) {
+        let unwaited: Vec<ProcessId> = self.processes[&KERNEL_PROCESS].children.iter().copied()
+            .filter(|child| {
+                let child = &self.processes[child];
+                matches!(child.state, ProcessState::Zombie(_)) && !child.spawner.map_or(false, task::is_alive)
+            })
+            .collect();
+
+        for child in unwaited {
+            self.reap(child);
+        }
+    }
+
+    /// Removes the zombie `id` and returns its exit status.
+    fn reap(&mut self, id: ProcessId) -> ExitStatus {
+        let process = self.processes.remove(&id).expect("Reaped process vanished");
+
+        if let Some(parent) = process.parent.and_then(|parent| self.processes.get_mut(&parent)) {
+            parent.children.retain(|child| *child != id);
+        }
+
+        match process.state {
+            ProcessState::Zombie(status) => status,
+            ProcessState::Running => panic!("Reaped process {} is still running", id),
+        }
+    }
+}
+
+lazy_static! {
+    static ref PROCESSES: SpinLock<ProcessTable> = SpinLock::new(ProcessTable::new());
+}
+
+//woken whenever a process exits, waiting parents check whether it was one of their children
+static CHILD_EXITED: WaitQueue = WaitQueue::new();
+
+/// Loads the executable `data` and starts it as a child of the current process, which it
+/// inherits the open files from. Signal handlers are not inherited, they belong to the old program.
+pub fn spawn(name: &str, data: &[u8], argv: &[&str], envp: &[&str]) -> Result<ProcessId, LoadError> {
+    let program = loader::load(data, argv, envp)?;
+    let (entry, stack_pointer) = (program.entry.as_u64(), program.stack_pointer.as_u64());
+    let mut address_space = Box::new(program.address_space);
+    let address_space_pointer: *mut AddressSpace = &mut *address_space;
+
+    //the lock keeps interrupts disabled, so the thread can't run before it is registered
+    let mut table = PROCESSES.lock();
+    let id = ProcessId::next();
+    let parent = table.current();
+    let spawner = (parent == KERNEL_PROCESS).then(task::current_id);
+    let files = table.processes[&parent].files.clone();
+
+    let thread = task::spawn_in("user", address_space_pointer, move || unsafe {
+        context::enter_user_mode(entry, stack_pointer);
+    });
+
+    table.processes.insert(id, Process {
+        id,
+        name: name.to_string(),
+        parent: Some(parent),
+        spawner,
+        children: Vec::new(),
+        threads: alloc::vec![thread],
+        files,
+        signal_actions: SignalActions::new(),
+        state: ProcessState::Running,
+        address_space: Some(address_space),
+    });
+    table.processes.get_mut(&parent).unwrap().children.push(id);
+    table.owners.insert(thread, id);
+
+    Ok(id)
+}
+
+/// The process the running thread belongs to.
+pub fn current() -> ProcessId {
+    PROCESSES.lock().current()
+}
+
+pub fn parent(id: ProcessId) -> Option<ProcessId> {
+    PROCESSES.lock().processes.get(&id).and_then(|process| process.parent)
+}
+
+pub fn state(id: ProcessId) -> Option<ProcessState> {
+    PROCESSES.lock().processes.get(&id).map(|process| process.state)
+}
+
+/// Runs `f` with the open files of the current process.
+pub fn with_files<T, F: FnOnce(&mut FileTable) -> T>(f: F) -> T {
+    let mut table = PROCESSES.lock();
+    let current = table.current();
+
+    f(&mut table.processes.get_mut(&current).unwrap().files)
+}
+
+/// The handler the current process installed for `signal`, if any.
+pub fn signal_action(signal: Signal) -> Option<SignalAction> {
+    let table = PROCESSES.lock();
+    table.processes[&table.current()].signal_actions.get(signal)
+}
+
+/// Installs the handler for `signal` in the current process, `None` restores the default of
+/// killing it.
+pub fn set_signal_action(signal: Signal, action: Option<SignalAction>) {
+    let mut table = PROCESSES.lock();
+    let current = table.current();
+
+    table.processes.get_mut(&current).unwrap().signal_actions.set(signal, action);
+}
+
+/// Ends the current process with all of its threads. It stays a zombie until its parent waits
+/// for it. A child of the kernel process that the spawning kernel thread can't wait for anymore is
+/// reaped the next time any process exits, so one such zombie can linger until then.
+/// A kernel thread only ends itself, the kernel process never exits.
+pub fn exit(status: ExitStatus) -> ! {
+    //nothing may switch back to this thread once its address space is gone
+    interrupts::disable();
+
+    let id = current();
+    if id == KERNEL_PROCESS {
+        task::exit();
+    }
+
+    task::set_address_space(ptr::null_mut());
+    let (threads, resources) = PROCESSES.lock().exit(id, status);
+
+    //the table lock is not held across `task::kill`
+    for thread in threads {
+        task::kill(thread);
+    }
+    drop(resources);
+
+    CHILD_EXITED.wake_all();
+    task::exit();
+}
+
+/// Kills the current process, e.g. because it faulted in user mode.
+pub fn kill_current(signal: Signal) -> ! {
+    exit(ExitStatus::Killed(signal));
+}
+
+/// Blocks until a child of the current process exited, `child` picks a specific one. The child is
+/// reaped, its id and exit status are returned.
+pub fn wait(child: Option<ProcessId>) -> Result<(ProcessId, ExitStatus), WaitError> {
+    loop {
+        let mut table = PROCESSES.lock();
+        let current = table.current();
+        let children = &table.processes[&current].children;
+
+        if children.is_empty() || child.map_or(false, |child| !children.contains(&child)) {
+            return Err(WaitError::NoChild);
+        }
+
+        let zombie = children.iter().copied()
+            .filter(|id| child.map_or(true, |child| child == *id))
+            .find(|id| matches!(table.processes[id].state, ProcessState::Zombie(_)));
+
+        if let Some(zombie) = zombie {
+            return Ok((zombie, table.reap(zombie)));
+        }
+
+        CHILD_EXITED.wait(table);
+    }
+}
+
+pub fn wait_pid(id: ProcessId) -> Result<ExitStatus, WaitError> {
+    wait(Some(id)).map(|(_, status)| status)
+}
+
+#[cfg(test)]
+const STATUS_PROGRAM: &[u8] = include_bytes!("../loader/programs/status");
+#[cfg(test)]
+const SIGNAL_PROGRAM: &[u8] = include_bytes!("../loader/programs/signal");
+
+#[test_case]
+fn exit_status_is_collected_by_the_parent() {
+    let child = spawn("status", STATUS_PROGRAM, &["status"], &[]).unwrap();
+
+    assert_eq!(parent(child), Some(current()));
+    assert_eq!(wait_pid(child), Ok(ExitStatus::Exited(42)));
+    //reaped
+    assert_eq!(state(child), None);
+    assert_eq!(wait_pid(child), Err(WaitError::NoChild));
+}
+
+#[test_case]
+fn user_mode_faults_kill_only_the_process() {
+    let faulting = spawn("status", STATUS_PROGRAM, &["status", "fault"], &[]).unwrap();
+    let exiting = spawn("status", STATUS_PROGRAM, &["status"], &[]).unwrap();
+
+    let mut statuses = [wait(None).unwrap(), wait(None).unwrap()];
+    statuses.sort_by_key(|(id, _)| *id);
+
+    assert_eq!(statuses, [(faulting, ExitStatus::Killed(Signal::Segfault)), (exiting, ExitStatus::Exited(42))]);
+    assert_eq!(ExitStatus::Killed(Signal::Segfault).to_wait_status(), 11);
+    assert_eq!(ExitStatus::Exited(42).to_wait_status(), 42 << 8);
+}
+
+#[test_case]
+fn signal_handlers_resume_the_process() {
+    let child = spawn("signal", SIGNAL_PROGRAM, &["signal"], &[]).unwrap();
+
+    //the handler skipped the faulting write and set the exit status in the saved registers
+    assert_eq!(wait_pid(child), Ok(ExitStatus::Exited(7)));
+}
+
+#[test_case]
+fn unwaited_kernel_children_are_reaped() {
+    static CHILD: AtomicU64 = AtomicU64::new(0);
+
+    //the thread that spawns the child ends without waiting for it
+    let spawner = task::spawn("spawner", || {
+        let child = spawn("status", STATUS_PROGRAM, &["status"], &[]).unwrap();
+        CHILD.store(child.0, Ordering::SeqCst);
+    });
+
+    while task::is_alive(spawner) {
+        task::yield_now();
+    }
+
+    let child = ProcessId(CHILD.load(Ordering::SeqCst));
+    while state(child) == Some(ProcessState::Running) {
+        task::yield_now();
+    }
+
+    //zombies nobody can wait for are reaped whenever a process exits
+    let other = spawn("status", STATUS_PROGRAM, &["status"], &[]).unwrap();
+    assert_eq!(wait_pid(other), Ok(ExitStatus::Exited(42)));
+    assert_eq!(state(child), None);
+}
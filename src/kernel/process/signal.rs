@@ -0,0 +1,174 @@
+use core::mem::size_of;
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+use crate::kernel::arch::x86::context::RflagsMasks;
+use crate::kernel::arch::x86::gdt;
+use crate::kernel::arch::x86::registers::StackFrame;
+use crate::kernel::memory::paging;
+use super::Signal;
+
+/// Signals are numbered below 32 like on POSIX systems, not every number is used.
+pub const SIGNAL_COUNT: usize = 32;
+
+//leaf functions use the 128 bytes below rsp without moving it, the saved registers go below them
+const RED_ZONE: u64 = 128;
+//what user code may change in the registers it hands back to `sigreturn`
+const USER_FLAGS: usize = RflagsMasks::STATUS | RflagsMasks::DIRECTION | RflagsMasks::ALIGNMENT_CHECK;
+
+/// A signal handler in user space. It is called as `handler(signal, registers)`, where `registers`
+/// points at the interrupted thread's registers, laid out as a `StackFrame`. It returns into
+/// `restorer`, which has to make the `sigreturn` system call to go on with these registers,
+/// including any changes the handler made, e.g. to `rip` to skip a faulting instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalAction {
+    pub handler: u64,
+    pub restorer: u64,
+}
+
+/// The handlers a process installed, indexed by signal number.
+#[derive(Debug, Clone, Copy)]
+pub struct SignalActions([Option<SignalAction>; SIGNAL_COUNT]);
+
+impl SignalActions {
+    pub const fn new() -> SignalActions {
+        SignalActions([None; SIGNAL_COUNT])
+    }
+
+    pub fn get(&self, signal: Signal) -> Option<SignalAction> {
+        self.0[signal as usize]
+    }
+
+    pub fn set(&mut self, signal: Signal, action: Option<SignalAction>) {
+        self.0[signal as usize] = action;
+    }
+}
+
+/// Redirects the interrupted user thread in `registers` into the current process's handler for
+/// `signal`. Returns false if it has none or the registers don't fit on its stack, the caller
+/// kills the process then.
+pub fn deliver(registers: &mut StackFrame, signal: Signal) -> bool {
+    match super::signal_action(signal) {
+        Some(action) => redirect(registers, signal, action),
+        None => false,
+    }
+}
+
+/// Saves `registers` on the user stack and points them at the handler of `action`. The stack is
+/// set up like after a call to the handler: its return address is the restorer, and the saved
+/// registers start 16 byte aligned right above it.
+pub fn redirect(registers: &mut StackFrame, signal: Signal, action: SignalAction) -> bool {
+    let saved_at = (registers.iret.rsp as u64).wrapping_sub(RED_ZONE + size_of::<StackFrame>() as u64) & !0xf;
+    let return_address_at = saved_at.wrapping_sub(8);
+
+    //no other thread of the process may unmap the stack between the check and the copy
+    let copied = interrupts::without_interrupts(|| {
+        //the stack pointer is whatever user code left in rsp
+        let is_writable = VirtAddr::try_new(return_address_at)
+            .map_or(false, |start| paging::is_user_accessible(start, 8 + size_of::<StackFrame>() as u64, true));
+
+        if is_writable {
+            unsafe {
+                (saved_at as *mut StackFrame).write_unaligned(*registers);
+                (return_address_at as *mut u64).write(action.restorer);
+            }
+        }
+        is_writable
+    });
+    if !copied {
+        return false;
+    }
+
+    registers.iret.rip = action.handler as usize;
+    registers.iret.rsp = return_address_at as usize;
+    registers.scratch.rdi = signal as usize;
+    registers.scratch.rsi = saved_at as usize;
+    //like at any function entry the direction flag is clear, and the handler isn't single stepped
+    registers.iret.rflags &= !(RflagsMasks::DIRECTION | RflagsMasks::TRAP);
+
+    true
+}
+
+/// `sigreturn`: replaces `registers` with the ones `redirect` saved, which the user stack pointer
+/// points at once the handler returned into the restorer. They come from user memory, so only what
+/// user code could have set itself is taken over: the segments stay those of ring 3, and of the
+/// flags only the arithmetic ones, the direction and alignment check. Returns false if the saved
+/// registers can't be read or `rip` is outside of user space, the caller kills the process then.
+pub fn restore(registers: &mut StackFrame) -> bool {
+    //no other thread of the process may unmap the stack between the check and the copy
+    let saved = interrupts::without_interrupts(|| match VirtAddr::try_new(registers.iret.rsp as u64) {
+        Ok(address) if paging::is_user_accessible(address, size_of::<StackFrame>() as u64, false) => {
+            Some(unsafe { address.as_ptr::<StackFrame>().read_unaligned() })
+        }
+        _ => None,
+    });
+
+    let mut saved = match saved {
+        Some(saved) => saved,
+        None => return false,
+    };
+    if !VirtAddr::try_new(saved.iret.rip as u64).map_or(false, paging::is_user_address) {
+        return false;
+    }
+
+    let selectors = gdt::selectors();
+    saved.iret.cs = selectors.user_code.0 as usize;
+    saved.iret.ss = selectors.user_data.0 as usize;
+    saved.iret.rflags = (saved.iret.rflags & USER_FLAGS) | RflagsMasks::RESERVED | RflagsMasks::INTERRUPT_ENABLE;
+
+    *registers = saved;
+    true
+}
+
+#[test_case]
+fn restored_registers_stay_in_user_mode() {
+    use alloc::boxed::Box;
+    use x86_64::structures::paging::{Page, PageTableFlags};
+    use crate::kernel::lib::testing;
+    use crate::kernel::memory::paging::{AddressSpace, USER_SPACE_START};
+
+    const STACK: u64 = USER_SPACE_START + 0x1000;
+    const HANDLER: u64 = USER_SPACE_START + 0x100;
+    const RESTORER: u64 = USER_SPACE_START + 0x200;
+
+    let mut address_space = Box::new(AddressSpace::new().unwrap());
+    let user = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE;
+    address_space.map_zeroed(Page::containing_address(VirtAddr::new(STACK)), user).unwrap();
+
+    //the user pages are only reachable while the address space is active
+    testing::run_in(&mut address_space, || {
+        let mut registers = StackFrame::default();
+        registers.iret.rip = USER_SPACE_START as usize;
+        registers.iret.rsp = (STACK + 0x1000) as usize;
+        registers.preserved.rbx = 42;
+
+        let action = SignalAction { handler: HANDLER, restorer: RESTORER };
+        assert!(redirect(&mut registers, Signal::Segfault, action));
+        assert_eq!({ registers.iret.rip }, HANDLER as usize);
+        assert_eq!({ registers.scratch.rdi }, Signal::Segfault as usize);
+        assert_eq!({ registers.iret.rsp } % 16, 8);
+
+        //the handler returns into the restorer, which makes the system call
+        let return_address = unsafe { (registers.iret.rsp as *const u64).read() };
+        assert_eq!(return_address, RESTORER);
+        registers.iret.rsp += 8;
+
+        //a handler may change what it is given, but not leave ring 3 or disable interrupts
+        unsafe {
+            let saved = registers.scratch.rsi as *mut StackFrame;
+            (*saved).iret.rip += 2;
+            (*saved).iret.cs = gdt::selectors().kernel_code.0 as usize;
+            (*saved).iret.rflags = 0x3000;
+        }
+
+        assert!(restore(&mut registers));
+        assert_eq!({ registers.iret.rip }, USER_SPACE_START as usize + 2);
+        assert_eq!({ registers.preserved.rbx }, 42);
+        assert!(registers.iret.is_user_mode());
+        assert_eq!({ registers.iret.rflags }, RflagsMasks::RESERVED | RflagsMasks::INTERRUPT_ENABLE);
+
+        //a stack pointer outside of user space
+        registers.iret.rsp = 0;
+        assert!(!redirect(&mut registers, Signal::Segfault, action));
+        assert!(!restore(&mut registers));
+    });
+}
@@ -0,0 +1,204 @@
+use alloc::vec::Vec;
+use core::fmt;
+use bootloader::BootInfo;
+use bootloader::bootinfo::MemoryRegionType;
+use spin::Once;
+use x86_64::PhysAddr;
+use crate::kernel::loader::elf::Elf;
+use crate::kernel::memory;
+
+/// A function of the kernel image, `name` is the mangled name from its symbol table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub address: u64,
+    pub size: u64,
+    pub name: &'static str,
+}
+
+impl Symbol {
+    pub fn demangled(&self) -> Demangled<'static> {
+        Demangled(self.name)
+    }
+}
+
+//sorted by address, empty if the kernel image has no symbol table
+static SYMBOLS: Once<Vec<Symbol>> = Once::new();
+
+/// Builds the symbol table from the kernel's ELF file. The bootloader keeps the whole file in a
+/// kernel region of physical memory, so the names are borrowed from there and stay valid forever.
+/// Needs the heap, so it runs after `memory::init`.
+pub fn init(boot_info: &'static BootInfo) {
+    SYMBOLS.call_once(|| {
+        let image = boot_info.memory_map.iter()
+            .filter(|region| region.region_type == MemoryRegionType::Kernel)
+            .map(|region| unsafe {
+                let start = memory::phys_to_virt(PhysAddr::new(region.range.start_addr()));
+                core::slice::from_raw_parts(start.as_ptr::<u8>(), (region.range.end_addr() - region.range.start_addr()) as usize)
+            })
+            .find_map(|data| Elf::parse_image(data).ok());
+
+        let mut symbols: Vec<Symbol> = image.iter()
+            .flat_map(|elf| elf.symbols())
+            .filter(|(_, entry)| entry.is_function() && entry.value != 0)
+            .map(|(name, entry)| Symbol { address: entry.value, size: entry.size, name })
+            .collect();
+
+        symbols.sort_unstable_by_key(|symbol| symbol.address);
+        symbols
+    });
+}
+
+/// Number of functions known, 0 before `init` or if the kernel was stripped.
+pub fn count() -> usize {
+    SYMBOLS.get().map_or(0, Vec::len)
+}
+
+/// The function containing `address` and the offset of `address` into it.
+/// Doesn't lock or allocate, so it can be used while handling exceptions and panics.
+pub fn lookup(address: u64) -> Option<(&'static Symbol, u64)> {
+    let symbols = SYMBOLS.get()?;
+    let index = symbols.partition_point(|symbol| symbol.address <= address).checked_sub(1)?;
+    let symbol = &symbols[index];
+    let offset = address - symbol.address;
+
+    //symbols without a size (e.g. from assembly) extend to the next one
+    if symbol.size == 0 || offset < symbol.size {
+        Some((symbol, offset))
+    } else {
+        None
+    }
+}
+
+/// Formats a Rust symbol name in the legacy mangling (`_ZN...E`) as a path without the hash suffix,
+/// e.g. `thunder::kernel::task::schedule`. Other names are written as they are.
+pub struct Demangled<'a>(pub &'a str);
+
+impl fmt::Display for Demangled<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self.0.strip_prefix("_ZN").and_then(|path| path.strip_suffix('E')) {
+            Some(path) if Components(path).all(|component| component.is_some()) => path,
+            _ => return f.write_str(self.0),
+        };
+
+        let mut components = Components(path).flatten().peekable();
+        let mut first = true;
+
+        while let Some(component) = components.next() {
+            if components.peek().is_none() && is_hash(component) {
+                break;
+            }
+            if !first {
+                f.write_str("::")?;
+            }
+
+            write_unescaped(f, component)?;
+            first = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// The length prefixed components of a mangled path, `None` once it turns out to be malformed.
+struct Components<'a>(&'a str);
+
+impl<'a> Iterator for Components<'a> {
+    type Item = Option<&'a str>;
+
+    fn next(&mut self) -> Option<Option<&'a str>> {
+        if self.0.is_empty() {
+            return None;
+        }
+
+        let digits = self.0.bytes().take_while(u8::is_ascii_digit).count();
+        let component = self.0[..digits].parse::<usize>().ok()
+            .and_then(|len| self.0.get(digits..digits.checked_add(len)?));
+
+        match component {
+            Some(component) => {
+                self.0 = &self.0[digits + component.len()..];
+                Some(Some(component))
+            }
+            None => {
+                self.0 = "";
+                Some(None)
+            }
+        }
+    }
+}
+
+//the last component is `h` followed by 16 hex digits of a hash of the crate and signature
+fn is_hash(component: &str) -> bool {
+    component.len() == 17 && component.starts_with('h') && component[1..].bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+fn write_unescaped(f: &mut fmt::Formatter<'_>, component: &str) -> fmt::Result {
+    //components that would start with a `$` or a digit are prefixed with an underscore
+    let mut rest = component.strip_prefix("_$").map_or(component, |_| &component[1..]);
+
+    while let Some(character) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("..") {
+            f.write_str("::")?;
+            rest = after;
+            continue;
+        }
+
+        if character == '$' {
+            if let Some(end) = rest[1..].find('$') {
+                if let Some(unescaped) = unescape(&rest[1..end + 1]) {
+                    fmt::Write::write_char(f, unescaped)?;
+                    rest = &rest[end + 2..];
+                    continue;
+                }
+            }
+        }
+
+        fmt::Write::write_char(f, character)?;
+        rest = &rest[character.len_utf8()..];
+    }
+
+    Ok(())
+}
+
+fn unescape(escape: &str) -> Option<char> {
+    match escape {
+        "SP" => Some('@'),
+        "BP" => Some('*'),
+        "RF" => Some('&'),
+        "LT" => Some('<'),
+        "GT" => Some('>'),
+        "LP" => Some('('),
+        "RP" => Some(')'),
+        "C" => Some(','),
+        _ => escape.strip_prefix('u')
+            .and_then(|code| u32::from_str_radix(code, 16).ok())
+            .and_then(char::from_u32),
+    }
+}
+
+#[test_case]
+fn names_are_demangled() {
+    use alloc::string::ToString;
+
+    assert_eq!(Demangled("_ZN7thunder6kernel4task8schedule17h0123456789abcdefE").to_string(), "thunder::kernel::task::schedule");
+    assert_eq!(
+        Demangled("_ZN66_$LT$alloc..vec..Vec$LT$T$GT$$u20$as$u20$core..ops..drop..Drop$GT$4drop17h0123456789abcdefE").to_string(),
+        "<alloc::vec::Vec<T> as core::ops::drop::Drop>::drop"
+    );
+    assert_eq!(Demangled("_ZN4core3fmt5write17h0123456789abcdefE").to_string(), "core::fmt::write");
+    //not mangled or cut off
+    assert_eq!(Demangled("memcpy").to_string(), "memcpy");
+    assert_eq!(Demangled("_ZN7thunder9E").to_string(), "_ZN7thunder9E");
+}
+
+#[test_case]
+fn kernel_functions_are_found() {
+    use alloc::string::ToString;
+
+    let address = count as fn() -> usize as u64;
+    let (symbol, offset) = lookup(address + 4).expect("No symbol for a kernel function");
+
+    assert_eq!(symbol.address, address);
+    assert_eq!(offset, 4);
+    assert_eq!(symbol.demangled().to_string(), "thunder::kernel::symbols::count");
+}
@@ -0,0 +1,60 @@
+use crate::kernel::sync::mutex::MutexGuard;
+use crate::kernel::sync::wait_queue::WaitQueue;
+
+/// Condition variable for `sync::Mutex`.
+pub struct Condvar {
+    waiters: WaitQueue,
+}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar { waiters: WaitQueue::new() }
+    }
+
+    /// Releases the mutex, blocks until notified and takes the mutex again. Wake ups can be
+    /// spurious, so the condition has to be checked in a loop, or use `wait_while`.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let mutex = MutexGuard::mutex(&guard);
+
+        MutexGuard::unlock_and_wait(guard, &self.waiters);
+        mutex.lock()
+    }
+
+    /// Waits as long as `condition` returns true.
+    pub fn wait_while<'a, T: ?Sized, F: FnMut(&mut T) -> bool>(&self, mut guard: MutexGuard<'a, T>, mut condition: F) -> MutexGuard<'a, T> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.waiters.wake_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.waiters.wake_all();
+    }
+}
+
+#[test_case]
+fn condvar_wakes_waiting_thread() {
+    use alloc::sync::Arc;
+    use crate::kernel::sync::mutex::Mutex;
+    use crate::kernel::task;
+
+    let shared = Arc::new((Mutex::new(false), Condvar::new()));
+    let notifier = shared.clone();
+
+    task::spawn("test", move || {
+        let (ready, condvar) = &*notifier;
+
+        *ready.lock() = true;
+        condvar.notify_all();
+    });
+
+    let (ready, condvar) = &*shared;
+    let guard = condvar.wait_while(ready.lock(), |ready| !*ready);
+    assert!(*guard);
+}
@@ -0,0 +1,157 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use crate::kernel::sync::spinlock::SpinLock;
+use crate::kernel::sync::wait_queue::WaitQueue;
+use crate::kernel::task::{self, ThreadId};
+
+/// Mutex that blocks waiting threads instead of spinning, it may be held across thread switches
+/// but must not be taken in interrupt handlers.
+pub struct Mutex<T: ?Sized> {
+    owner: SpinLock<Option<ThreadId>>,
+    waiters: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Mutex<T> {
+        Mutex {
+            owner: SpinLock::new(None),
+            waiters: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Blocks until the mutex is free. Debug builds panic if the current thread already holds it.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let current = task::current_id();
+
+        loop {
+            let mut owner = self.owner.lock();
+
+            match *owner {
+                None => {
+                    *owner = Some(current);
+                    return MutexGuard { mutex: self };
+                }
+                Some(holder) => {
+                    debug_assert!(holder != current, "Deadlock: mutex locked again by thread {} which already holds it", current.0);
+                    self.waiters.wait(owner);
+                }
+            }
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut owner = self.owner.lock();
+
+        if owner.is_some() {
+            return None;
+        }
+
+        *owner = Some(task::current_id());
+        Some(MutexGuard { mutex: self })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.owner.lock().is_some()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Releases the mutex and wakes up the next waiter, `owner` is the locked owner field.
+    fn unlock(&self, owner: &mut Option<ThreadId>) {
+        *owner = None;
+        self.waiters.wake_one();
+    }
+}
+
+impl<T: ?Sized + Default> Default for Mutex<T> {
+    fn default() -> Mutex<T> {
+        Mutex::new(T::default())
+    }
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    pub(super) fn mutex(guard: &MutexGuard<'a, T>) -> &'a Mutex<T> {
+        guard.mutex
+    }
+
+    /// Releases the mutex and blocks the current thread on `waiters`, see `Condvar::wait`.
+    /// Taking the mutex again is up to the caller.
+    pub(super) fn unlock_and_wait(guard: MutexGuard<'a, T>, waiters: &WaitQueue) {
+        let mutex = guard.mutex;
+        core::mem::forget(guard);
+
+        //the owner lock keeps interrupts disabled, nobody can notify before the thread is queued
+        let mut owner = mutex.owner.lock();
+        mutex.unlock(&mut owner);
+        waiters.wait(owner);
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut owner = self.mutex.owner.lock();
+        self.mutex.unlock(&mut owner);
+    }
+}
+
+#[test_case]
+fn mutex_is_exclusive_across_threads() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+    let counter = Arc::new(Mutex::new(0));
+
+    for _ in 0..2 {
+        let counter = counter.clone();
+
+        task::spawn("test", move || {
+            for _ in 0..50 {
+                let mut value = counter.lock();
+                let read = *value;
+                //give the other thread a chance to run inside the critical section
+                task::yield_now();
+                *value = read + 1;
+            }
+            FINISHED.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    while FINISHED.load(Ordering::SeqCst) < 2 {
+        task::yield_now();
+    }
+
+    assert_eq!(*counter.lock(), 100);
+    assert!(!counter.is_locked());
+}
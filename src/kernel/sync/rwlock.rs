@@ -0,0 +1,255 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+#[cfg(debug_assertions)]
+use alloc::vec::Vec;
+use crate::kernel::sync::spinlock::SpinLock;
+use crate::kernel::sync::wait_queue::WaitQueue;
+use crate::kernel::task::{self, ThreadId};
+
+struct State {
+    readers: usize,
+    //threads holding a read lock, a second read by one of them waits behind a writer forever
+    #[cfg(debug_assertions)]
+    reader_ids: Vec<ThreadId>,
+    writer: Option<ThreadId>,
+    //new readers wait while a writer does, so that writers can't starve
+    writers_waiting: usize,
+}
+
+impl State {
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn add_reader(&mut self, reader: ThreadId) {
+        self.readers += 1;
+        #[cfg(debug_assertions)]
+        self.reader_ids.push(reader);
+    }
+
+    #[cfg_attr(not(debug_assertions), allow(unused_variables))]
+    fn remove_reader(&mut self, reader: ThreadId) {
+        self.readers -= 1;
+        #[cfg(debug_assertions)]
+        if let Some(index) = self.reader_ids.iter().position(|id| *id == reader) {
+            self.reader_ids.swap_remove(index);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_not_reading(&self, current: ThreadId) {
+        if self.reader_ids.contains(&current) {
+            panic!("Deadlock: thread {} waits for a lock it already reads", current.0);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn check_not_reading(&self, _current: ThreadId) {}
+}
+
+/// Reader-writer lock that blocks waiting threads, writers are preferred over new readers.
+pub struct RwLock<T: ?Sized> {
+    state: SpinLock<State>,
+    readers: WaitQueue,
+    writers: WaitQueue,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> RwLock<T> {
+        RwLock {
+            state: SpinLock::new(State {
+                readers: 0,
+                #[cfg(debug_assertions)]
+                reader_ids: Vec::new(),
+                writer: None,
+                writers_waiting: 0,
+            }),
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    /// Blocks while there is a writer or one is waiting. Debug builds panic if the current thread
+    /// would wait while already holding the lock, e.g. a recursive read behind a waiting writer.
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        let current = task::current_id();
+
+        loop {
+            let mut state = self.state.lock();
+
+            if state.writer.is_none() && state.writers_waiting == 0 {
+                state.add_reader(current);
+                return RwLockReadGuard { lock: self, reader: current };
+            }
+
+            debug_assert!(state.writer != Some(current), "Deadlock: thread {} reads a lock it writes", current.0);
+            state.check_not_reading(current);
+            self.readers.wait(state);
+        }
+    }
+
+    /// Blocks until there are neither readers nor a writer. Debug builds panic if the current
+    /// thread already holds the lock.
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        let current = task::current_id();
+        let mut state = self.state.lock();
+        state.writers_waiting += 1;
+
+        loop {
+            if state.writer.is_none() && state.readers == 0 {
+                state.writers_waiting -= 1;
+                state.writer = Some(current);
+                return RwLockWriteGuard { lock: self };
+            }
+
+            debug_assert!(state.writer != Some(current), "Deadlock: write lock taken again by thread {} which already holds it", current.0);
+            state.check_not_reading(current);
+            self.writers.wait(state);
+            state = self.state.lock();
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.lock();
+
+        if state.writer.is_some() || state.writers_waiting > 0 {
+            return None;
+        }
+
+        let current = task::current_id();
+        state.add_reader(current);
+        Some(RwLockReadGuard { lock: self, reader: current })
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let mut state = self.state.lock();
+
+        if state.writer.is_some() || state.readers > 0 {
+            return None;
+        }
+
+        state.writer = Some(task::current_id());
+        Some(RwLockWriteGuard { lock: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Lets a waiting writer in, or all waiting readers if there is none.
+    fn wake_waiters(&self, state: &State) {
+        if state.writers_waiting > 0 {
+            self.writers.wake_one();
+        } else {
+            self.readers.wake_all();
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    //the guard may be dropped by another thread than the one that took it
+    reader: ThreadId,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.lock.state.lock();
+        state.remove_reader(self.reader);
+
+        if state.readers == 0 {
+            self.lock.wake_waiters(&state);
+        }
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.lock.state.lock();
+        state.writer = None;
+        self.lock.wake_waiters(&state);
+    }
+}
+
+#[test_case]
+fn readers_share_writers_exclude() {
+    let lock = RwLock::new(1);
+
+    {
+        let first = lock.read();
+        let second = lock.read();
+        assert_eq!(*first + *second, 2);
+        assert!(lock.try_write().is_none());
+    }
+
+    {
+        let mut writer = lock.write();
+        *writer = 2;
+        assert!(lock.try_read().is_none());
+        assert!(lock.try_write().is_none());
+    }
+
+    assert_eq!(*lock.read(), 2);
+}
+
+#[test_case]
+fn writer_waits_for_readers() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static WRITTEN: AtomicBool = AtomicBool::new(false);
+    let lock = Arc::new(RwLock::new(0));
+    let writer = lock.clone();
+
+    let reader = lock.read();
+    task::spawn("test", move || {
+        *writer.write() = 1;
+        WRITTEN.store(true, Ordering::SeqCst);
+    });
+
+    for _ in 0..3 {
+        task::yield_now();
+    }
+    assert!(!WRITTEN.load(Ordering::SeqCst));
+    assert_eq!(*reader, 0);
+    drop(reader);
+
+    while !WRITTEN.load(Ordering::SeqCst) {
+        task::yield_now();
+    }
+    assert_eq!(*lock.read(), 1);
+}
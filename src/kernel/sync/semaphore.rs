@@ -0,0 +1,80 @@
+use crate::kernel::sync::spinlock::SpinLock;
+use crate::kernel::sync::wait_queue::WaitQueue;
+
+/// Counting semaphore, `acquire` blocks while the count is 0.
+pub struct Semaphore {
+    count: SpinLock<usize>,
+    waiters: WaitQueue,
+}
+
+impl Semaphore {
+    pub const fn new(count: usize) -> Semaphore {
+        Semaphore {
+            count: SpinLock::new(count),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn acquire(&self) {
+        loop {
+            let mut count = self.count.lock();
+
+            if *count > 0 {
+                *count -= 1;
+                return;
+            }
+
+            self.waiters.wait(count);
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut count = self.count.lock();
+
+        if *count == 0 {
+            return false;
+        }
+
+        *count -= 1;
+        true
+    }
+
+    /// Increments the count and wakes up a waiter. Safe to call from interrupt handlers.
+    pub fn release(&self) {
+        let mut count = self.count.lock();
+
+        *count += 1;
+        self.waiters.wake_one();
+    }
+
+    pub fn count(&self) -> usize {
+        *self.count.lock()
+    }
+}
+
+#[test_case]
+fn semaphore_blocks_until_released() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use crate::kernel::task;
+
+    static SEMAPHORE: Semaphore = Semaphore::new(0);
+    static ACQUIRED: AtomicBool = AtomicBool::new(false);
+
+    task::spawn("test", || {
+        SEMAPHORE.acquire();
+        ACQUIRED.store(true, Ordering::SeqCst);
+    });
+
+    for _ in 0..3 {
+        task::yield_now();
+    }
+    assert!(!ACQUIRED.load(Ordering::SeqCst));
+
+    SEMAPHORE.release();
+    while !ACQUIRED.load(Ordering::SeqCst) {
+        task::yield_now();
+    }
+
+    assert_eq!(SEMAPHORE.count(), 0);
+    assert!(!SEMAPHORE.try_acquire());
+}
@@ -0,0 +1,187 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(debug_assertions)]
+use core::sync::atomic::AtomicU64;
+use x86_64::instructions::interrupts;
+#[cfg(debug_assertions)]
+use crate::kernel::task;
+
+#[cfg(debug_assertions)]
+const NO_OWNER: u64 = u64::MAX;
+
+/// Spinlock that keeps interrupts disabled while it is held, so an interrupt handler taking the
+/// same lock can't spin forever on the code it interrupted. It must not be held across a thread switch.
+///
+/// Debug builds remember the holding thread and panic when it tries to take the lock again,
+/// e.g. from an exception handler, instead of silently hanging.
+pub struct SpinLock<T: ?Sized> {
+    locked: AtomicBool,
+    #[cfg(debug_assertions)]
+    owner: AtomicU64,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Sync for SpinLock<T> {}
+unsafe impl<T: ?Sized + Send> Send for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(data: T) -> SpinLock<T> {
+        SpinLock {
+            locked: AtomicBool::new(false),
+            #[cfg(debug_assertions)]
+            owner: AtomicU64::new(NO_OWNER),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            self.check_reentry();
+
+            while self.locked.load(Ordering::Relaxed) {
+                core::hint::spin_loop();
+            }
+        }
+
+        self.set_owner();
+        SpinLockGuard { lock: self, interrupts_enabled }
+    }
+
+    /// Takes the lock if it is free. With a single CPU and interrupts disabled while the lock is held,
+    /// failing means the code holding it was interrupted by an exception that now runs.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let interrupts_enabled = interrupts::are_enabled();
+        interrupts::disable();
+
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            self.set_owner();
+            Some(SpinLockGuard { lock: self, interrupts_enabled })
+        } else {
+            if interrupts_enabled {
+                interrupts::enable();
+            }
+            None
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_reentry(&self) {
+        let current = task::current_id();
+
+        if self.owner.load(Ordering::Relaxed) == current.0 {
+            panic!("Deadlock: spinlock taken again by thread {} which already holds it", current.0);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn check_reentry(&self) {}
+
+    #[cfg(debug_assertions)]
+    fn set_owner(&self) {
+        self.owner.store(task::current_id().0, Ordering::Relaxed);
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn set_owner(&self) {}
+
+    fn unlock(&self) {
+        #[cfg(debug_assertions)]
+        self.owner.store(NO_OWNER, Ordering::Relaxed);
+
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+impl<T: ?Sized + Default> Default for SpinLock<T> {
+    fn default() -> SpinLock<T> {
+        SpinLock::new(T::default())
+    }
+}
+
+pub struct SpinLockGuard<'a, T: ?Sized> {
+    lock: &'a SpinLock<T>,
+    //whether interrupts were enabled before the lock was taken, restored on unlock
+    interrupts_enabled: bool,
+}
+
+impl<'a, T: ?Sized> SpinLockGuard<'a, T> {
+    /// Releases the lock but leaves interrupts disabled, returns whether they have to be enabled again.
+    /// The blocking primitives use it to queue a thread and switch it out without missing a wake up.
+    pub(super) fn unlock_interrupts_disabled(guard: SpinLockGuard<'a, T>) -> bool {
+        let interrupts_enabled = guard.interrupts_enabled;
+
+        guard.lock.unlock();
+        core::mem::forget(guard);
+        interrupts_enabled
+    }
+}
+
+impl<T: ?Sized> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+
+        if self.interrupts_enabled {
+            interrupts::enable();
+        }
+    }
+}
+
+#[test_case]
+fn interrupts_are_disabled_while_held() {
+    let lock = SpinLock::new(0);
+
+    assert!(interrupts::are_enabled());
+    {
+        let mut guard = lock.lock();
+        *guard += 1;
+        assert!(!interrupts::are_enabled());
+        assert!(lock.try_lock().is_none());
+    }
+    assert!(interrupts::are_enabled());
+    assert_eq!(*lock.lock(), 1);
+}
+
+#[test_case]
+fn nested_locks_restore_interrupts_once() {
+    let outer = SpinLock::new(());
+    let inner = SpinLock::new(());
+
+    let outer_guard = outer.lock();
+    drop(inner.lock());
+    assert!(!interrupts::are_enabled());
+
+    drop(outer_guard);
+    assert!(interrupts::are_enabled());
+}
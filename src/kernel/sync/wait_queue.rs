@@ -0,0 +1,97 @@
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts;
+use crate::kernel::sync::spinlock::{SpinLock, SpinLockGuard};
+use crate::kernel::task::{self, ThreadId};
+
+/// Threads blocked until some condition changes, the building block of the blocking primitives.
+pub struct WaitQueue {
+    waiters: SpinLock<Vec<ThreadId>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue { waiters: SpinLock::new(Vec::new()) }
+    }
+
+    /// Releases `guard` and blocks the current thread until it is woken up. The guard keeps
+    /// interrupts disabled until the thread is switched out, so a wake up for the state it protects
+    /// can't get lost in between. Callers check their condition again afterwards.
+    pub fn wait<T: ?Sized>(&self, guard: SpinLockGuard<T>) {
+        //without a scheduler there is nobody else who could release anything, just retry
+        if !task::is_running() {
+            if SpinLockGuard::unlock_interrupts_disabled(guard) {
+                interrupts::enable();
+            }
+            core::hint::spin_loop();
+            return;
+        }
+
+        self.waiters.lock().push(task::current_id());
+        let interrupts_enabled = SpinLockGuard::unlock_interrupts_disabled(guard);
+
+        task::block();
+
+        if interrupts_enabled {
+            interrupts::enable();
+        }
+    }
+
+    /// Wakes up the thread waiting the longest, returns false if none was waiting. Threads killed
+    /// while they waited are skipped, so that the wake up isn't lost on them.
+    pub fn wake_one(&self) -> bool {
+        loop {
+            let waiter = {
+                let mut waiters = self.waiters.lock();
+                if waiters.is_empty() { None } else { Some(waiters.remove(0)) }
+            };
+
+            match waiter {
+                Some(id) if task::unblock(id) => return true,
+                Some(_) => continue,
+                None => return false,
+            }
+        }
+    }
+
+    /// Wakes up every waiting thread, returns how many there were.
+    pub fn wake_all(&self) -> usize {
+        let waiters = core::mem::take(&mut *self.waiters.lock());
+
+        for id in waiters.iter() {
+            task::unblock(*id);
+        }
+
+        waiters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty()
+    }
+}
+
+#[test_case]
+fn killed_waiters_are_skipped() {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static QUEUE: WaitQueue = WaitQueue::new();
+    static LOCK: SpinLock<()> = SpinLock::new(());
+    static WOKEN: AtomicBool = AtomicBool::new(false);
+
+    let killed = task::spawn("test", || QUEUE.wait(LOCK.lock()));
+    task::spawn("test", || {
+        QUEUE.wait(LOCK.lock());
+        WOKEN.store(true, Ordering::SeqCst);
+    });
+
+    while QUEUE.waiters.lock().len() < 2 {
+        task::yield_now();
+    }
+
+    task::kill(killed);
+    assert!(QUEUE.wake_one());
+    assert!(QUEUE.is_empty());
+
+    while !WOKEN.load(Ordering::SeqCst) {
+        task::yield_now();
+    }
+}
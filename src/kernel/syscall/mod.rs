@@ -0,0 +1,303 @@
+use core::time::Duration;
+use x86_64::instructions::interrupts;
+use x86_64::VirtAddr;
+use crate::kernel::memory::paging;
+use crate::kernel::process::{self, ExitStatus, ProcessId, Signal, WaitError};
+use crate::kernel::process::file::FileError;
+use crate::kernel::process::signal::SignalAction;
+use crate::kernel::task;
+
+pub use crate::kernel::process::file::{STDERR, STDIN, STDOUT};
+
+/// System call numbers, passed in `rax`. They are part of the user space ABI: a number is never
+/// changed or reused, new calls are appended to `SYSCALLS`.
+pub struct SyscallNumber;
+
+impl SyscallNumber {
+    pub const EXIT: usize = 0;
+    pub const WRITE: usize = 1;
+    pub const YIELD: usize = 2;
+    pub const SLEEP: usize = 3;
+    pub const READ: usize = 4;
+    pub const GETPID: usize = 5;
+    pub const GETPPID: usize = 6;
+    pub const WAIT: usize = 7;
+    pub const CLOSE: usize = 8;
+    pub const SIGNAL: usize = 9;
+    /// sigreturn(): goes on with the registers a signal handler was given. It replaces all registers
+    /// instead of returning a value, so the architecture specific entries handle it and it has no
+    /// entry in `SYSCALLS`.
+    pub const SIGRETURN: usize = 10;
+}
+
+/// `pid` argument of `wait` for any child.
+pub const ANY_CHILD: usize = usize::MAX;
+
+/// Errors are returned as negative numbers in `rax`, so their values are ABI as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(isize)]
+pub enum SyscallError {
+    NoSuchSyscall = -1,
+    BadAddress = -2,
+    InvalidArgument = -3,
+    BadFileDescriptor = -4,
+    NoChild = -5,
+    NoParent = -6,
+}
+
+impl SyscallError {
+    pub fn code(self) -> usize {
+        self as isize as usize
+    }
+}
+
+impl From<FileError> for SyscallError {
+    fn from(error: FileError) -> SyscallError {
+        match error {
+            FileError::BadDescriptor | FileError::NotReadable | FileError::NotWritable => SyscallError::BadFileDescriptor,
+            FileError::InvalidData => SyscallError::InvalidArgument,
+        }
+    }
+}
+
+impl From<WaitError> for SyscallError {
+    fn from(error: WaitError) -> SyscallError {
+        match error {
+            WaitError::NoChild => SyscallError::NoChild,
+        }
+    }
+}
+
+pub type Arguments = [usize; 6];
+pub type SyscallHandler = fn(&Arguments) -> Result<usize, SyscallError>;
+
+pub struct Syscall {
+    pub name: &'static str,
+    pub handler: SyscallHandler,
+}
+
+/// Indexed by `SyscallNumber`.
+pub static SYSCALLS: [Syscall; 10] = [
+    Syscall { name: "exit", handler: exit },
+    Syscall { name: "write", handler: write },
+    Syscall { name: "yield", handler: yield_now },
+    Syscall { name: "sleep", handler: sleep },
+    Syscall { name: "read", handler: read },
+    Syscall { name: "getpid", handler: getpid },
+    Syscall { name: "getppid", handler: getppid },
+    Syscall { name: "wait", handler: wait },
+    Syscall { name: "close", handler: close },
+    Syscall { name: "signal", handler: signal },
+];
+
+/// Runs system call `number`, returns the value for `rax`. Called by the architecture specific entries.
+pub fn dispatch(number: usize, arguments: &Arguments) -> usize {
+    //system calls may block, only the entry runs with interrupts disabled
+    interrupts::enable();
+
+    let result = match SYSCALLS.get(number) {
+        Some(syscall) => (syscall.handler)(arguments),
+        None => Err(SyscallError::NoSuchSyscall),
+    };
+
+    match result {
+        Ok(value) => value,
+        Err(error) => error.code(),
+    }
+}
+
+fn check_user_range(address: usize, len: usize, write: bool) -> Result<(), SyscallError> {
+    let start = VirtAddr::try_new(address as u64).map_err(|_| SyscallError::BadAddress)?;
+
+    if paging::is_user_accessible(start, len as u64, write) {
+        Ok(())
+    } else {
+        Err(SyscallError::BadAddress)
+    }
+}
+
+/// Borrows `len` bytes of user memory at `address`, after checking that user code could read them.
+fn user_slice<'a>(address: usize, len: usize) -> Result<&'a [u8], SyscallError> {
+    check_user_range(address, len, false)?;
+    Ok(unsafe { core::slice::from_raw_parts(address as *const u8, len) })
+}
+
+/// Borrows `len` bytes of user memory at `address`, after checking that user code could write them.
+fn user_slice_mut<'a>(address: usize, len: usize) -> Result<&'a mut [u8], SyscallError> {
+    check_user_range(address, len, true)?;
+    Ok(unsafe { core::slice::from_raw_parts_mut(address as *mut u8, len) })
+}
+
+/// exit(code): ends the calling process with all of its threads.
+fn exit(arguments: &Arguments) -> Result<usize, SyscallError> {
+    process::exit(ExitStatus::Exited(arguments[0] as i32));
+}
+
+/// write(fd, buffer, len): returns the number of bytes written.
+fn write(arguments: &Arguments) -> Result<usize, SyscallError> {
+    let [fd, buffer, len, ..] = *arguments;
+
+    //the file is used after the process table was unlocked, writing may take a while
+    let file = process::with_files(|files| files.get(fd))?;
+    Ok(file.write(user_slice(buffer, len)?)?)
+}
+
+/// read(fd, buffer, len): returns the number of bytes read.
+fn read(arguments: &Arguments) -> Result<usize, SyscallError> {
+    let [fd, buffer, len, ..] = *arguments;
+
+    let file = process::with_files(|files| files.get(fd))?;
+    Ok(file.read(user_slice_mut(buffer, len)?)?)
+}
+
+/// close(fd)
+fn close(arguments: &Arguments) -> Result<usize, SyscallError> {
+    process::with_files(|files| files.close(arguments[0]))?;
+    Ok(0)
+}
+
+/// yield(): gives the rest of the time slice to the next thread.
+fn yield_now(_arguments: &Arguments) -> Result<usize, SyscallError> {
+    task::yield_now();
+    Ok(0)
+}
+
+/// sleep(milliseconds): blocks the calling thread.
+fn sleep(arguments: &Arguments) -> Result<usize, SyscallError> {
+    task::sleep(Duration::from_millis(arguments[0] as u64));
+    Ok(0)
+}
+
+/// getpid(): the id of the calling process.
+fn getpid(_arguments: &Arguments) -> Result<usize, SyscallError> {
+    Ok(process::current().0 as usize)
+}
+
+/// getppid(): the id of the parent process, `NoParent` once it exited. 0 is the kernel process.
+fn getppid(_arguments: &Arguments) -> Result<usize, SyscallError> {
+    process::parent(process::current()).map(|parent| parent.0 as usize).ok_or(SyscallError::NoParent)
+}
+
+/// wait(pid, status): waits for the child `pid` (or `ANY_CHILD`) to exit and reaps it. Stores the
+/// `wait` encoded exit status at `status` unless it is 0, returns the id of the child.
+fn wait(arguments: &Arguments) -> Result<usize, SyscallError> {
+    let [pid, status, ..] = *arguments;
+    let status_size = core::mem::size_of::<usize>();
+
+    if status != 0 {
+        check_user_range(status, status_size, true)?;
+    }
+
+    let child = if pid == ANY_CHILD { None } else { Some(ProcessId(pid as u64)) };
+    let (child, exit_status) = process::wait(child)?;
+
+    if status != 0 {
+        user_slice_mut(status, status_size)?.copy_from_slice(&exit_status.to_wait_status().to_le_bytes());
+    }
+
+    Ok(child.0 as usize)
+}
+
+/// signal(signal, handler, restorer): installs `handler` for `signal`, see `SignalAction`. A
+/// handler of 0 restores the default of killing the process.
+fn signal(arguments: &Arguments) -> Result<usize, SyscallError> {
+    let [number, handler, restorer, ..] = *arguments;
+    let signal = Signal::from_number(number)
+        .filter(Signal::is_catchable)
+        .ok_or(SyscallError::InvalidArgument)?;
+
+    let action = if handler == 0 {
+        None
+    } else {
+        let is_user_code = |address: usize| VirtAddr::try_new(address as u64).map_or(false, paging::is_user_address);
+        if !is_user_code(handler) || !is_user_code(restorer) {
+            return Err(SyscallError::BadAddress);
+        }
+
+        Some(SignalAction { handler: handler as u64, restorer: restorer as u64 })
+    };
+
+    process::set_signal_action(signal, action);
+    Ok(0)
+}
+
+#[test_case]
+fn numbers_match_the_table() {
+    assert_eq!(SYSCALLS[SyscallNumber::EXIT].name, "exit");
+    assert_eq!(SYSCALLS[SyscallNumber::WRITE].name, "write");
+    assert_eq!(SYSCALLS[SyscallNumber::YIELD].name, "yield");
+    assert_eq!(SYSCALLS[SyscallNumber::SLEEP].name, "sleep");
+    assert_eq!(SYSCALLS[SyscallNumber::READ].name, "read");
+    assert_eq!(SYSCALLS[SyscallNumber::GETPID].name, "getpid");
+    assert_eq!(SYSCALLS[SyscallNumber::GETPPID].name, "getppid");
+    assert_eq!(SYSCALLS[SyscallNumber::WAIT].name, "wait");
+    assert_eq!(SYSCALLS[SyscallNumber::CLOSE].name, "close");
+    assert_eq!(SYSCALLS[SyscallNumber::SIGNAL].name, "signal");
+    assert!(SYSCALLS.get(SyscallNumber::SIGRETURN).is_none());
+    assert_eq!(dispatch(SYSCALLS.len(), &[0; 6]), SyscallError::NoSuchSyscall.code());
+}
+
+#[test_case]
+fn user_mode_makes_system_calls() {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+    use x86_64::structures::paging::{Page, PageTableFlags};
+    use crate::kernel::lib::testing;
+    use crate::kernel::memory;
+    use crate::kernel::memory::paging::{AddressSpace, USER_SPACE_START};
+
+    const CODE: u64 = USER_SPACE_START;
+    const DATA: u64 = USER_SPACE_START + 0x1000;
+    const MESSAGE: &[u8] = b"hello from ring 3\n";
+
+    //write through `syscall`, yield through `int 0x80`, store both results and exit
+    let mut code = Vec::new();
+    code.extend_from_slice(&[0xb8, SyscallNumber::WRITE as u8, 0, 0, 0]); //mov eax, WRITE
+    code.extend_from_slice(&[0xbf, STDOUT as u8, 0, 0, 0]); //mov edi, STDOUT
+    code.extend_from_slice(&[0x48, 0xbe]); //mov rsi, DATA
+    code.extend_from_slice(&DATA.to_le_bytes());
+    code.push(0xba); //mov edx, len
+    code.extend_from_slice(&(MESSAGE.len() as u32).to_le_bytes());
+    code.extend_from_slice(&[0x0f, 0x05]); //syscall
+    code.extend_from_slice(&[0x48, 0xa3]); //mov [DATA + 0x100], rax
+    code.extend_from_slice(&(DATA + 0x100).to_le_bytes());
+    code.extend_from_slice(&[0xb8, SyscallNumber::YIELD as u8, 0, 0, 0]); //mov eax, YIELD
+    code.extend_from_slice(&[0xcd, 0x80]); //int 0x80
+    code.extend_from_slice(&[0x48, 0xa3]); //mov [DATA + 0x108], rax
+    code.extend_from_slice(&(DATA + 0x108).to_le_bytes());
+    code.extend_from_slice(&[0xb8, SyscallNumber::EXIT as u8, 0, 0, 0]); //mov eax, EXIT
+    code.extend_from_slice(&[0x31, 0xff]); //xor edi, edi
+    code.extend_from_slice(&[0x0f, 0x05]); //syscall
+
+    let mut address_space = Box::new(AddressSpace::new().unwrap());
+    let user = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let code_frame = address_space.map_zeroed(Page::containing_address(VirtAddr::new(CODE)), user).unwrap();
+    let data_frame = address_space.map_zeroed(Page::containing_address(VirtAddr::new(DATA)), user | PageTableFlags::WRITABLE).unwrap();
+
+    let data = memory::phys_to_virt(data_frame.start_address()).as_mut_ptr::<u8>();
+    unsafe {
+        core::ptr::copy_nonoverlapping(code.as_ptr(), memory::phys_to_virt(code_frame.start_address()).as_mut_ptr(), code.len());
+        core::ptr::copy_nonoverlapping(MESSAGE.as_ptr(), data, MESSAGE.len());
+        //something the calls have to overwrite
+        data.add(0x108).cast::<u64>().write(u64::MAX);
+    }
+
+    testing::run_user_mode(&mut address_space, CODE, DATA + 0x1000);
+
+    unsafe {
+        assert_eq!(data.add(0x100).cast::<u64>().read(), MESSAGE.len() as u64);
+        assert_eq!(data.add(0x108).cast::<u64>().read(), 0);
+    }
+}
+
+#[test_case]
+fn only_user_code_handles_signals() {
+    use crate::kernel::memory::paging::USER_SPACE_START;
+
+    let handler = USER_SPACE_START as usize;
+    let signal = |number: usize, handler: usize| dispatch(SyscallNumber::SIGNAL, &[number, handler, handler, 0, 0, 0]);
+
+    assert_eq!(signal(Signal::Kill as usize, handler), SyscallError::InvalidArgument.code());
+    assert_eq!(signal(2, handler), SyscallError::InvalidArgument.code());
+    assert_eq!(signal(Signal::Segfault as usize, 0x1000), SyscallError::BadAddress.code());
+}
@@ -0,0 +1,228 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TaskId(u64);
+
+impl TaskId {
+    fn next() -> TaskId {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        TaskId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+pub struct Task {
+    id: TaskId,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
+}
+
+impl Task {
+    pub fn new<F: Future<Output = ()> + Send + 'static>(future: F) -> Task {
+        Task {
+            id: TaskId::next(),
+            future: Box::pin(future),
+        }
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        self.future.as_mut().poll(context)
+    }
+}
+
+//wakers run in interrupt handlers, so the queue is only locked with interrupts disabled
+type TaskQueue = Arc<Mutex<VecDeque<TaskId>>>;
+
+fn push(queue: &TaskQueue, id: TaskId) {
+    interrupts::without_interrupts(|| queue.lock().push_back(id));
+}
+
+fn pop(queue: &TaskQueue) -> Option<TaskId> {
+    interrupts::without_interrupts(|| queue.lock().pop_front())
+}
+
+struct TaskWaker {
+    id: TaskId,
+    queue: TaskQueue,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        push(&self.queue, self.id);
+    }
+}
+
+/// Runs futures on the current thread. A task is only polled again after its waker was called.
+pub struct Executor {
+    tasks: BTreeMap<TaskId, Task>,
+    wakers: BTreeMap<TaskId, Waker>,
+    queue: TaskQueue,
+}
+
+impl Executor {
+    pub fn new() -> Executor {
+        Executor {
+            tasks: BTreeMap::new(),
+            wakers: BTreeMap::new(),
+            queue: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub fn spawn<F: Future<Output = ()> + Send + 'static>(&mut self, future: F) -> TaskId {
+        let task = Task::new(future);
+        let id = task.id;
+
+        self.tasks.insert(id, task);
+        push(&self.queue, id);
+        id
+    }
+
+    pub fn task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Polls every task that was woken up, until none is left.
+    pub fn run_ready_tasks(&mut self) {
+        while let Some(id) = pop(&self.queue) {
+            //a task can be woken again after it completed
+            let task = match self.tasks.get_mut(&id) {
+                Some(task) => task,
+                None => continue,
+            };
+
+            let queue = &self.queue;
+            let waker = self.wakers
+                .entry(id)
+                .or_insert_with(|| Waker::from(Arc::new(TaskWaker { id, queue: queue.clone() })));
+
+            if task.poll(&mut Context::from_waker(waker)).is_ready() {
+                self.tasks.remove(&id);
+                self.wakers.remove(&id);
+            }
+        }
+    }
+
+    /// Halts until the next interrupt if no task is ready, the check and the `hlt` happen with
+    /// interrupts disabled so that a wake up in between can't be missed.
+    fn sleep_if_idle(&self) {
+        interrupts::disable();
+
+        if self.queue.lock().is_empty() {
+            interrupts::enable_and_hlt();
+        } else {
+            interrupts::enable();
+        }
+    }
+
+    /// Runs the tasks until all of them completed.
+    pub fn run_until_complete(&mut self) {
+        while !self.tasks.is_empty() {
+            self.run_ready_tasks();
+
+            if !self.tasks.is_empty() {
+                self.sleep_if_idle();
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> ! {
+        loop {
+            self.run_ready_tasks();
+            self.sleep_if_idle();
+        }
+    }
+}
+
+/// Completes after returning `Pending` once, giving the other tasks a turn.
+pub async fn yield_now() {
+    struct YieldNow(bool);
+
+    impl Future for YieldNow {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+
+            self.0 = true;
+            context.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    YieldNow(false).await
+}
+
+#[test_case]
+fn tasks_run_until_complete() {
+    use alloc::vec::Vec;
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut executor = Executor::new();
+
+    for id in 0..2 {
+        let order = order.clone();
+
+        executor.spawn(async move {
+            order.lock().push((id, 0));
+            yield_now().await;
+            order.lock().push((id, 1));
+        });
+    }
+
+    executor.run_until_complete();
+
+    assert_eq!(executor.task_count(), 0);
+    assert_eq!(*order.lock(), [(0, 0), (1, 0), (0, 1), (1, 1)]);
+}
+
+#[test_case]
+fn tasks_are_woken_from_interrupts() {
+    use core::sync::atomic::AtomicBool;
+    use crate::kernel::arch::x86::interrupts::irq::{self, IRQ_BASE};
+    use crate::kernel::lib::waker_cell::WakerCell;
+
+    static FIRED: AtomicBool = AtomicBool::new(false);
+    static WAKER: WakerCell = WakerCell::new();
+
+    struct Fired;
+
+    impl Future for Fired {
+        type Output = ();
+
+        fn poll(self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+            WAKER.register(context.waker());
+
+            if FIRED.load(Ordering::SeqCst) { Poll::Ready(()) } else { Poll::Pending }
+        }
+    }
+
+    //IRQ 3 (COM2) is never raised by QEMU with our configuration, so raise it with a software `int`.
+    //No hardware interrupt is in service outside of a handler, so its end of interrupt is a no-op
+    irq::register_vector(IRQ_BASE + 3, |_| {
+        FIRED.store(true, Ordering::SeqCst);
+        WAKER.wake();
+    });
+
+    let mut executor = Executor::new();
+    executor.spawn(Fired);
+    executor.spawn(async {
+        unsafe { core::arch::asm!("int {}", const IRQ_BASE + 3) };
+    });
+    executor.run_until_complete();
+
+    irq::unregister_vector(IRQ_BASE + 3);
+    assert!(FIRED.load(Ordering::SeqCst));
+}
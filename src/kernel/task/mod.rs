@@ -0,0 +1,237 @@
+use alloc::boxed::Box;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use scheduler::Scheduler;
+use thread::{Thread, ThreadState};
+use crate::kernel::arch::x86::{context, gdt};
+use crate::kernel::memory::paging::{self, AddressSpace};
+use crate::kernel::time;
+
+pub use thread::ThreadId;
+
+pub mod executor;
+pub mod scheduler;
+pub mod thread;
+
+static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+//set by the timer interrupt, the switch itself happens once the interrupt is acknowledged
+static NEED_RESCHEDULE: AtomicBool = AtomicBool::new(false);
+//id of the running thread, readable without the scheduler lock. The boot thread has id 0
+static CURRENT: AtomicU64 = AtomicU64::new(0);
+
+type ThreadFunction = Box<dyn FnOnce() + Send + 'static>;
+
+extern "C" fn idle(_: usize) -> ! {
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+extern "C" fn thread_main(function: usize) -> ! {
+    let function = unsafe { Box::from_raw(function as *mut ThreadFunction) };
+    function();
+    exit();
+}
+
+/// Turns the running code into the boot thread and starts scheduling, the heap has to be set up already.
+pub fn init() {
+    let scheduler = Scheduler::new(Thread::new("idle", idle, 0));
+    interrupts::without_interrupts(|| *SCHEDULER.lock() = Some(scheduler));
+}
+
+pub fn is_running() -> bool {
+    interrupts::without_interrupts(|| SCHEDULER.lock().is_some())
+}
+
+/// Starts a kernel thread running `function`, it exits when `function` returns.
+pub fn spawn<F: FnOnce() + Send + 'static>(name: &'static str, function: F) -> ThreadId {
+    spawn_in(name, ptr::null_mut(), function)
+}
+
+/// Starts a thread that runs in `address_space`, usually to enter user mode there. The address
+/// space has to stay alive until the thread exited.
+pub fn spawn_in<F: FnOnce() + Send + 'static>(name: &'static str, address_space: *mut AddressSpace, function: F) -> ThreadId {
+    let function: ThreadFunction = Box::new(function);
+    let mut thread = Thread::new(name, thread_main, Box::into_raw(Box::new(function)) as usize);
+    thread.address_space = address_space;
+
+    interrupts::without_interrupts(|| {
+        SCHEDULER.lock().as_mut().expect("Scheduler is not initialized").add(thread)
+    })
+}
+
+pub fn current() -> Option<ThreadId> {
+    interrupts::without_interrupts(|| SCHEDULER.lock().as_ref().map(|scheduler| scheduler.current()))
+}
+
+/// The running thread, also before the scheduler starts. Unlike `current` it takes no lock,
+/// so it can be used by the locks themselves and in exception handlers.
+pub fn current_id() -> ThreadId {
+    ThreadId(CURRENT.load(Ordering::Relaxed))
+}
+
+/// Whether the thread exists and did not exit yet.
+pub fn is_alive(id: ThreadId) -> bool {
+    interrupts::without_interrupts(|| SCHEDULER.lock().as_ref().map_or(false, |scheduler| scheduler.is_alive(id)))
+}
+
+/// Sets the address space the current thread runs in, null for the kernel's own.
+pub fn set_address_space(address_space: *mut AddressSpace) {
+    interrupts::without_interrupts(|| {
+        if let Some(scheduler) = SCHEDULER.lock().as_mut() {
+            let current = scheduler.current();
+            scheduler.thread_mut(current).unwrap().address_space = address_space;
+        }
+
+        unsafe { switch_address_space(address_space) };
+    });
+}
+
+unsafe fn switch_address_space(address_space: *mut AddressSpace) {
+    match address_space.as_mut() {
+        Some(address_space) if !address_space.is_active() => address_space.activate(),
+        Some(_) => {}
+        //kernel threads must not keep an address space alive, its process might free it
+        None if paging::is_user_space_active() => paging::activate_kernel(),
+        None => {}
+    }
+}
+
+/// Switches to the next thread, interrupts have to be disabled.
+fn schedule() {
+    let switch = match SCHEDULER.lock().as_mut() {
+        Some(scheduler) => {
+            let switch = scheduler.switch_next();
+            let thread = scheduler.current_thread();
+
+            CURRENT.store(thread.id.0, Ordering::Relaxed);
+            gdt::set_kernel_stack(thread.kernel_stack_top().unwrap_or_else(gdt::boot_kernel_stack));
+            switch.map(|switch| (switch, thread.address_space))
+        }
+        None => None,
+    };
+
+    if let Some(((old_stack_pointer, new_stack_pointer), address_space)) = switch {
+        unsafe {
+            switch_address_space(address_space);
+            context::switch(old_stack_pointer, new_stack_pointer);
+        }
+    }
+}
+
+fn schedule_with_state(state: ThreadState) {
+    interrupts::without_interrupts(|| {
+        if let Some(scheduler) = SCHEDULER.lock().as_mut() {
+            scheduler.set_current_state(state);
+        }
+
+        schedule();
+    });
+}
+
+/// Gives the rest of the time slice to the next ready thread.
+pub fn yield_now() {
+    interrupts::without_interrupts(schedule);
+}
+
+/// Blocks the current thread for at least `count` ticks.
+pub fn sleep_ticks(count: u64) {
+    if !is_running() {
+        return time::sleep_ticks(count);
+    }
+
+    schedule_with_state(ThreadState::Sleeping(time::ticks() + count));
+}
+
+pub fn sleep(duration: Duration) {
+    sleep_ticks(time::duration_to_ticks(duration, time::tick_frequency()));
+}
+
+/// Blocks the current thread until `unblock` is called for it. Interrupts have to be disabled,
+/// otherwise the thread could be woken up before it is switched out and then sleep forever.
+pub fn block() {
+    if let Some(scheduler) = SCHEDULER.lock().as_mut() {
+        scheduler.set_current_state(ThreadState::Blocked);
+    }
+
+    schedule();
+}
+
+/// Makes a thread blocked by `block` ready to run again, returns false if it wasn't blocked.
+pub fn unblock(id: ThreadId) -> bool {
+    interrupts::without_interrupts(|| {
+        SCHEDULER.lock().as_mut().map_or(false, |scheduler| scheduler.unblock(id))
+    })
+}
+
+/// Ends another thread, e.g. one of a process that exits. Neither that thread nor the caller may
+/// hold any locks.
+pub fn kill(id: ThreadId) {
+    interrupts::without_interrupts(|| {
+        if let Some(scheduler) = SCHEDULER.lock().as_mut() {
+            scheduler.kill(id);
+        }
+    });
+}
+
+/// Ends the current thread, its stack is freed once another thread runs.
+pub fn exit() -> ! {
+    schedule_with_state(ThreadState::Exited);
+    unreachable!("Exited thread was scheduled again");
+}
+
+/// Called from the timer interrupt.
+pub fn timer_tick() {
+    let preempt = SCHEDULER.lock().as_mut().map_or(false, |scheduler| scheduler.tick(time::ticks()));
+
+    if preempt {
+        NEED_RESCHEDULE.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Called at the end of every hardware interrupt, after it was acknowledged. Switches threads if
+/// the timer asked for it, the interrupted thread continues when it is scheduled again.
+pub fn preempt() {
+    if NEED_RESCHEDULE.swap(false, Ordering::Relaxed) {
+        schedule();
+    }
+}
+
+#[test_case]
+fn spawned_threads_run() {
+    use core::sync::atomic::AtomicUsize;
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    for _ in 0..3 {
+        spawn("test", || {
+            COUNT.fetch_add(1, Ordering::SeqCst);
+        });
+    }
+
+    while COUNT.load(Ordering::SeqCst) < 3 {
+        yield_now();
+    }
+}
+
+#[test_case]
+fn busy_threads_are_preempted() {
+    static STARTED: AtomicBool = AtomicBool::new(false);
+
+    spawn("test", || STARTED.store(true, Ordering::SeqCst));
+
+    //no yield here, only the timer can let the other thread run
+    while !STARTED.load(Ordering::SeqCst) {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn sleeping_threads_wake_up() {
+    let start = time::ticks();
+    sleep_ticks(5);
+    assert!(time::ticks() >= start + 5);
+}
@@ -0,0 +1,180 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use crate::kernel::task::thread::{Thread, ThreadId, ThreadState};
+
+/// Ticks a thread may run before it is preempted.
+pub const TIME_SLICE: u64 = 10;
+
+/// Round robin scheduler, threads run in the order they became ready.
+pub struct Scheduler {
+    //boxed so that the stack pointer locations handed to `context::switch` don't move
+    threads: BTreeMap<ThreadId, Box<Thread>>,
+    ready: VecDeque<ThreadId>,
+    sleeping: Vec<ThreadId>,
+    exited: Vec<ThreadId>,
+    current: ThreadId,
+    idle: ThreadId,
+    slice_remaining: u64,
+}
+
+impl Scheduler {
+    /// Takes over the running code as the boot thread, `idle` runs whenever nothing else can.
+    pub fn new(idle: Thread) -> Scheduler {
+        let boot = Thread::boot();
+        let (current, idle_id) = (boot.id, idle.id);
+
+        let mut threads = BTreeMap::new();
+        threads.insert(boot.id, Box::new(boot));
+        threads.insert(idle.id, Box::new(idle));
+
+        Scheduler {
+            threads,
+            ready: VecDeque::new(),
+            sleeping: Vec::new(),
+            exited: Vec::new(),
+            current,
+            idle: idle_id,
+            slice_remaining: TIME_SLICE,
+        }
+    }
+
+    pub fn current(&self) -> ThreadId {
+        self.current
+    }
+
+    pub fn current_thread(&self) -> &Thread {
+        &self.threads[&self.current]
+    }
+
+    /// Whether the thread exists and did not exit yet.
+    pub fn is_alive(&self, id: ThreadId) -> bool {
+        self.threads.get(&id).map_or(false, |thread| thread.state != ThreadState::Exited)
+    }
+
+    pub fn add(&mut self, thread: Thread) -> ThreadId {
+        let id = thread.id;
+
+        self.threads.insert(id, Box::new(thread));
+        self.ready.push_back(id);
+        id
+    }
+
+    pub fn thread_count(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Changes the state of the current thread, it takes effect with the next switch.
+    pub fn set_current_state(&mut self, state: ThreadState) {
+        self.threads.get_mut(&self.current).expect("Current thread vanished").state = state;
+    }
+
+    pub fn thread_mut(&mut self, id: ThreadId) -> Option<&mut Thread> {
+        self.threads.get_mut(&id).map(|thread| &mut **thread)
+    }
+
+    /// Ends a thread that is not running, its stack is freed with the next switch.
+    pub fn kill(&mut self, id: ThreadId) {
+        assert_ne!(id, self.current, "The current thread has to exit instead");
+
+        let thread = match self.threads.get_mut(&id) {
+            Some(thread) if thread.state != ThreadState::Exited => thread,
+            _ => return,
+        };
+
+        thread.state = ThreadState::Exited;
+        self.ready.retain(|ready| *ready != id);
+        self.sleeping.retain(|sleeping| *sleeping != id);
+        self.exited.push(id);
+    }
+
+    /// Makes a blocked thread ready again, the thread runs once its turn comes.
+    /// Returns false if the thread wasn't blocked, e.g. because it was killed.
+    pub fn unblock(&mut self, id: ThreadId) -> bool {
+        let thread = match self.threads.get_mut(&id) {
+            Some(thread) if thread.state == ThreadState::Blocked => thread,
+            _ => return false,
+        };
+
+        //blocked but not switched out yet, it simply keeps running
+        if id == self.current {
+            thread.state = ThreadState::Running;
+            return true;
+        }
+
+        thread.state = ThreadState::Ready;
+        self.ready.push_back(id);
+        true
+    }
+
+    /// Advances the time slice and wakes up sleepers, returns true if the current thread should be preempted.
+    pub fn tick(&mut self, now: u64) -> bool {
+        let threads = &mut self.threads;
+        let ready = &mut self.ready;
+
+        self.sleeping.retain(|id| {
+            let thread = threads.get_mut(id).unwrap();
+
+            match thread.state {
+                ThreadState::Sleeping(wake_up) if wake_up <= now => {
+                    thread.state = ThreadState::Ready;
+                    ready.push_back(*id);
+                    false
+                }
+                _ => true,
+            }
+        });
+
+        self.slice_remaining = self.slice_remaining.saturating_sub(1);
+        self.slice_remaining == 0 || (self.current == self.idle && !self.ready.is_empty())
+    }
+
+    /// Picks the thread to run next and marks it as running. Returns where to save the current
+    /// thread's stack pointer and the stack pointer to resume, or None to keep running the current thread.
+    pub fn switch_next(&mut self) -> Option<(*mut usize, usize)> {
+        //the stacks of exited threads are not in use anymore, unless it is the current one
+        let current = self.current;
+        let threads = &mut self.threads;
+        self.exited.retain(|id| {
+            if *id == current {
+                return true;
+            }
+
+            threads.remove(id);
+            false
+        });
+
+        self.slice_remaining = TIME_SLICE;
+
+        let state = self.threads[&self.current].state;
+        let next = match self.ready.pop_front() {
+            Some(next) => next,
+            None if state == ThreadState::Running => return None,
+            None => self.idle,
+        };
+
+        match state {
+            ThreadState::Running if self.current != self.idle => {
+                self.threads.get_mut(&self.current).unwrap().state = ThreadState::Ready;
+                self.ready.push_back(self.current);
+            }
+            ThreadState::Running => self.threads.get_mut(&self.current).unwrap().state = ThreadState::Ready,
+            ThreadState::Sleeping(_) => self.sleeping.push(self.current),
+            ThreadState::Exited => self.exited.push(self.current),
+            //only `unblock` puts it back into the ready queue
+            ThreadState::Blocked | ThreadState::Ready => {}
+        }
+
+        if next == self.current {
+            self.threads.get_mut(&next).unwrap().state = ThreadState::Running;
+            return None;
+        }
+
+        let old_stack_pointer = &mut self.threads.get_mut(&self.current).unwrap().stack_pointer as *mut usize;
+        let next_thread = self.threads.get_mut(&next).unwrap();
+        next_thread.state = ThreadState::Running;
+        self.current = next;
+
+        Some((old_stack_pointer, next_thread.stack_pointer))
+    }
+}
@@ -0,0 +1,83 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::kernel::arch::x86::context;
+use crate::kernel::memory::paging::AddressSpace;
+
+/// Size of the kernel stack every spawned thread gets.
+pub const STACK_SIZE: usize = 16 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ThreadId(pub u64);
+
+impl ThreadId {
+    fn next() -> ThreadId {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        ThreadId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Ready,
+    Running,
+    /// Waiting for the tick count to reach the value.
+    Sleeping(u64),
+    /// Waiting in a wait queue, see `sync::WaitQueue`.
+    Blocked,
+    Exited,
+}
+
+pub struct Thread {
+    pub id: ThreadId,
+    pub name: &'static str,
+    pub state: ThreadState,
+    /// Stack pointer while the thread is switched out, see `context::switch`.
+    pub stack_pointer: usize,
+    /// Activated whenever the thread is switched to, null for kernel threads. It is owned by the
+    /// thread's process, which frees it only once the thread can't run anymore.
+    pub address_space: *mut AddressSpace,
+    //the boot thread runs on the stack the bootloader set up
+    stack: Option<Box<[u8]>>,
+}
+
+unsafe impl Send for Thread {}
+
+impl Thread {
+    /// The thread that is already running when the scheduler starts.
+    pub fn boot() -> Thread {
+        Thread {
+            id: ThreadId(0),
+            name: "boot",
+            state: ThreadState::Running,
+            stack_pointer: 0,
+            address_space: ptr::null_mut(),
+            stack: None,
+        }
+    }
+
+    /// A thread that calls `entry(argument)` once it is switched to.
+    pub fn new(name: &'static str, entry: extern "C" fn(usize) -> !, argument: usize) -> Thread {
+        let mut stack = vec![0u8; STACK_SIZE].into_boxed_slice();
+        let stack_pointer = context::prepare_stack(&mut stack, entry, argument);
+
+        Thread {
+            id: ThreadId::next(),
+            name,
+            state: ThreadState::Ready,
+            stack_pointer,
+            address_space: ptr::null_mut(),
+            stack: Some(stack),
+        }
+    }
+
+    pub fn has_own_stack(&self) -> bool {
+        self.stack.is_some()
+    }
+
+    /// Top of the thread's own stack, where the CPU enters the kernel when the thread runs user code.
+    pub fn kernel_stack_top(&self) -> Option<u64> {
+        self.stack.as_ref().map(|stack| (stack.as_ptr() as u64 + stack.len() as u64) & !0xF)
+    }
+}
@@ -0,0 +1,78 @@
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::time::Duration;
+
+/// Rate the timer interrupt is programmed to at boot.
+pub const TICK_FREQUENCY: u32 = 1000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+//the frequency the hardware actually runs at, which can differ slightly from the requested one
+static FREQUENCY: AtomicU32 = AtomicU32::new(TICK_FREQUENCY);
+
+/// Advances the clock by one tick, called from the timer interrupt.
+pub fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn set_tick_frequency(frequency: u32) {
+    FREQUENCY.store(frequency, Ordering::Relaxed);
+}
+
+pub fn tick_frequency() -> u32 {
+    FREQUENCY.load(Ordering::Relaxed)
+}
+
+/// Number of timer interrupts since boot, never goes backwards.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+pub fn ticks_to_duration(ticks: u64, frequency: u32) -> Duration {
+    let nanoseconds = ticks as u128 * 1_000_000_000 / frequency as u128;
+    Duration::from_nanos(nanoseconds as u64)
+}
+
+pub fn duration_to_ticks(duration: Duration, frequency: u32) -> u64 {
+    //round up so that sleeping never returns early
+    let ticks = (duration.as_nanos() * frequency as u128 + 999_999_999) / 1_000_000_000;
+    ticks as u64
+}
+
+pub fn uptime() -> Duration {
+    ticks_to_duration(ticks(), tick_frequency())
+}
+
+/// Halts until `count` more ticks have passed, interrupts have to be enabled.
+pub fn sleep_ticks(count: u64) {
+    let target = ticks() + count;
+
+    while ticks() < target {
+        x86_64::instructions::hlt();
+    }
+}
+
+pub fn sleep(duration: Duration) {
+    sleep_ticks(duration_to_ticks(duration, tick_frequency()));
+}
+
+/// Like `sleep_ticks`, but spins instead of halting, e.g. to poll a device while waiting.
+pub fn busy_wait_ticks(count: u64) {
+    let target = ticks() + count;
+
+    while ticks() < target {
+        core::hint::spin_loop();
+    }
+}
+
+#[test_case]
+fn duration_conversion_rounds_up() {
+    assert_eq!(duration_to_ticks(Duration::from_millis(10), 1000), 10);
+    assert_eq!(duration_to_ticks(Duration::from_micros(1500), 1000), 2);
+    assert_eq!(ticks_to_duration(250, 100), Duration::from_millis(2500));
+}
+
+#[test_case]
+fn ticks_advance_while_sleeping() {
+    let start = ticks();
+    sleep_ticks(2);
+    assert!(ticks() >= start + 2);
+}
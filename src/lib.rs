@@ -0,0 +1,66 @@
+#![no_std]
+#![cfg_attr(test, no_main)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::kernel::lib::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+#![feature(asm)]
+#![feature(core_intrinsics)]
+#![feature(naked_functions)]
+#![feature(asm_sym)]
+#![feature(asm_const)]
+#![feature(alloc_error_handler)]
+#![feature(inline_const)]
+
+extern crate alloc;
+
+pub mod kernel;
+
+pub use kernel::lib::print;
+use kernel::arch::x86::{gdt, pit, serial, syscall};
+use kernel::arch::x86::interrupts::{idt, irq, pic};
+use kernel::drivers::keyboard::{self, scancode::ScancodeSet};
+
+//legacy ISA interrupt line of COM1
+const COM1_IRQ: u8 = 4;
+
+pub fn init() {
+    //the IDT entries pick up the code segment selector of the GDT, so it has to be loaded first
+    gdt::init();
+    idt::init();
+    syscall::init();
+    pic::init();
+    pit::init(kernel::time::TICK_FREQUENCY);
+
+    irq::register_irq(COM1_IRQ, |_| serial::handle_interrupt());
+    serial::SERIAL1.lock().enable_receive_interrupt();
+
+    if let Err(error) = keyboard::init(ScancodeSet::Set1) {
+        serial_println!("PS/2 keyboard not available: {:?}", error);
+    }
+
+    x86_64::instructions::interrupts::enable();
+}
+
+#[cfg(test)]
+use core::panic::PanicInfo;
+#[cfg(test)]
+use bootloader::{entry_point, BootInfo};
+
+#[cfg(test)]
+entry_point!(test_kernel_main);
+
+#[cfg(test)]
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    init();
+    kernel::memory::init(boot_info);
+    kernel::symbols::init(boot_info);
+    kernel::task::init();
+    test_main();
+    kernel::arch::x86::hlt_loop();
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    kernel::lib::testing::test_panic_handler(info)
+}
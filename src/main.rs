@@ -1,48 +1,79 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
+#![test_runner(thunder::kernel::lib::testing::test_runner)]
 #![reexport_test_harness_main = "test_main"]
-#![feature(asm)]
-#![feature(core_intrinsics)]
-#![feature(naked_functions)]
-#![feature(asm_sym)]
 #![feature(asm_const)]
 
-mod kernel;
-
 use core::arch::asm;
 use core::panic::PanicInfo;
-use x86_64::instructions::port::Port;
-use kernel::lib::print;
-use crate::idt::{Attributes, Entry, InterruptDescriptorTable};
-use kernel::arch::x86::interrupts::idt;
+use bootloader::{entry_point, BootInfo};
+use thunder::{print, println, serial_println};
+use thunder::kernel::arch::x86::interrupts::apic;
+use thunder::kernel::{memory, symbols, task};
+use thunder::kernel::arch::x86::serial;
+use thunder::kernel::task::executor::Executor;
+use thunder::kernel::drivers::keyboard::{self, KeyState};
 
 #[macro_use] // needed for the `int!` macro
 extern crate x86_64;
 
-#[no_mangle]
-pub extern "C" fn _start() -> ! {
-    idt::init();
+entry_point!(kernel_main);
+
+fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    thunder::init();
+    memory::init(boot_info);
+    symbols::init(boot_info);
+
+    //keep the PICs if there are no APICs to switch to
+    if let Err(error) = apic::init(memory::physical_memory_offset()) {
+        serial_println!("Using the 8259 PICs, APIC initialization failed: {:?}", error);
+    }
+
+    task::init();
+
+    let frames = memory::frame_stats();
+    serial_println!("Physical memory: {} of {} frames free", frames.free, frames.total);
+
     unsafe { software_interrupt!(3) };
-    //unsafe { *(0xdeadbeaf as *mut u64) = 42 };
-    //divide_by_zero();
     println!("It did not crash!");
-    loop {}
+    serial_println!("It did not crash!");
+
+    #[cfg(test)]
+    test_main();
+
+    let mut executor = Executor::new();
+    executor.spawn(echo_keys());
+    executor.spawn(echo_serial());
+    executor.run();
+}
+
+async fn echo_keys() {
+    loop {
+        let event = keyboard::next_event().await;
+
+        if let (KeyState::Pressed, Some(character)) = (event.state, event.character) {
+            print!("{}", character);
+        }
+    }
+}
+
+async fn echo_serial() {
+    loop {
+        let byte = serial::next_byte().await;
+        print!("{}", byte as char);
+    }
 }
 
 /// This function is called on panic.
 #[cfg(not(test))]
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    println!("{}", info);
-    loop {}
+    thunder::kernel::arch::x86::crash::panic(info)
 }
 
-fn divide_by_zero() {
-    unsafe {
-        asm! {
-            "mov dx, 0",
-            "div dx",
-        }
-    }
-}
\ No newline at end of file
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    thunder::kernel::lib::testing::test_panic_handler(info)
+}
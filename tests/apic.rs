@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use bootloader::{entry_point, BootInfo};
+use x86_64::VirtAddr;
+use x86_64::instructions::interrupts;
+use thunder::{serial_print, serial_println};
+use thunder::kernel::arch::x86::hlt_loop;
+use thunder::kernel::arch::x86::interrupts::apic::{self, LOCAL_TIMER_VECTOR, SPURIOUS_VECTOR};
+use thunder::kernel::arch::x86::interrupts::apic::local::{LocalApicRegister, LvtMasks, TimerDivide, TimerMode};
+use thunder::kernel::arch::x86::qemu::{exit_qemu, QemuExitCode};
+
+entry_point!(test_kernel_main);
+
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("apic::local_apic_is_enabled...\t");
+
+    thunder::init();
+    apic::init(VirtAddr::new(boot_info.physical_memory_offset)).expect("APIC initialization failed");
+    assert!(apic::is_enabled());
+
+    let local_apic = apic::local_apic().unwrap();
+    let spurious = unsafe { local_apic.read(LocalApicRegister::SPURIOUS_INTERRUPT_VECTOR) };
+    assert_ne!(spurious & LvtMasks::SOFTWARE_ENABLE, 0);
+    assert_eq!(spurious & 0xFF, SPURIOUS_VECTOR as u32);
+
+    //nothing handles the timer vector here, so it must not fire
+    interrupts::without_interrupts(|| {
+        apic::start_local_timer(TimerMode::OneShot, TimerDivide::By128, u32::MAX);
+        let timer = unsafe { local_apic.read(LocalApicRegister::LVT_TIMER) };
+        let counting = local_apic.timer_current_count();
+        apic::stop_local_timer();
+
+        assert_eq!(timer & 0xFF, LOCAL_TIMER_VECTOR as u32);
+        assert_eq!(timer & LvtMasks::MASKED, 0);
+        assert_ne!(counting, 0);
+    });
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    thunder::kernel::lib::testing::test_panic_handler(info)
+}
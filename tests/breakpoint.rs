@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use thunder::{serial_print, serial_println};
+use thunder::kernel::arch::x86::hlt_loop;
+use thunder::kernel::arch::x86::qemu::{exit_qemu, QemuExitCode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("breakpoint::breakpoint_returns...\t");
+
+    thunder::init();
+    //the breakpoint handler is a trap, so execution has to continue right after int3
+    x86_64::instructions::interrupts::int3();
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    thunder::kernel::lib::testing::test_panic_handler(info)
+}
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+use core::arch::asm;
+use core::panic::PanicInfo;
+use thunder::{serial_print, serial_println};
+use thunder::kernel::arch::x86::{crash, hlt_loop};
+use thunder::kernel::arch::x86::qemu::{exit_qemu, QemuExitCode};
+
+//the real handler reports the crash and halts, getting here means it ran
+fn crashed_then_exit() -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("divide_error::divide_error_is_caught...\t");
+
+    thunder::init();
+    crash::set_halt_handler(crashed_then_exit);
+    unsafe {
+        asm! {
+            "mov dx, 0",
+            "div dx",
+        }
+    }
+
+    panic!("Execution continued after divide error");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    thunder::kernel::lib::testing::test_panic_handler(info)
+}
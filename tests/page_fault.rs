@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+#![feature(naked_functions)]
+#![feature(asm_sym)]
+#![feature(asm_const)]
+
+use core::panic::PanicInfo;
+use lazy_static::lazy_static;
+use x86_64::registers::control::Cr2;
+use thunder::{exception_entry, serial_print, serial_println};
+use thunder::kernel::arch::x86::hlt_loop;
+use thunder::kernel::arch::x86::interrupts::exception::{self, ExceptionContext, ExceptionVector};
+use thunder::kernel::arch::x86::interrupts::idt::InterruptDescriptorTable;
+use thunder::kernel::arch::x86::interrupts::page_fault::PageFaultBuilder;
+use thunder::kernel::arch::x86::qemu::{exit_qemu, QemuExitCode};
+use thunder::kernel::memory::paging::{self, FaultError};
+
+const FAULTING_ADDRESS: u64 = 0xdeadbeaf;
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.register_exception(exception_entry!(ExceptionVector::PAGE_FAULT, page_fault_then_exit));
+        idt
+    };
+}
+
+//the real handler halts on faults it can't resolve, so this reports the fault itself
+fn page_fault_then_exit(context: &mut ExceptionContext) -> ! {
+    let page_fault = PageFaultBuilder::build(context.error_code.expect("Page fault without error code"));
+    let error = paging::handle_page_fault(&page_fault).expect_err("Unmapped address was resolved");
+
+    assert_eq!(error, FaultError::NotMapped);
+    exception::report_page_fault(context.registers, &page_fault, error);
+
+    assert_eq!(Cr2::read().as_u64(), FAULTING_ADDRESS);
+    assert_eq!(context.fault_address.map(|address| address.as_u64()), Some(FAULTING_ADDRESS));
+
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("page_fault::page_fault_is_caught...\t");
+
+    TEST_IDT.load();
+    unsafe { *(FAULTING_ADDRESS as *mut u64) = 42 };
+
+    panic!("Execution continued after page fault");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    thunder::kernel::lib::testing::test_panic_handler(info)
+}
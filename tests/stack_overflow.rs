@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use thunder::{serial_print, serial_println};
+use thunder::kernel::arch::x86::{crash, hlt_loop};
+use thunder::kernel::arch::x86::qemu::{exit_qemu, QemuExitCode};
+
+//the page fault on the guard page can't be delivered on the overflowed stack,
+//so only the double fault handler on its own IST stack can have reported the crash
+fn crashed_then_exit() -> ! {
+    serial_println!("[ok]");
+    exit_qemu(QemuExitCode::Success);
+    hlt_loop();
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    //prevent tail call optimization
+    volatile::Volatile::new(0).read();
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("stack_overflow::double_fault_on_stack_overflow...\t");
+
+    thunder::init();
+    crash::set_halt_handler(crashed_then_exit);
+
+    stack_overflow();
+
+    panic!("Execution continued after stack overflow");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    thunder::kernel::lib::testing::test_panic_handler(info)
+}